@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use fluent_data::{model::Ball, space, Algo, Model};
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+/// The number of points fitted by each iteration of the fit benchmark.
+const POINTS: usize = 1000;
+
+/// Gets gaussian points with the same distribution and seed as the integration tests point generator,
+/// see `tests/utilities.rs`.
+fn get_points(count: usize, dim: usize) -> Vec<Vec<f64>> {
+    let normal = Normal::new(2.0, 3.0).unwrap();
+    let mut rng = StdRng::seed_from_u64(9787043385113690);
+    (0..count)
+        .map(|_| (0..dim).map(|_| normal.sample(&mut rng)).collect())
+        .collect()
+}
+
+/// Measures the throughput of fitting a stream of points to a new model in 1D, 8D and 64D.
+fn bench_fit(c: &mut Criterion) {
+    let algo = Algo::new(space::euclid_dist, space::real_combine);
+    let mut group = c.benchmark_group("fit");
+    group.throughput(Throughput::Elements(POINTS as u64));
+    for dim in [1, 8, 64] {
+        let points = get_points(POINTS, dim);
+        group.bench_with_input(BenchmarkId::from_parameter(dim), &points, |b, points| {
+            b.iter_batched(
+                || (Model::new(space::euclid_dist), points.clone()),
+                |(mut model, points)| {
+                    for point in points {
+                        algo.fit(&mut model, point);
+                    }
+                    model
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Measures the latency of predicting the balls of a point as the number of balls grows.
+fn bench_predict(c: &mut Criterion) {
+    let mut group = c.benchmark_group("predict");
+    for balls in [10, 100, 1000, 4000] {
+        let centers = get_points(balls, 8);
+        let data = centers
+            .into_iter()
+            .map(|center| Ball::new(center, 1., 1.))
+            .collect();
+        let model = Model::load(space::euclid_dist, data);
+        let points = get_points(POINTS, 8);
+        let mut points = points.iter().cycle();
+        group.bench_with_input(BenchmarkId::from_parameter(balls), &model, |b, model| {
+            b.iter(|| {
+                let point = points.next().unwrap();
+                model.predict(point)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fit, bench_predict);
+criterion_main!(benches);
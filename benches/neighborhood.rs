@@ -0,0 +1,48 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use fluent_data::{model::Ball, space, Algo, Model};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Builds a model with the given number of small balls spread uniformly in a square.
+fn build_model(balls: usize, rng: &mut StdRng) -> Model<Vec<f64>> {
+    let data = (0..balls)
+        .map(|_| {
+            let center = vec![rng.gen_range(0. ..100.), rng.gen_range(0. ..100.)];
+            Ball::new(center, 0.5, 1.)
+        })
+        .collect();
+    Model::load(space::euclid_dist, data)
+}
+
+/// Draws a batch of points in the square covered by the balls.
+fn sample(rng: &mut StdRng) -> Vec<Vec<f64>> {
+    (0..100)
+        .map(|_| vec![rng.gen_range(0. ..100.), rng.gen_range(0. ..100.)])
+        .collect()
+}
+
+/// Compares fitting points with and without the grid index as the number of balls grows.
+/// The topology is frozen so that the number of balls does not change during the benchmark,
+/// and the points are fitted by batches so that the cost of the decay does not hide the cost of the neighborhood.
+fn bench_fit(c: &mut Criterion) {
+    let algo = Algo::new(space::euclid_dist, space::real_combine)
+        .with_freeze_flag(Arc::new(AtomicBool::new(true)));
+    let mut group = c.benchmark_group("fit_batch");
+    for balls in [100, 1000, 4000] {
+        let mut rng = StdRng::seed_from_u64(7541);
+        let mut linear = build_model(balls, &mut rng);
+        group.bench_with_input(BenchmarkId::new("linear", balls), &balls, |b, _| {
+            b.iter(|| algo.fit_batch(&mut linear, sample(&mut rng)))
+        });
+        let mut rng = StdRng::seed_from_u64(7541);
+        let mut indexed = build_model(balls, &mut rng).with_grid_index(5.);
+        group.bench_with_input(BenchmarkId::new("grid", balls), &balls, |b, _| {
+            b.iter(|| algo.fit_batch(&mut indexed, sample(&mut rng)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fit);
+criterion_main!(benches);
@@ -1,6 +1,17 @@
 //! The [Algo] struct implements the algorithm that fits a set of balls model from data point streams.
 
-use std::{marker::PhantomData, ops::DerefMut};
+use std::{
+    collections::HashMap,
+    error::Error,
+    marker::PhantomData,
+    ops::DerefMut,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
 
 use crate::model::{Ball, BallNode, GetNeighbors, Model};
 
@@ -9,7 +20,281 @@ const INTRA_THRESHOLD: f64 = 16.;
 const MERGE_THRESHOLD: f64 = 1.;
 const DECAY_FACTOR: f64 = 0.95;
 const DECAY_THRESHOLD: f64 = 1E-2;
+const SPLIT_NEIGHBOR_WEIGHT: f64 = -1.;
+const SPLIT_POINT_WEIGHT: f64 = 5.;
 const MAX_NEIGHBORS: usize = 2;
+/// The number of balls below which the decay is not split between threads.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_BALLS: usize = 1024;
+
+/// A function that validates an incoming point against a ball center.
+type ValidateFn<Point> = Box<dyn Fn(&Point, &Point) -> Result<(), Box<dyn Error>>>;
+
+type CheckFn<Point> = Box<dyn Fn(&Point) -> Result<(), Box<dyn Error>>>;
+
+/// A function that decays the weight of all balls but the given one, see [Algo::with_parallel_decay].
+type DecayFn<Point> = fn(&[BallNode<Point>], &BallNode<Point>, f64);
+
+/// The ball which received a point, if any, and the changes made to the model by the fit.
+pub(crate) type Fitted<Point> = (Option<BallNode<Point>>, BatchStats);
+
+/// The changes made to a model by [Algo::fit_batch].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    /// The number of points fitted.
+    pub points: usize,
+    /// The number of balls created.
+    pub created: usize,
+    /// The number of balls merged into a neighbor,
+    /// including the balls merged to keep the model under the cap, see [CapPolicy::MergeClosest].
+    pub merged: usize,
+    /// The number of balls removed, because they decayed or were merged during a batch.
+    pub removed: usize,
+    /// The number of balls evicted to keep the model under the cap, see [Algo::with_max_balls].
+    pub evicted: usize,
+    /// The number of points rejected by the validation, see [Algo::with_point_validation].
+    pub rejected: usize,
+}
+
+/// The outcome of fitting a point, see [Algo::fit_with_result].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FitResult<Point: PartialEq> {
+    /// The ball which received the point, after the fit,
+    /// or `None` if the point was rejected by the validation or if the frozen model is empty.
+    /// If the ball created for the point was merged into a neighbor, it is that neighbor.
+    pub ball: Option<Ball<Point>>,
+    /// The changes made to the model, including the balls evicted or merged to keep the model under the cap.
+    pub stats: BatchStats,
+}
+
+impl<Point: PartialEq + Clone + 'static> FitResult<Point> {
+    /// Builds the outcome of a fit from the ball which received the point.
+    pub(crate) fn new(vertex: Option<&BallNode<Point>>, stats: BatchStats) -> Self {
+        let ball = vertex.map(|vertex| vertex.deref_data().clone());
+        Self { ball, stats }
+    }
+}
+
+/// The counters of the work done by an [Algo] since it started,
+/// shared with other threads, e.g. the [service](crate::service) metrics endpoint.
+/// Clones share the same counters.
+/// ```
+/// use fluent_data::{algorithm::AlgoStats, space, Algo, Model};
+///
+/// let stats = AlgoStats::new();
+/// let algo = Algo::new(space::euclid_dist, space::real_combine).with_stats(stats.clone());
+/// let mut model = Model::new(space::euclid_dist);
+/// algo.fit(&mut model, vec![1.]);
+/// algo.fit(&mut model, vec![2.]);
+/// assert_eq!(2, stats.points());
+/// assert_eq!(1, stats.balls());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AlgoStats {
+    points: Arc<AtomicU64>,
+    balls: Arc<AtomicU64>,
+    merges: Arc<AtomicU64>,
+    splits: Arc<AtomicU64>,
+}
+
+impl AlgoStats {
+    /// Builds new counters set to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of points fitted.
+    pub fn points(&self) -> u64 {
+        self.points.load(Ordering::Relaxed)
+    }
+
+    /// The number of balls of the model after the last fit.
+    pub fn balls(&self) -> u64 {
+        self.balls.load(Ordering::Relaxed)
+    }
+
+    /// The number of balls merged into a neighbor.
+    pub fn merges(&self) -> u64 {
+        self.merges.load(Ordering::Relaxed)
+    }
+
+    /// The number of balls created, i.e. the first ball and the balls split from their closest ball.
+    pub fn splits(&self) -> u64 {
+        self.splits.load(Ordering::Relaxed)
+    }
+
+    /// Adds the changes made to a model of the given number of balls.
+    fn record(&self, stats: &BatchStats, balls: usize) {
+        self.points
+            .fetch_add(stats.points as u64, Ordering::Relaxed);
+        self.merges
+            .fetch_add(stats.merged as u64, Ordering::Relaxed);
+        self.splits
+            .fetch_add(stats.created as u64, Ordering::Relaxed);
+        self.balls.store(balls as u64, Ordering::Relaxed);
+    }
+}
+
+/// The thresholds of the algorithm, see [Algo::with_params].
+/// Like the distance function and the ball radii, thresholds apply to squared distances.
+/// Missing fields take their default value when deserialized, e.g. from a configuration file.
+/// ```
+/// use fluent_data::algorithm::AlgoParams;
+///
+/// let params: AlgoParams = serde_json::from_str(r#"{"decay_factor":0.9}"#).unwrap();
+/// assert_eq!(0.9, params.decay_factor);
+/// assert_eq!(AlgoParams::default().intra_threshold, params.intra_threshold);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlgoParams {
+    /// A point updates its closest ball if its distance to the center
+    /// is less than `intra_threshold` times the radius, 16 by default.
+    pub intra_threshold: f64,
+    /// Otherwise the point seeds a new ball which radius is the distance
+    /// divided by `extra_threshold`, 25 by default.
+    pub extra_threshold: f64,
+    /// Neighbor balls merge when the squared distance between their centers
+    /// is less than `merge_threshold` times the sum of their squared radii, 1 by default.
+    /// Both sides scale alike, thus merges do not depend on the scale of the data.
+    pub merge_threshold: f64,
+    /// The weight of the balls which do not receive the point is multiplied by `decay_factor`, 0.95 by default.
+    pub decay_factor: f64,
+    /// Balls which weight falls below `decay_threshold` are removed, 0.01 by default.
+    pub decay_threshold: f64,
+    /// The center of a new ball combines the center of the closest ball with weight `split_neighbor_weight`,
+    /// -1 by default, and the point with weight `split_point_weight`, 5 by default.
+    /// These legacy weights extrapolate the center beyond the point, away from the closest ball,
+    /// which offsets the ball of a far outlier from the point, see [AlgoParams::centered_split].
+    pub split_neighbor_weight: f64,
+    /// See `split_neighbor_weight`.
+    pub split_point_weight: f64,
+    /// How the radius of a ball is updated when a point is merged, see [RadiusUpdate].
+    pub radius_update: RadiusUpdate,
+    /// The radius of the ball created for the first point, see [InitialRadius].
+    pub initial_radius: InitialRadius,
+}
+
+impl AlgoParams {
+    /// Centers new balls on the point that seeds them instead of extrapolating their center.
+    /// ```
+    /// use fluent_data::{algorithm::AlgoParams, Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine)
+    ///     .with_params(AlgoParams::default().centered_split());
+    /// let mut model = Model::new(space::euclid_dist);
+    /// algo.fit(&mut model, vec![0.]);
+    /// algo.fit(&mut model, vec![1.]);
+    /// algo.fit(&mut model, vec![101.]);
+    /// assert!(model.iter_balls().any(|b| b.center() == &vec![101.]));
+    /// ```
+    pub fn centered_split(self) -> Self {
+        Self {
+            split_neighbor_weight: 0.,
+            split_point_weight: 1.,
+            ..self
+        }
+    }
+}
+
+impl Default for AlgoParams {
+    fn default() -> Self {
+        Self {
+            intra_threshold: INTRA_THRESHOLD,
+            extra_threshold: EXTRA_THRESHOLD,
+            merge_threshold: MERGE_THRESHOLD,
+            decay_factor: DECAY_FACTOR,
+            decay_threshold: DECAY_THRESHOLD,
+            split_neighbor_weight: SPLIT_NEIGHBOR_WEIGHT,
+            split_point_weight: SPLIT_POINT_WEIGHT,
+            radius_update: RadiusUpdate::default(),
+            initial_radius: InitialRadius::default(),
+        }
+    }
+}
+
+/// How the radius of a ball is updated when a point is merged, see [AlgoParams::radius_update].
+/// ```
+/// use fluent_data::algorithm::{AlgoParams, RadiusUpdate};
+///
+/// let params: AlgoParams = serde_json::from_str(r#"{"radius_update":"welford"}"#).unwrap();
+/// assert_eq!(RadiusUpdate::Welford, params.radius_update);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RadiusUpdate {
+    /// The running average of the distances between the points and the center as it was when they were received.
+    /// As the center moves, the radius drifts from the spread of the points around the current center.
+    #[default]
+    Average,
+    /// The weighted Welford update, which accounts for the shift of the center:
+    /// the radius is the weighted average of the distances between the points and the current center.
+    Welford,
+}
+
+/// The radius of the ball created for the first point, see [AlgoParams::initial_radius].
+/// ```
+/// use fluent_data::{algorithm::{AlgoParams, InitialRadius}, Algo, Model, space};
+///
+/// let params: AlgoParams = serde_json::from_str(r#"{"initial_radius":{"fixed":4.0}}"#).unwrap();
+/// assert_eq!(InitialRadius::Fixed(4.), params.initial_radius);
+/// let algo = Algo::new(space::euclid_dist, space::real_combine).with_params(params);
+/// let mut model = Model::new(space::euclid_dist);
+/// algo.fit(&mut model, vec![1.]);
+/// let ball = model.iter_balls().next().unwrap();
+/// assert_eq!(2., ball.radius());
+/// assert_eq!(1., ball.weight());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialRadius {
+    /// The first ball has an infinite radius and no weight:
+    /// the second point moves its center and sets its radius to the distance between the two points.
+    /// The radius of the first ball is omitted from the serialized model until then.
+    #[default]
+    Infinite,
+    /// The first ball has the given squared radius and the weight of the first point,
+    /// e.g. for sparse data which balls must be meaningful from the first point.
+    Fixed(f64),
+}
+
+/// How [Algo::with_max_balls] keeps the model under its cap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CapPolicy {
+    /// Evicts the ball with the lowest weight.
+    #[default]
+    EvictLightest,
+    /// Merges the two closest neighbor balls, which keeps the total weight of the model.
+    MergeClosest,
+}
+
+/// The decay deferred by [Algo::fit_batch]:
+/// the weight of a ball is brought up to date only when the ball is used.
+struct Deferred {
+    /// The index of the current point in the batch.
+    step: usize,
+    /// The step from which the weight of each ball must still be decayed.
+    /// Balls which are not found are up to date at the start of the batch.
+    since: HashMap<u64, usize>,
+    /// The decay factor of each step.
+    decay_factor: f64,
+}
+
+impl Deferred {
+    /// Applies the decay of the previous steps to the ball weight.
+    /// Returns true if the weight was actually decayed.
+    fn catch_up<Point: PartialEq>(&mut self, vertex: &BallNode<Point>) -> bool {
+        let mut ball = vertex.deref_data_mut();
+        let since = self.since.insert(ball.id, self.step).unwrap_or(0);
+        ball.weight *= self.decay_factor.powi((self.step - since) as i32);
+        since < self.step
+    }
+
+    /// Records that the ball is not decayed by the current step.
+    fn touch<Point: PartialEq>(&mut self, vertex: &BallNode<Point>) {
+        self.since.insert(vertex.deref_data().id, self.step + 1);
+    }
+}
 
 /// Fits incoming points to a set of balls model.
 ///
@@ -40,6 +325,14 @@ const MAX_NEIGHBORS: usize = 2;
 pub struct Algo<Point: PartialEq + 'static> {
     dist: Box<dyn Fn(&Point, &Point) -> f64>,
     combine: Box<dyn Fn(&Point, f64, &Point, f64) -> Point>,
+    validate: Option<ValidateFn<Point>>,
+    check: Option<CheckFn<Point>>,
+    frozen: Option<Arc<AtomicBool>>,
+    max_balls: Option<usize>,
+    cap_policy: CapPolicy,
+    stats: Option<AlgoStats>,
+    params: AlgoParams,
+    decay_weights: DecayFn<Point>,
     phantom: PhantomData<Point>,
 }
 
@@ -53,33 +346,433 @@ impl<Point: PartialEq + 'static> Algo<Point> {
         Self {
             dist: Box::new(dist),
             combine: Box::new(combine),
+            validate: None,
+            check: None,
+            frozen: None,
+            max_balls: None,
+            cap_policy: CapPolicy::default(),
+            stats: None,
+            params: AlgoParams::default(),
+            decay_weights,
             phantom: PhantomData,
         }
     }
 
+    /// Sets a function that validates incoming points against the center of the first ball of the model,
+    /// e.g. [space::check_dim](crate::space::check_dim) that checks the points dimension.
+    /// Validation is performed by all the fit methods: [Algo::try_fit] returns the error,
+    /// the others ignore the rejected point.
+    pub fn with_validation<Validate, E>(mut self, validate: Validate) -> Self
+    where
+        Validate: Fn(&Point, &Point) -> Result<(), E> + 'static,
+        E: Error + 'static,
+    {
+        self.validate = Some(Box::new(move |p1, p2| Ok(validate(p1, p2)?)));
+        self
+    }
+
+    /// Sets a function that validates each incoming point on its own, even when the model is empty,
+    /// e.g. [space::check_finite](crate::space::check_finite) that rejects NaN and infinite coordinates.
+    /// Validation is performed by all the fit methods, before the validation set by [Algo::with_validation].
+    pub fn with_point_validation<Check, E>(mut self, check: Check) -> Self
+    where
+        Check: Fn(&Point) -> Result<(), E> + 'static,
+        E: Error + 'static,
+    {
+        self.check = Some(Box::new(move |p| Ok(check(p)?)));
+        self
+    }
+
+    /// Freezes the topology of the model while the given flag is set:
+    /// balls are neither created, merged nor removed but incoming points still update
+    /// the center, radius and weight of their closest ball.
+    /// The flag can be toggled at runtime, e.g. by the [service](crate::service) control endpoint.
+    /// ```
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    ///
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let frozen = Arc::new(AtomicBool::new(false));
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine).with_freeze_flag(frozen.clone());
+    /// let mut model = Model::new(space::euclid_dist);
+    /// algo.fit(&mut model, vec![1.]);
+    /// algo.fit(&mut model, vec![2.]);
+    /// frozen.store(true, Ordering::Relaxed);
+    /// algo.fit(&mut model, vec![1000.]);
+    /// assert_eq!(1, model.iter_balls().count());
+    /// ```
+    pub fn with_freeze_flag(mut self, frozen: Arc<AtomicBool>) -> Self {
+        self.frozen = Some(frozen);
+        self
+    }
+
+    /// Caps the number of balls of the model.
+    /// When a new ball would exceed the cap, the ball with the lowest weight is evicted,
+    /// except the ball that has just been updated.
+    /// Evictions are recorded as deletions when the model changes are tracked, like decayed balls.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine).with_max_balls(2);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// for i in 0..10 {
+    ///     algo.fit(&mut model, vec![(10 * i * i) as f64]);
+    /// }
+    /// assert!(model.iter_balls().count() <= 2);
+    /// ```
+    pub fn with_max_balls(mut self, max_balls: usize) -> Self {
+        self.max_balls = Some(max_balls);
+        self
+    }
+
+    /// Sets how the model is kept under the cap set by [Algo::with_max_balls],
+    /// by evicting the lightest ball by default.
+    /// ```
+    /// use fluent_data::{algorithm::CapPolicy, Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine)
+    ///     .with_max_balls(2)
+    ///     .with_cap_policy(CapPolicy::MergeClosest);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// for i in 0..10 {
+    ///     algo.fit(&mut model, vec![(10 * i * i) as f64]);
+    /// }
+    /// assert!(model.iter_balls().count() <= 2);
+    /// ```
+    pub fn with_cap_policy(mut self, cap_policy: CapPolicy) -> Self {
+        self.cap_policy = cap_policy;
+        self
+    }
+
+    /// Counts the points fitted and the changes made to the model in the given counters,
+    /// e.g. to be exposed by [service::backend_with_metrics](crate::service::backend_with_metrics).
+    /// See [AlgoStats].
+    pub fn with_stats(mut self, stats: AlgoStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Sets the thresholds of the algorithm instead of the default ones.
+    /// ```
+    /// use fluent_data::{algorithm::AlgoParams, Algo, Model, space};
+    ///
+    /// let params = AlgoParams {
+    ///     decay_factor: 0.9,
+    ///     ..AlgoParams::default()
+    /// };
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine).with_params(params);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// algo.fit(&mut model, vec![1.]);
+    /// algo.fit(&mut model, vec![2.]);
+    /// algo.fit(&mut model, vec![1000.]);
+    /// assert_eq!(0.9, model.iter_balls().next().unwrap().weight());
+    /// ```
+    pub fn with_params(mut self, params: AlgoParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Decays the weights of the balls of large models in parallel, instead of one ball after the other.
+    /// The fitted model is the same, only the points must be `Send + Sync`.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let sequential = Algo::new(space::euclid_dist, space::real_combine);
+    /// let parallel = Algo::new(space::euclid_dist, space::real_combine).with_parallel_decay();
+    /// let mut expected = Model::new(space::euclid_dist);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// for i in 0..10 {
+    ///     sequential.fit(&mut expected, vec![(10 * i * i) as f64]);
+    ///     parallel.fit(&mut model, vec![(10 * i * i) as f64]);
+    /// }
+    /// let weights = |model: &Model<Vec<f64>>| -> Vec<f64> {
+    ///     model.iter_balls().map(|b| b.weight()).collect()
+    /// };
+    /// assert_eq!(weights(&expected), weights(&model));
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn with_parallel_decay(mut self) -> Self
+    where
+        Point: Send + Sync,
+    {
+        self.decay_weights = par_decay_weights;
+        self
+    }
+
+    /// Gets the thresholds of the algorithm.
+    pub fn params(&self) -> &AlgoParams {
+        &self.params
+    }
+
+    /// Validates the incoming point then fits it to the given mixture model.
+    /// The model is left unchanged if the point is rejected.
+    pub fn try_fit(&self, model: &mut Model<Point>, point: Point) -> Result<(), Box<dyn Error>> {
+        self.try_fit_with_stats(model, point)?;
+        Ok(())
+    }
+
+    /// Validates the incoming point then fits it to the given mixture model like [Algo::try_fit].
+    /// Returns the ball which received the point and the changes made to the model.
+    pub(crate) fn try_fit_with_stats(
+        &self,
+        model: &mut Model<Point>,
+        point: Point,
+    ) -> Result<Fitted<Point>, Box<dyn Error>> {
+        self.validate_point(model, &point)?;
+        Ok(self.fit_with_stats(model, point))
+    }
+
+    /// Checks the incoming point with the validation functions, if any.
+    fn validate_point(&self, model: &Model<Point>, point: &Point) -> Result<(), Box<dyn Error>> {
+        if let Some(check) = &self.check {
+            check(point)?;
+        }
+        if let Some(validate) = &self.validate {
+            if let Some(first) = model.iter_balls().next() {
+                validate(point, &first.center)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Fits the incoming points to the given mixture model.
+    /// A point rejected by the validation is ignored, see [Algo::try_fit] to get the error.
     pub fn fit<'a>(&'a self, model: &'a mut Model<Point>, point: Point) {
-        let neighborhood = model.get_neighborhood(&point);
+        self.try_fit_with_stats(model, point).ok();
+    }
+
+    /// Fits the incoming point to the given mixture model like [Algo::fit]
+    /// and tells which ball received the point and what changed in the model.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let result = algo.fit_with_result(&mut model, vec![1.]);
+    /// assert_eq!(&vec![1.], result.ball.unwrap().center());
+    /// assert_eq!(1, result.stats.created);
+    /// let result = algo.fit_with_result(&mut model, vec![2.]);
+    /// assert_eq!(0, result.stats.created);
+    /// ```
+    pub fn fit_with_result(&self, model: &mut Model<Point>, point: Point) -> FitResult<Point>
+    where
+        Point: Clone,
+    {
+        match self.try_fit_with_stats(model, point) {
+            Ok((vertex, stats)) => FitResult::new(vertex.as_ref(), stats),
+            Err(_) => FitResult::new(
+                None,
+                BatchStats {
+                    rejected: 1,
+                    ..BatchStats::default()
+                },
+            ),
+        }
+    }
+
+    /// Fits the incoming point to the model.
+    /// Returns the ball which received the point and the changes made to the model.
+    fn fit_with_stats(&self, model: &mut Model<Point>, point: Point) -> Fitted<Point> {
+        let mut stats = BatchStats::default();
+        let vertex = self.fit_point(model, point, &mut stats, None);
+        self.record(model, &stats);
+        (vertex, stats)
+    }
+
+    /// Fits all the given points to the model and counts the changes made to the model.
+    ///
+    /// The decay is deferred during the batch: the weight of a ball is brought up to date
+    /// only when a point falls in its neighborhood, and balls which weight is too low are removed
+    /// at that time or at the end of the batch. This saves a sweep over all balls for each point.
+    /// The model is the same as when fitting points one by one,
+    /// except that a ball which weight is too low may be evicted by [Algo::with_max_balls]
+    /// or remain in the neighborhood graph of its neighbors until the end of the batch.
+    /// The points rejected by the validation are skipped and counted.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let stats = algo.fit_batch(&mut model, vec![vec![1.], vec![2.], vec![1000.]]);
+    /// assert_eq!(3, stats.points);
+    /// assert_eq!(2, stats.created);
+    /// assert_eq!(0, stats.removed);
+    /// ```
+    pub fn fit_batch(
+        &self,
+        model: &mut Model<Point>,
+        points: impl IntoIterator<Item = Point>,
+    ) -> BatchStats {
+        let mut stats = BatchStats::default();
+        let mut deferred = Deferred {
+            step: 0,
+            since: HashMap::new(),
+            decay_factor: self.params.decay_factor,
+        };
+        for point in points {
+            if self.validate_point(model, &point).is_err() {
+                stats.rejected += 1;
+                continue;
+            }
+            self.fit_point(model, point, &mut stats, Some(&mut deferred));
+            deferred.step += 1;
+        }
+        let frozen = self.is_frozen();
+        let count = model.graph.len();
+        model.retain_balls(|v| {
+            let decayed = deferred.catch_up(v);
+            frozen || !decayed || v.deref_data().weight > self.params.decay_threshold
+        });
+        for vertex in model.graph.clone() {
+            model.touch(&vertex);
+        }
+        stats.removed += count - model.graph.len();
+        self.record(model, &stats);
+        stats
+    }
+
+    /// Merges another model, e.g. trained on another shard of the data, into the given model,
+    /// then merges each ball with its closest neighbor if they are close enough, like fitting does,
+    /// see [Model::merge_from]. The weights of merged balls add up. Returns the number of merged balls.
+    /// ```
+    /// use fluent_data::{Algo, Model, model::Ball, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::load(space::euclid_dist, vec![Ball::new(vec![0.], 1., 2.)]);
+    /// let other = Model::load(
+    ///     space::euclid_dist,
+    ///     vec![Ball::new(vec![0.5], 1., 3.), Ball::new(vec![9.], 1., 1.)],
+    /// );
+    /// assert_eq!(1, algo.merge(&mut model, other));
+    /// assert_eq!(2, model.iter_balls().count());
+    /// assert_eq!(6., model.total_weight());
+    /// ```
+    pub fn merge(&self, model: &mut Model<Point>, other: Model<Point>) -> usize {
+        model.merge_from(other, &*self.combine, self.params.merge_threshold)
+    }
+
+    /// Adds the changes made to the model to the shared counters, if any.
+    fn record(&self, model: &Model<Point>, stats: &BatchStats) {
+        if let Some(counters) = &self.stats {
+            counters.record(stats, model.graph.len());
+        }
+    }
+
+    /// Fits the incoming point to the model and counts the changes made to the model.
+    /// The decay is deferred if a [Deferred] state is given.
+    /// Returns the ball which received the point, if any:
+    /// the candidate ball if the ball created for the point was merged into it.
+    fn fit_point(
+        &self,
+        model: &mut Model<Point>,
+        point: Point,
+        stats: &mut BatchStats,
+        mut deferred: Option<&mut Deferred>,
+    ) -> Option<BallNode<Point>> {
+        stats.points += 1;
+        let frozen = self.is_frozen();
+        let neighborhood = match deferred.as_deref_mut() {
+            Some(deferred) => self.catch_up_neighborhood(model, &point, deferred, frozen, stats),
+            None => model.get_neighborhood(&point),
+        };
         match neighborhood.first() {
             None => {
-                self.init(model, point);
+                if frozen {
+                    return None;
+                }
+                let vertex = self.init(model, point);
+                stats.created += 1;
+                if let Some(deferred) = deferred {
+                    deferred.touch(&vertex);
+                }
+                Some(vertex)
+            }
+            Some(candidate) if frozen => {
+                let vertex = self.refine(model, candidate, point);
+                stats.removed += self.decay(model, vertex.clone(), true, deferred);
+                Some(vertex)
             }
             Some(candidate) => {
                 let (vertex, maybe_neighbor) = self.update(model, candidate, point, &neighborhood);
+                if vertex.deref_data().id != candidate.deref_data().id {
+                    stats.created += 1;
+                }
+                let mut received = vertex.clone();
                 if let Some(maybe_neighbor) = maybe_neighbor {
-                    self.update_local_graph(candidate, maybe_neighbor);
+                    if let Some(merged) = self.update_local_graph(model, candidate, maybe_neighbor)
+                    {
+                        stats.merged += 1;
+                        if merged.deref_data().id == received.deref_data().id {
+                            received = candidate.clone();
+                        }
+                        if deferred.is_some() {
+                            let id = merged.deref_data().id;
+                            model.retain_balls(|v| v.deref_data().id != id);
+                            stats.removed += 1;
+                        }
+                    }
                 };
-                self.decay(model, vertex);
+                if let Some(deferred) = deferred.as_deref_mut() {
+                    if matches!(self.max_balls, Some(max_balls) if model.graph.len() > max_balls) {
+                        model.graph.iter().for_each(|v| {
+                            deferred.catch_up(v);
+                        });
+                    }
+                }
+                self.evict(model, &received, stats);
+                stats.removed += self.decay(model, vertex, false, deferred);
+                Some(received)
             }
         }
     }
 
+    /// Brings the weight of the balls in the neighborhood of the point up to date
+    /// and removes those which weight is too low, as the decay would have done.
+    /// Returns the neighborhood of the point.
+    fn catch_up_neighborhood(
+        &self,
+        model: &mut Model<Point>,
+        point: &Point,
+        deferred: &mut Deferred,
+        frozen: bool,
+        stats: &mut BatchStats,
+    ) -> Vec<BallNode<Point>> {
+        loop {
+            let neighborhood = model.get_neighborhood(point);
+            let mut involved = neighborhood.clone();
+            if let Some(candidate) = neighborhood.first() {
+                involved.extend(candidate.iter_neighbors());
+            }
+            let decayed: Vec<u64> = involved
+                .iter()
+                .filter(|v| {
+                    deferred.catch_up(v) && v.deref_data().weight <= self.params.decay_threshold
+                })
+                .map(|v| v.deref_data().id)
+                .collect();
+            if frozen || decayed.is_empty() {
+                return neighborhood;
+            }
+            model.retain_balls(|v| !decayed.contains(&v.deref_data().id));
+            stats.removed += decayed.len();
+        }
+    }
+
+    /// Checks if the topology of the model is frozen.
+    fn is_frozen(&self) -> bool {
+        matches!(&self.frozen, Some(flag) if flag.load(Ordering::Relaxed))
+    }
+
     /// Initializes the model for the first incoming point.
-    /// It creates a first balls with an infinite radius and a zero weight.
+    /// By default, it creates a first balls with an infinite radius and a zero weight.
     /// The second point will be merged into this ball and the radius updated
     /// to the distance between the two points.
+    /// With a fixed initial radius, the first ball has this radius and the weight of the point.
     fn init(&self, model: &mut Model<Point>, point: Point) -> BallNode<Point> {
-        let ball = Ball::new(point, f64::INFINITY, 0.);
+        let ball = match self.params.initial_radius {
+            InitialRadius::Infinite => Ball::new(point, f64::INFINITY, 0.),
+            InitialRadius::Fixed(radius) => Ball::new(point, radius, 1.),
+        };
         model.add_ball(ball, vec![])
     }
 
@@ -88,6 +781,10 @@ impl<Point: PartialEq + 'static> Algo<Point> {
     /// otherwise it is merged into the closest one.
     /// In both case the radius is calculated or updated using
     /// the distance between the point and its closest ball.
+    ///
+    /// A ball with an infinite radius, e.g. the initial ball, always includes the point
+    /// (its normalized distance to any point is zero, thus it is always the closest ball).
+    /// See [Algo::seed_ball].
     fn update(
         &self,
         model: &mut Model<Point>,
@@ -97,62 +794,121 @@ impl<Point: PartialEq + 'static> Algo<Point> {
     ) -> (BallNode<Point>, Option<BallNode<Point>>) {
         let mut closest = vertex.deref_data_mut();
         let d = (self.dist)(&closest.center, &point);
-        if d < INTRA_THRESHOLD * closest.radius {
-            self.update_ball(&mut closest, point, d);
-            (vertex.clone(), neighborhood.get(1).map(|v| v.clone()))
+        let (vertex, maybe_neighbor) = if closest.radius.is_infinite() {
+            self.seed_ball(&mut closest, &point, d);
+            (vertex.clone(), neighborhood.get(1).cloned())
+        } else if d < self.params.intra_threshold * closest.radius {
+            self.update_ball(&mut closest, &point, d);
+            (vertex.clone(), neighborhood.get(1).cloned())
         } else {
-            let ball = self.split_ball(point, d, &closest);
+            let ball = self.split_ball(&point, d, &closest);
             let vertex = model.add_ball(ball, neighborhood.get_neighbors());
             (vertex.clone(), Some(vertex))
+        };
+        drop(closest);
+        model.touch(&vertex);
+        model.remember(&vertex, point);
+        (vertex, maybe_neighbor)
+    }
+
+    /// Updates the model without changing its topology: the point is always merged into its closest ball.
+    fn refine(
+        &self,
+        model: &mut Model<Point>,
+        vertex: &BallNode<Point>,
+        point: Point,
+    ) -> BallNode<Point> {
+        let mut closest = vertex.deref_data_mut();
+        let d = (self.dist)(&closest.center, &point);
+        if closest.radius.is_infinite() {
+            self.seed_ball(&mut closest, &point, d);
+        } else {
+            self.update_ball(&mut closest, &point, d);
         }
+        drop(closest);
+        model.touch(vertex);
+        model.remember(vertex, point);
+        vertex.clone()
+    }
+
+    /// Updates a ball with an infinite radius when the given point is merged.
+    /// The center is updated to the weighted center of point and the ball.
+    /// The radius is set to the distance between the point and the ball center,
+    /// which makes it finite whatever the weight of the ball.
+    fn seed_ball(&self, ball: &mut impl DerefMut<Target = Ball<Point>>, point: &Point, dist: f64) {
+        ball.center = self.update_mu(ball, point);
+        ball.radius = dist;
+        ball.weight += 1.;
     }
 
     /// Updates the ball when the given point is merged.
     /// The center is updated to the weighted center of point ansd the ball.
     /// The radius is updated using the distance between the point and the ball center.
-    fn update_ball(&self, ball: &mut impl DerefMut<Target = Ball<Point>>, point: Point, dist: f64) {
+    fn update_ball(
+        &self,
+        ball: &mut impl DerefMut<Target = Ball<Point>>,
+        point: &Point,
+        dist: f64,
+    ) {
         ball.center = self.update_mu(ball, point);
         ball.radius = self.update_sigma(ball, dist);
         ball.weight += 1.;
     }
 
     /// Updates the ball center to the weighted center of point ansd the ball.
-    fn update_mu(&self, ball: &impl DerefMut<Target = Ball<Point>>, point: Point) -> Point {
-        (self.combine)(&ball.center, ball.weight, &point, 1.)
+    fn update_mu(&self, ball: &impl DerefMut<Target = Ball<Point>>, point: &Point) -> Point {
+        (self.combine)(&ball.center, ball.weight, point, 1.)
     }
 
     /// Updates the ball radius using the distance between the point and the ball center.
     fn update_sigma(&self, ball: &impl DerefMut<Target = Ball<Point>>, dist: f64) -> f64 {
         if ball.weight == 0. {
-            dist
-        } else {
-            (ball.radius * ball.weight + dist) / (ball.weight + 1.)
+            return dist;
         }
+        let dist = match self.params.radius_update {
+            RadiusUpdate::Average => dist,
+            // the center shift contributes dist / (weight + 1) to the spread of the previous points
+            RadiusUpdate::Welford => dist * ball.weight / (ball.weight + 1.),
+        };
+        (ball.radius * ball.weight + dist) / (ball.weight + 1.)
     }
 
     /// Creates a new ball for the point.
     /// The center and the radius are calculated using the distance to its closest neighbor.
     fn split_ball(
         &self,
-        point: Point,
+        point: &Point,
         d: f64,
         neighbor: &impl DerefMut<Target = Ball<Point>>,
     ) -> Ball<Point> {
-        let radius = d / EXTRA_THRESHOLD;
-        let center = (self.combine)(&neighbor.center, -1., &point, 5.);
+        let radius = d / self.params.extra_threshold;
+        let center = (self.combine)(
+            &neighbor.center,
+            self.params.split_neighbor_weight,
+            point,
+            self.params.split_point_weight,
+        );
         Ball::new(center, radius, 1.)
     }
 
     /// Updates the neighborhood of a ball with the candidate ball if it is closer than its current neighbors.
     /// Then merges the ball with its closest neighbor if close enough.
-    fn update_local_graph(&self, vertex: &BallNode<Point>, maybe_neighbor: BallNode<Point>) {
+    /// Returns the merged neighbor, if any.
+    fn update_local_graph(
+        &self,
+        model: &mut Model<Point>,
+        vertex: &BallNode<Point>,
+        maybe_neighbor: BallNode<Point>,
+    ) -> Option<BallNode<Point>> {
         let neighborhood: Vec<BallNode<Point>> = vertex.iter_neighbors().collect();
         let neighborhood = self.rebuild_neighborhood(vertex, neighborhood, maybe_neighbor);
-        let mut neighborhood = self.rebuild_merge(vertex, neighborhood);
+        let (mut neighborhood, merged) = self.rebuild_merge(model, vertex, neighborhood);
         if neighborhood.len() > MAX_NEIGHBORS {
             neighborhood.pop();
         }
         vertex.set_neighbors(neighborhood.get_neighbors());
+        debug_assert!(vertex.degree() <= MAX_NEIGHBORS);
+        merged
     }
 
     /// Updates the neighborhood of a ball with the candidate ball if it is closer than its current neighbors.
@@ -189,61 +945,196 @@ impl<Point: PartialEq + 'static> Algo<Point> {
     /// Merges a ball to its closest neighbor if it is close enough.
     fn rebuild_merge(
         &self,
+        model: &mut Model<Point>,
         vertex: &BallNode<Point>,
         mut neighborhood: Vec<BallNode<Point>>,
-    ) -> Vec<BallNode<Point>> {
+    ) -> (Vec<BallNode<Point>>, Option<BallNode<Point>>) {
         let (should_merge, d) = self.should_merge(vertex, &neighborhood[0]);
         if should_merge {
             self.merge_balls(vertex, &neighborhood[0], d);
-            neighborhood.remove(0);
+            model.touch(vertex);
+            model.touch(&neighborhood[0]);
+            model.merge_reservoirs(vertex, &neighborhood[0]);
+            let merged = neighborhood.remove(0);
+            (neighborhood, Some(merged))
+        } else {
+            (neighborhood, None)
         }
-        neighborhood
     }
 
-    /// Decides if two balls are close enough to merge.
+    /// Decides if two balls are close enough to merge, see [Model::should_merge].
     fn should_merge(&self, first: &BallNode<Point>, second: &BallNode<Point>) -> (bool, f64) {
-        let current_data = first.deref_data();
-        let neighbor_data = second.deref_data();
-        let d = (self.dist)(&current_data.center, &neighbor_data.center);
-        let should_merge = d < (current_data.radius + neighbor_data.radius) * MERGE_THRESHOLD;
-        (should_merge, d)
+        Model::should_merge(first, second, &self.dist, self.params.merge_threshold)
     }
 
-    /// Merge two balls.
-    /// The new center is the weighted center of the ball centers
-    /// and the new radius is the weighted average of the balls variances.
+    /// Merge two balls, see [Model::merge_balls].
     fn merge_balls(&self, vertex: &BallNode<Point>, neighbor: &BallNode<Point>, d: f64) {
-        let mut current_data = vertex.deref_data_mut();
-        let mut neighbor_data = neighbor.deref_data_mut();
-        current_data.center = (self.combine)(
-            &current_data.center,
-            current_data.weight,
-            &neighbor_data.center,
-            neighbor_data.weight,
-        );
-        current_data.radius = d
-            + (current_data.radius * current_data.weight
-                + neighbor_data.radius * neighbor_data.weight)
-                / (current_data.weight + neighbor_data.weight);
-        current_data.weight = current_data.weight + neighbor_data.weight;
-        neighbor_data.weight = 0.;
+        Model::merge_balls(vertex, neighbor, d, &self.combine)
     }
 
-    /// Decrease the weight of all balls by applying decay factor.
-    /// Remove balls which weight is too low.
-    fn decay(&self, model: &mut Model<Point>, vertex: BallNode<Point>) {
-        model.graph.retain(|v| {
-            if v.deref_data().ne(&vertex.deref_data()) {
-                v.deref_data_mut().weight *= DECAY_FACTOR;
+    /// Evicts the ball with the lowest weight or merges the two closest balls, according to the cap policy,
+    /// until the model does not exceed the cap. The given ball is never removed.
+    /// The neighbors of a removed ball inherit its other neighbors.
+    /// Counts the evicted balls and the merged balls.
+    fn evict(&self, model: &mut Model<Point>, vertex: &BallNode<Point>, stats: &mut BatchStats) {
+        let max_balls = match self.max_balls {
+            Some(max_balls) if model.graph.len() > max_balls => max_balls,
+            _ => return,
+        };
+        while model.graph.len() > max_balls {
+            let (evicted, count) = match self.cap_policy {
+                CapPolicy::EvictLightest => (Self::lightest(model, vertex), &mut stats.evicted),
+                CapPolicy::MergeClosest => (self.merge_closest(model, vertex), &mut stats.merged),
+            };
+            let evicted = match evicted {
+                Some(evicted) => evicted,
+                None => break,
+            };
+            *count += 1;
+            self.repair_neighbors(&evicted);
+            let id = evicted.deref_data().id;
+            model.retain_balls(|v| v.deref_data().id != id);
+        }
+    }
+
+    /// Gets the ball with the lowest weight, but the given one.
+    fn lightest(model: &Model<Point>, vertex: &BallNode<Point>) -> Option<BallNode<Point>> {
+        model
+            .graph
+            .iter()
+            .filter(|v| v.deref_data().ne(&vertex.deref_data()))
+            .min_by(|v1, v2| v1.deref_data().weight.total_cmp(&v2.deref_data().weight))
+            .cloned()
+    }
+
+    /// Merges the two closest neighbor balls which radius is finite.
+    /// The given ball, or else the heaviest ball, absorbs the other one.
+    /// Returns the absorbed ball, which weight is zero and must be removed.
+    fn merge_closest(
+        &self,
+        model: &mut Model<Point>,
+        vertex: &BallNode<Point>,
+    ) -> Option<BallNode<Point>> {
+        let (d, first, second) = model
+            .graph
+            .iter()
+            .filter_map(|v| {
+                let neighbor = v.iter_neighbors().next()?;
+                let (first, second) = (v.deref_data(), neighbor.deref_data());
+                if first.radius.is_infinite() || second.radius.is_infinite() {
+                    return None;
+                }
+                let d = (self.dist)(&first.center, &second.center);
+                drop((first, second));
+                Some((d, v.clone(), neighbor))
+            })
+            .min_by(|(d1, _, _), (d2, _, _)| d1.total_cmp(d2))?;
+        let id = vertex.deref_data().id;
+        let first_absorbs = first.deref_data().id == id
+            || (second.deref_data().id != id
+                && first.deref_data().weight >= second.deref_data().weight);
+        let (kept, absorbed) = if first_absorbs {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        self.merge_balls(&kept, &absorbed, d);
+        model.touch(&kept);
+        model.merge_reservoirs(&kept, &absorbed);
+        Some(absorbed)
+    }
+
+    /// Links the neighbors of a ball about to be removed to its other neighbors,
+    /// closest first, so that the graph stays connected around it.
+    fn repair_neighbors(&self, evicted: &BallNode<Point>) {
+        let orphans: Vec<BallNode<Point>> = evicted.iter_neighbors().collect();
+        for orphan in orphans.iter() {
+            let mut neighborhood: Vec<BallNode<Point>> =
+                orphan.iter_neighbors().filter(|v| v.ne(evicted)).collect();
+            for candidate in orphans.iter().filter(|v| v.ne(&orphan)) {
+                if !neighborhood.contains(candidate) {
+                    neighborhood.push(candidate.clone());
+                }
             }
-            v.deref_data().weight > DECAY_THRESHOLD
-        })
+            let data = orphan.deref_data();
+            neighborhood.sort_by(|v1, v2| {
+                let d1 = (self.dist)(&v1.deref_data().center, &data.center);
+                let d2 = (self.dist)(&v2.deref_data().center, &data.center);
+                d1.total_cmp(&d2)
+            });
+            drop(data);
+            neighborhood.truncate(MAX_NEIGHBORS);
+            orphan.set_neighbors(neighborhood.get_neighbors());
+        }
+    }
+
+    /// Decrease the weight of all balls by applying decay factor.
+    /// Remove balls which weight is too low, unless the topology is frozen.
+    /// If the decay is deferred, only records that the given ball is not decayed.
+    /// Returns the number of removed balls.
+    fn decay(
+        &self,
+        model: &mut Model<Point>,
+        vertex: BallNode<Point>,
+        frozen: bool,
+        deferred: Option<&mut Deferred>,
+    ) -> usize {
+        if let Some(deferred) = deferred {
+            deferred.touch(&vertex);
+            return 0;
+        }
+        let count = model.graph.len();
+        let params = &self.params;
+        model.record_decay(params.decay_factor);
+        (self.decay_weights)(&model.graph, &vertex, params.decay_factor);
+        model.retain_balls(|v| frozen || v.deref_data().weight > params.decay_threshold);
+        count - model.graph.len()
+    }
+}
+
+/// Multiplies the weight of all balls but the given one by the decay factor.
+fn decay_weights<Point: PartialEq>(
+    graph: &[BallNode<Point>],
+    vertex: &BallNode<Point>,
+    factor: f64,
+) {
+    let id = vertex.deref_data().id;
+    graph.iter().for_each(|v| decay_weight(v, id, factor));
+}
+
+/// Multiplies the weight of all balls but the given one by the decay factor, in parallel.
+///
+/// The result is the same as the sequential decay: the new weight of a ball only depends on its own weight,
+/// each ball is locked by a single thread and the ball that is not decayed is only compared by id,
+/// which is read before the pass starts.
+/// Small models are decayed by a single thread, for which the parallel pass is not worth its overhead.
+#[cfg(feature = "rayon")]
+fn par_decay_weights<Point: PartialEq + Send + Sync>(
+    graph: &[BallNode<Point>],
+    vertex: &BallNode<Point>,
+    factor: f64,
+) {
+    use rayon::prelude::*;
+
+    let id = vertex.deref_data().id;
+    graph
+        .par_iter()
+        .with_min_len(PARALLEL_MIN_BALLS)
+        .for_each(|v| decay_weight(v, id, factor));
+}
+
+/// Multiplies the weight of the ball by the decay factor, unless it has the given id.
+fn decay_weight<Point: PartialEq>(vertex: &BallNode<Point>, id: u64, factor: f64) {
+    let mut ball = vertex.deref_data_mut();
+    if ball.id != id {
+        ball.weight *= factor;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use approx_eq::assert_approx_eq;
+    use rand::{Rng, SeedableRng};
 
     use crate::algorithm::*;
     use crate::space;
@@ -258,6 +1149,33 @@ mod tests {
         assert_eq!(0., first.weight);
     }
 
+    #[test]
+    fn test_initial_radius() {
+        let params = AlgoParams {
+            initial_radius: InitialRadius::Fixed(4.),
+            ..AlgoParams::default()
+        };
+        let algo = Algo::new(space::euclid_dist, space::real_combine).with_params(params);
+        let mut model = Model::new(space::euclid_dist);
+        algo.fit(&mut model, vec![1., 1.]);
+        let first = model.iter_balls().next().unwrap().clone();
+        assert_eq!(4., first.radius);
+        assert_eq!(1., first.weight);
+        let json = serde_json::to_string(&crate::streamer::serialize_model(&model)).unwrap();
+        assert_eq!(r#"[{"center":[1.0,1.0],"radius":2.0,"weight":1.0}]"#, json);
+        algo.fit(&mut model, vec![3., 1.]);
+        let balls = model.cloned_balls();
+        assert_eq!(1, balls.len());
+        assert_eq!(vec![2., 1.], balls[0].center);
+        assert_eq!(4., balls[0].radius);
+        assert_eq!(2., balls[0].weight);
+        algo.fit(&mut model, vec![100., 1.]);
+        assert_eq!(2, model.iter_balls().count());
+        let json = serde_json::to_string(&params).unwrap();
+        assert!(json.contains(r#""initial_radius":{"fixed":4.0}"#));
+        assert_eq!(params, serde_json::from_str(&json).unwrap());
+    }
+
     #[test]
     fn test_update() {
         let (dataset, model) = build_model(2);
@@ -282,6 +1200,92 @@ mod tests {
         assert_eq!(1., second.weight);
     }
 
+    #[test]
+    fn test_split_outlier() {
+        let outlier = vec![1000., -1000.];
+        let fit = |params: AlgoParams| {
+            let algo = Algo::new(space::euclid_dist, space::real_combine).with_params(params);
+            let mut model = Model::new(space::euclid_dist);
+            for i in 0..20 {
+                algo.fit(&mut model, vec![(i % 3) as f64, (i % 5) as f64]);
+            }
+            algo.fit(&mut model, outlier.clone());
+            let ball = model
+                .iter_balls()
+                .find(|b| b.weight == 1.)
+                .map(|b| b.clone())
+                .unwrap();
+            ball
+        };
+        let ball = fit(AlgoParams::default().centered_split());
+        assert_eq!(outlier, ball.center);
+        assert!(space::euclid_dist(&ball.center, &outlier) <= ball.radius);
+        let ball = fit(AlgoParams::default());
+        assert!(space::euclid_dist(&ball.center, &outlier) > ball.radius);
+    }
+
+    #[test]
+    fn test_radius_update_drift() {
+        let fit = |radius_update: RadiusUpdate| {
+            let params = AlgoParams {
+                intra_threshold: 1E9,
+                radius_update,
+                ..AlgoParams::default()
+            };
+            let algo = Algo::new(space::euclid_dist, space::real_combine).with_params(params);
+            let mut model = Model::new(space::euclid_dist);
+            let mut rng = rand::rngs::StdRng::seed_from_u64(6624098517326409);
+            let mut points = vec![];
+            for i in 0..100 {
+                let dx: f64 = rng.sample(rand_distr::StandardNormal);
+                let dy: f64 = rng.sample(rand_distr::StandardNormal);
+                let point = vec![0.1 * i as f64 + dx, dy];
+                points.push(point.clone());
+                algo.fit(&mut model, point);
+            }
+            assert_eq!(1, model.iter_balls().count());
+            let ball = model.iter_balls().next().unwrap().clone();
+            (ball, points)
+        };
+        let (ball, points) = fit(RadiusUpdate::Welford);
+        // the first point has no weight: it only sets the initial radius
+        let (first, points) = (&points[0], &points[1..]);
+        let spread: f64 = points
+            .iter()
+            .map(|p| space::euclid_dist(p, &ball.center))
+            .sum::<f64>();
+        let prior = space::euclid_dist(first, &points[0]);
+        let variance = (spread + prior) / ball.weight;
+        assert!((ball.radius - variance).abs() < 0.01 * variance);
+        let (legacy, _) = fit(RadiusUpdate::Average);
+        assert!((legacy.radius - variance).abs() > 0.01 * variance);
+    }
+
+    #[test]
+    fn test_merge_scale_invariance() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3159370286414837);
+        let mut dataset = build_sample();
+        for i in 0..3000 {
+            let [x, y] = [[0., 0.], [20., 20.]][i / 5 % 2];
+            let dx: f64 = rng.sample(rand_distr::StandardNormal);
+            let dy: f64 = rng.sample(rand_distr::StandardNormal);
+            dataset.push(vec![x + 3. * dx, y + 3. * dy]);
+        }
+        let fit = |scale: f64| {
+            let stats = AlgoStats::new();
+            let algo = Algo::new(space::euclid_dist, space::real_combine).with_stats(stats.clone());
+            let mut model = Model::new(space::euclid_dist);
+            for point in dataset.iter() {
+                algo.fit(&mut model, point.iter().map(|x| x * scale).collect());
+            }
+            (stats.balls(), stats.merges(), stats.splits())
+        };
+        let expected = fit(1.);
+        assert!(expected.1 > 0);
+        assert_eq!(expected, fit(0.1));
+        assert_eq!(expected, fit(10.));
+    }
+
     #[test]
     fn test_neighborhood_init() {
         let (_dataset, model) = build_model(3);
@@ -355,6 +1359,274 @@ mod tests {
         assert!(n1.next().is_none());
     }
 
+    #[test]
+    fn test_fit_loaded_infinite_radius() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let data = vec![
+            Ball::new(vec![0.], f64::INFINITY, 2.),
+            Ball::new(vec![10.], 1., 1.),
+        ];
+        let mut model = Model::load(space::euclid_dist, data);
+        algo.fit(&mut model, vec![3.]);
+        {
+            let mut balls = model.iter_balls();
+            let first = balls.next().unwrap();
+            assert_eq!(vec![1.], first.center);
+            assert_eq!(9., first.radius);
+            assert_eq!(3., first.weight);
+            let second = balls.next().unwrap();
+            assert_eq!(DECAY_FACTOR, second.weight);
+        }
+        algo.fit(&mut model, vec![2.]);
+        let first = model.iter_balls().next().unwrap();
+        assert_eq!(vec![1.25], first.center);
+        assert_eq!(7., first.radius);
+        assert_eq!(4., first.weight);
+    }
+
+    #[test]
+    fn test_try_fit() {
+        let algo =
+            Algo::new(space::euclid_dist, space::real_combine).with_validation(space::check_dim);
+        let mut model = Model::new(space::euclid_dist);
+        algo.try_fit(&mut model, vec![1., 1.]).unwrap();
+        algo.try_fit(&mut model, vec![2., 1.]).unwrap();
+        let result = algo.try_fit(&mut model, vec![3., 1., 0.]);
+        assert!(result.is_err());
+        let mut balls = model.iter_balls();
+        let first = balls.next().unwrap();
+        assert_eq!(vec![2., 1.], first.center);
+        assert_eq!(1., first.weight);
+        assert!(balls.next().is_none());
+    }
+
+    #[test]
+    fn test_try_fit_non_finite() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine)
+            .with_point_validation(space::check_finite)
+            .with_validation(space::check_dim);
+        let mut model = Model::new(space::euclid_dist);
+        assert!(algo.try_fit(&mut model, vec![f64::NAN, 1.]).is_err());
+        assert_eq!(0, model.iter_balls().count());
+        algo.try_fit(&mut model, vec![1., 1.]).unwrap();
+        algo.try_fit(&mut model, vec![2., 1.]).unwrap();
+        let err = algo
+            .try_fit(&mut model, vec![3., f64::INFINITY])
+            .unwrap_err();
+        assert_eq!("non finite coordinate at index 1", err.to_string());
+        let balls = model.cloned_balls();
+        assert_eq!(1, balls.len());
+        assert_eq!(vec![2., 1.], balls[0].center);
+        assert_eq!(1., balls[0].weight);
+    }
+
+    #[test]
+    fn test_fit_non_finite() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine)
+            .with_point_validation(space::check_finite);
+        let mut model = Model::new(space::euclid_dist);
+        algo.fit(&mut model, vec![f64::NAN, 1.]);
+        assert_eq!(0, model.iter_balls().count());
+        algo.fit(&mut model, vec![1., 1.]);
+        let result = algo.fit_with_result(&mut model, vec![f64::INFINITY, 1.]);
+        assert_eq!(None, result.ball);
+        assert_eq!(1, result.stats.rejected);
+        assert_eq!(0, result.stats.points);
+        let stats = algo.fit_batch(&mut model, vec![vec![2., 1.], vec![f64::NAN, 2.]]);
+        assert_eq!(1, stats.points);
+        assert_eq!(1, stats.rejected);
+        let balls = model.cloned_balls();
+        assert_eq!(1, balls.len());
+        assert_eq!(vec![2., 1.], balls[0].center);
+    }
+
+    #[test]
+    fn test_frozen_topology() {
+        let frozen = Arc::new(AtomicBool::new(false));
+        let algo =
+            Algo::new(space::euclid_dist, space::real_combine).with_freeze_flag(frozen.clone());
+        let mut model = Model::new(space::euclid_dist);
+        for point in build_sample() {
+            algo.fit(&mut model, point);
+        }
+        let before: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+        frozen.store(true, Ordering::Relaxed);
+        for i in 0..200 {
+            algo.fit(&mut model, vec![1E4 + i as f64, -1E4]);
+        }
+        let after: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+        assert_eq!(before.len(), after.len());
+        assert_ne!(before, after);
+        frozen.store(false, Ordering::Relaxed);
+        algo.fit(&mut model, vec![-1E4, 1E4]);
+        assert!(model.iter_balls().count() != before.len());
+    }
+
+    #[test]
+    fn test_decay_many_balls() {
+        check_decay_many_balls(Algo::new(space::euclid_dist, space::real_combine));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_decay_many_balls() {
+        check_decay_many_balls(
+            Algo::new(space::euclid_dist, space::real_combine).with_parallel_decay(),
+        );
+    }
+
+    fn check_decay_many_balls(algo: Algo<Vec<f64>>) {
+        let data = (0..1500)
+            .map(|i| Ball::new(vec![i as f64], 1., if i < 100 { 0.01 } else { 1. }))
+            .collect();
+        let mut model = Model::load(space::euclid_dist, data);
+        let vertex = model.graph[1000].clone();
+        assert_eq!(100, algo.decay(&mut model, vertex, false, None));
+        assert_eq!(1400, model.graph.len());
+        let weights: Vec<f64> = model.iter_balls().map(|b| b.weight).collect();
+        assert_eq!(1., weights[900]);
+        assert!(weights
+            .iter()
+            .enumerate()
+            .all(|(i, w)| i == 900 || *w == DECAY_FACTOR));
+    }
+
+    #[test]
+    fn test_max_balls() {
+        use rand::{Rng, SeedableRng};
+        use rand_distr::StandardNormal;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(846513548);
+        let algo = Algo::new(space::euclid_dist, space::real_combine).with_max_balls(4);
+        let mut model = Model::new(space::euclid_dist);
+        let clusters = [[0., 0.], [100., 100.]];
+        for i in 0..3000 {
+            let point = if i % 10 == 9 {
+                vec![rng.gen_range(-20. ..120.), rng.gen_range(-20. ..120.)]
+            } else {
+                let [x, y] = clusters[i / 2 % 2];
+                let dx: f64 = rng.sample(StandardNormal);
+                let dy: f64 = rng.sample(StandardNormal);
+                vec![x + dx, y + dy]
+            };
+            algo.fit(&mut model, point);
+            assert!(model.iter_balls().count() <= 4);
+        }
+        for [x, y] in clusters {
+            let heavy = model
+                .iter_balls()
+                .any(|b| space::euclid_dist(&b.center, &vec![x, y]) < b.radius && b.weight > 1.);
+            assert!(heavy);
+        }
+    }
+
+    #[test]
+    fn test_max_balls_merge_closest() {
+        use rand::{Rng, SeedableRng};
+        use rand_distr::StandardNormal;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(846513548);
+        let algo = Algo::new(space::euclid_dist, space::real_combine)
+            .with_max_balls(3)
+            .with_cap_policy(CapPolicy::MergeClosest);
+        let mut model = Model::new(space::euclid_dist);
+        let clusters = [[0., 0.], [100., 0.], [0., 100.], [100., 100.]];
+        for i in 0..3000 {
+            let [x, y] = clusters[i % 4];
+            let dx: f64 = rng.sample(StandardNormal);
+            let dy: f64 = rng.sample(StandardNormal);
+            algo.fit(&mut model, vec![x + dx, y + dy]);
+            assert!(model.iter_balls().count() <= 3);
+        }
+        // every cluster is still covered by a ball
+        let covered = clusters.iter().all(|[x, y]| {
+            model
+                .iter_balls()
+                .any(|b| space::euclid_dist(&b.center, &vec![*x, *y]) < b.radius)
+        });
+        assert!(covered);
+    }
+
+    #[test]
+    fn test_max_balls_stats() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine).with_max_balls(2);
+        let mut model = Model::new(space::euclid_dist);
+        let stats: Vec<_> = [0., 1., 1000., 1000000., 1000000000.]
+            .into_iter()
+            .map(|x| algo.fit_with_result(&mut model, vec![x]).stats)
+            .collect();
+        assert_eq!(2, model.iter_balls().count());
+        assert_eq!(
+            vec![0, 0, 0, 1, 1],
+            stats.iter().map(|s| s.evicted).collect::<Vec<_>>()
+        );
+        assert!(stats.iter().all(|s| s.merged == 0 && s.removed == 0));
+    }
+
+    #[test]
+    fn test_merge_closest_stats() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine)
+            .with_max_balls(2)
+            .with_cap_policy(CapPolicy::MergeClosest);
+        let mut model = Model::new(space::euclid_dist);
+        let stats: Vec<_> = [0., 1., 1000., 1000000., 1000000000.]
+            .into_iter()
+            .map(|x| algo.fit_with_result(&mut model, vec![x]).stats)
+            .collect();
+        assert_eq!(2, model.iter_balls().count());
+        assert_eq!(
+            vec![0, 0, 0, 1, 1],
+            stats.iter().map(|s| s.merged).collect::<Vec<_>>()
+        );
+        assert!(stats.iter().all(|s| s.evicted == 0 && s.removed == 0));
+    }
+
+    #[test]
+    fn test_fit_with_result() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut merged = 0;
+        for point in build_sample() {
+            let result = algo.fit_with_result(&mut model, point);
+            let ball = result.ball.unwrap();
+            assert!(model.iter_balls().any(|b| *b == ball));
+            merged += result.stats.merged;
+        }
+        assert!(merged > 0);
+    }
+
+    #[test]
+    fn test_fit_batch() {
+        let (_dataset, model) = build_model(8);
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut batch_model = Model::new(space::euclid_dist);
+        let stats = algo.fit_batch(&mut batch_model, build_sample().into_iter().take(8));
+        let balls: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+        let batch_balls: Vec<_> = batch_model.iter_balls().map(|b| b.clone()).collect();
+        assert_eq!(balls.len(), batch_balls.len());
+        for (ball, batch_ball) in balls.iter().zip(batch_balls.iter()) {
+            assert_eq!(ball.center, batch_ball.center);
+            assert_eq!(ball.radius, batch_ball.radius);
+            assert_approx_eq!(ball.weight, batch_ball.weight);
+        }
+        assert_eq!(8, stats.points);
+        assert_eq!(stats.created - stats.removed, balls.len());
+        assert!(stats.merged > 0);
+    }
+
+    #[test]
+    fn test_merge_models() {
+        let (_dataset, mut model) = build_model(8);
+        let (_dataset, other) = build_model(8);
+        let count = model.iter_balls().count();
+        let weight = model.total_weight();
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let merged = algo.merge(&mut model, other);
+        assert_eq!(count, merged);
+        assert_eq!(count, model.iter_balls().count());
+        assert_approx_eq!(2. * weight, model.total_weight());
+    }
+
     fn build_model(count: usize) -> (Vec<Vec<f64>>, Model<Vec<f64>>) {
         let dataset = build_sample();
         let algo = Algo::new(space::euclid_dist, space::real_combine);
@@ -1,12 +1,18 @@
+//! A graph which vertices can be shared between threads.
+//!
+//! Vertices are `Send + Sync` when their data is `Send + Sync`.
+//! Like `RefCell` borrows, a vertex data must not be borrowed mutably while it is borrowed:
+//! such a borrow would deadlock instead of panicking.
+
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    collections::{HashSet, VecDeque},
     ops::{Deref, DerefMut},
-    rc::{Rc, Weak},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak},
 };
 
 /// A vertex of a graph.
 pub struct Vertex<Data: PartialEq> {
-    node: Rc<RefCell<Node<Data>>>,
+    node: Arc<RwLock<Node<Data>>>,
 }
 
 impl<Data: PartialEq> Clone for Vertex<Data> {
@@ -19,13 +25,13 @@ impl<Data: PartialEq> Clone for Vertex<Data> {
 
 impl<Data: PartialEq> PartialEq for Vertex<Data> {
     fn eq(&self, other: &Self) -> bool {
-        self.node.eq(&other.node)
+        *self.deref_data() == *other.deref_data()
     }
 }
 
 /// A vertex neighbor. Neighbors are represented as weak pointers to avoid memory leaks.
 pub struct Neighbor<Data: PartialEq> {
-    target: Weak<RefCell<Node<Data>>>,
+    target: Weak<RwLock<Node<Data>>>,
 }
 
 /// Vertex internal structure, shared by vertices and neighbors thanks to a smart pointer.
@@ -44,7 +50,7 @@ impl<Data: PartialEq> Vertex<Data> {
     /// Build a new vertex.
     pub fn new(data: Data) -> Vertex<Data> {
         Vertex {
-            node: Rc::new(RefCell::new(Node {
+            node: Arc::new(RwLock::new(Node {
                 data,
                 neighbors: vec![],
             })),
@@ -55,35 +61,129 @@ impl<Data: PartialEq> Vertex<Data> {
     /// Actually downgrades the smart pointer.
     pub fn as_neighbor(&self) -> Neighbor<Data> {
         Neighbor {
-            target: Rc::downgrade(&self.node),
+            target: Arc::downgrade(&self.node),
         }
     }
 
     /// Get an iterator over the vertices that are neighbor of this vertex.
     pub fn iter_neighbors(&self) -> impl Iterator<Item = Vertex<Data>> + '_ {
-        NeighborIterator::new(Ref::map(self.node.borrow(), |n| &n.neighbors))
+        NeighborIterator::new(self.read())
+    }
+
+    /// Get the number of neighbors of this vertex, including the neighbors that have been dropped
+    /// which [Vertex::iter_neighbors] skips, see [Vertex::live_degree].
+    pub fn degree(&self) -> usize {
+        self.read().neighbors.len()
+    }
+
+    /// Get the number of neighbors of this vertex that are still alive,
+    /// i.e. the number of vertices yielded by [Vertex::iter_neighbors].
+    pub fn live_degree(&self) -> usize {
+        self.read()
+            .neighbors
+            .iter()
+            .filter(|n| n.target.strong_count() > 0)
+            .count()
+    }
+
+    /// Get the vertices reachable from this vertex through neighbor relations, in breadth-first order,
+    /// starting with this vertex. Each vertex is visited once.
+    pub fn bfs(&self) -> Vec<Vertex<Data>> {
+        let mut visited = HashSet::from([Arc::as_ptr(&self.node)]);
+        let mut queue = VecDeque::from([self.clone()]);
+        let mut order = vec![];
+        while let Some(vertex) = queue.pop_front() {
+            for neighbor in vertex.iter_neighbors() {
+                if visited.insert(Arc::as_ptr(&neighbor.node)) {
+                    queue.push_back(neighbor);
+                }
+            }
+            order.push(vertex);
+        }
+        order
     }
 
     /// Update this vertex neighbors.
     pub fn set_neighbors(&self, neighbors: Vec<Neighbor<Data>>) {
-        self.node.borrow_mut().neighbors = neighbors;
+        self.write().neighbors = neighbors;
     }
 
-    /// Get a `Ref` to this vertex data.
+    /// Get a shared reference to this vertex data.
     pub fn deref_data<'a>(&'a self) -> impl Deref<Target = Data> + 'a {
-        Ref::map(self.node.borrow(), |n| &n.data)
+        DataRef { node: self.read() }
     }
 
-    /// Get a `RefMut` to this vertex data.
+    /// Get a mutable reference to this vertex data.
     pub fn deref_data_mut(&self) -> impl DerefMut<Target = Data> + '_ {
-        RefMut::map(self.node.borrow_mut(), |n| &mut n.data)
+        DataRefMut { node: self.write() }
+    }
+
+    /// Locks this vertex node for reading.
+    fn read(&self) -> RwLockReadGuard<'_, Node<Data>> {
+        self.node.read().unwrap()
+    }
+
+    /// Locks this vertex node for writing.
+    fn write(&self) -> RwLockWriteGuard<'_, Node<Data>> {
+        self.node.write().unwrap()
+    }
+}
+
+/// Enumerates the edges of a graph given as a collection of vertices.
+pub trait IterEdges<Data: PartialEq> {
+    /// Gets an iterator over the directed edges, i.e. each vertex paired with each of its current neighbors.
+    /// The vertex is read locked while its neighbors are iterated.
+    fn iter_edges<'a>(&'a self) -> impl Iterator<Item = (&'a Vertex<Data>, Vertex<Data>)>
+    where
+        Data: 'a;
+}
+
+impl<Data: PartialEq> IterEdges<Data> for [Vertex<Data>] {
+    fn iter_edges<'a>(&'a self) -> impl Iterator<Item = (&'a Vertex<Data>, Vertex<Data>)>
+    where
+        Data: 'a,
+    {
+        self.iter()
+            .flat_map(|vertex| vertex.iter_neighbors().map(move |n| (vertex, n)))
+    }
+}
+
+/// A shared reference to a vertex data, which holds the vertex read lock.
+struct DataRef<'a, Data: PartialEq> {
+    node: RwLockReadGuard<'a, Node<Data>>,
+}
+
+impl<'a, Data: PartialEq> Deref for DataRef<'a, Data> {
+    type Target = Data;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node.data
+    }
+}
+
+/// A mutable reference to a vertex data, which holds the vertex write lock.
+struct DataRefMut<'a, Data: PartialEq> {
+    node: RwLockWriteGuard<'a, Node<Data>>,
+}
+
+impl<'a, Data: PartialEq> Deref for DataRefMut<'a, Data> {
+    type Target = Data;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node.data
+    }
+}
+
+impl<'a, Data: PartialEq> DerefMut for DataRefMut<'a, Data> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.node.data
     }
 }
 
-/// Iterator over a reference to a `Vec` of neighbors that returns target vertices
+/// Iterator over a locked node neighbors that returns target vertices
 struct NeighborIterator<'a, Data: PartialEq> {
     curr: usize,
-    neighbors: Ref<'a, Vec<Neighbor<Data>>>,
+    node: RwLockReadGuard<'a, Node<Data>>,
 }
 
 impl<'a, Data: PartialEq> Iterator for NeighborIterator<'a, Data> {
@@ -91,10 +191,10 @@ impl<'a, Data: PartialEq> Iterator for NeighborIterator<'a, Data> {
 
     /// Returns the next vertex.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.curr >= self.neighbors.len() {
+        if self.curr >= self.node.neighbors.len() {
             None
         } else {
-            let neighbor = self.neighbors[self.curr].target.upgrade();
+            let neighbor = self.node.neighbors[self.curr].target.upgrade();
             self.curr += 1;
             if neighbor.is_none() {
                 self.next()
@@ -107,8 +207,8 @@ impl<'a, Data: PartialEq> Iterator for NeighborIterator<'a, Data> {
 
 impl<'a, Data: PartialEq> NeighborIterator<'a, Data> {
     /// Builds a new iterator.
-    fn new(neighbors: Ref<'a, Vec<Neighbor<Data>>>) -> Self {
-        NeighborIterator { curr: 0, neighbors }
+    fn new(node: RwLockReadGuard<'a, Node<Data>>) -> Self {
+        NeighborIterator { curr: 0, node }
     }
 }
 
@@ -124,8 +224,8 @@ mod tests {
         n2.set_neighbors(vec![n1.as_neighbor()]);
         n3.set_neighbors(vec![n1.as_neighbor(), n2.as_neighbor()]);
         let mut e3 = n3.iter_neighbors();
-        assert_eq!(n1.node.as_ptr(), e3.next().unwrap().node.as_ptr());
-        assert_eq!(n2.node.as_ptr(), e3.next().unwrap().node.as_ptr());
+        assert_eq!(Arc::as_ptr(&n1.node), Arc::as_ptr(&e3.next().unwrap().node));
+        assert_eq!(Arc::as_ptr(&n2.node), Arc::as_ptr(&e3.next().unwrap().node));
     }
 
     #[test]
@@ -139,14 +239,14 @@ mod tests {
         n2.set_neighbors(vec![n1_from_n2.as_neighbor(), n3.as_neighbor()]);
         n1.set_neighbors(vec![n2.as_neighbor(), n3.as_neighbor()]);
         let mut e1 = n1.iter_neighbors();
-        assert_eq!(n2.node.as_ptr(), e1.next().unwrap().node.as_ptr());
-        assert_eq!(n3.node.as_ptr(), e1.next().unwrap().node.as_ptr());
+        assert_eq!(Arc::as_ptr(&n2.node), Arc::as_ptr(&e1.next().unwrap().node));
+        assert_eq!(Arc::as_ptr(&n3.node), Arc::as_ptr(&e1.next().unwrap().node));
         let mut e2 = n2.iter_neighbors();
-        assert_eq!(n1.node.as_ptr(), e2.next().unwrap().node.as_ptr());
-        assert_eq!(n3.node.as_ptr(), e2.next().unwrap().node.as_ptr());
+        assert_eq!(Arc::as_ptr(&n1.node), Arc::as_ptr(&e2.next().unwrap().node));
+        assert_eq!(Arc::as_ptr(&n3.node), Arc::as_ptr(&e2.next().unwrap().node));
         let mut e3 = n3.iter_neighbors();
-        assert_eq!(n1.node.as_ptr(), e3.next().unwrap().node.as_ptr());
-        assert_eq!(n2.node.as_ptr(), e3.next().unwrap().node.as_ptr());
+        assert_eq!(Arc::as_ptr(&n1.node), Arc::as_ptr(&e3.next().unwrap().node));
+        assert_eq!(Arc::as_ptr(&n2.node), Arc::as_ptr(&e3.next().unwrap().node));
     }
 
     #[test]
@@ -159,6 +259,65 @@ mod tests {
         assert_eq!(3, *n1.deref_data());
     }
 
+    #[test]
+    fn test_degree() {
+        let n1 = Vertex::new(1);
+        let n2 = Vertex::new(2);
+        let n3 = Vertex::new(3);
+        assert_eq!(0, n1.degree());
+        n1.set_neighbors(vec![n2.as_neighbor(), n3.as_neighbor()]);
+        assert_eq!(2, n1.degree());
+        assert_eq!(2, n1.live_degree());
+        drop(n3);
+        assert_eq!(2, n1.degree());
+        assert_eq!(1, n1.live_degree());
+        assert_eq!(1, n1.iter_neighbors().count());
+        n1.set_neighbors(vec![n2.as_neighbor()]);
+        assert_eq!(1, n1.degree());
+    }
+
+    #[test]
+    fn test_bfs() {
+        let hub = Vertex::new(0);
+        let leaves: Vec<_> = (1..4).map(Vertex::new).collect();
+        let far = Vertex::new(4);
+        let isolated = Vertex::new(5);
+        hub.set_neighbors(leaves.iter().map(|v| v.as_neighbor()).collect());
+        leaves[0].set_neighbors(vec![leaves[1].as_neighbor(), far.as_neighbor()]);
+        leaves[1].set_neighbors(vec![leaves[0].as_neighbor(), hub.as_neighbor()]);
+        far.set_neighbors(vec![isolated.as_neighbor()]);
+        let order: Vec<i32> = hub.bfs().iter().map(|v| *v.deref_data()).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], order);
+        let order: Vec<i32> = isolated.bfs().iter().map(|v| *v.deref_data()).collect();
+        assert_eq!(vec![5], order);
+    }
+
+    #[test]
+    fn test_iter_edges() {
+        let vertices: Vec<_> = (0..4).map(Vertex::new).collect();
+        vertices[0].set_neighbors(vec![vertices[1].as_neighbor()]);
+        vertices[1].set_neighbors(vec![vertices[0].as_neighbor(), vertices[2].as_neighbor()]);
+        vertices[3].set_neighbors(vec![vertices[0].as_neighbor(), vertices[1].as_neighbor()]);
+        let neighbors: usize = vertices.iter().map(|v| v.iter_neighbors().count()).sum();
+        assert_eq!(neighbors, vertices.iter_edges().count());
+        let edges: Vec<(i32, i32)> = vertices
+            .iter_edges()
+            .map(|(v, n)| (*v.deref_data(), *n.deref_data()))
+            .collect();
+        assert_eq!(vec![(0, 1), (1, 0), (1, 2), (3, 0), (3, 1)], edges);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let n1 = Vertex::new(1);
+        let n2 = Vertex::new(2);
+        n2.set_neighbors(vec![n1.as_neighbor()]);
+        assert_send_sync(&n2);
+        let handle = std::thread::spawn(move || *n2.iter_neighbors().next().unwrap().deref_data());
+        assert_eq!(1, handle.join().unwrap());
+    }
+
     #[test]
     fn test_vertex_suppression() {
         let n1 = Vertex::new(1);
@@ -171,7 +330,10 @@ mod tests {
         let mut e2 = graph[0].iter_neighbors();
         assert!(e2.next().is_none());
         let mut e3 = graph[1].iter_neighbors();
-        assert_eq!(graph[0].node.as_ptr(), e3.next().unwrap().node.as_ptr());
+        assert_eq!(
+            Arc::as_ptr(&graph[0].node),
+            Arc::as_ptr(&e3.next().unwrap().node)
+        );
         assert!(e3.next().is_none());
     }
 }
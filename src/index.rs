@@ -0,0 +1,168 @@
+//! This module defines the spatial indexes that speed up the search of the balls
+//! which a point most probably belongs to, see [Model::with_grid_index](crate::Model::with_grid_index).
+
+use std::collections::HashMap;
+
+use crate::{
+    model::{Ball, BallNode},
+    space::RealPoint,
+};
+
+/// An index over the ball centers, maintained when balls are added, moved or removed.
+pub(crate) trait SpatialIndex<Point: PartialEq>: Send + Sync {
+    /// Indexes the ball, or reindexes it if its center or radius changed.
+    fn insert(&mut self, vertex: &BallNode<Point>);
+
+    /// Removes the ball from the index.
+    fn remove(&mut self, id: u64);
+
+    /// Gets the two balls nearest to the point by normalized distance, ordered by their distance.
+    /// The result is the same as a linear scan, the index only avoids computing the distance to far balls.
+    fn get_neighborhood(
+        &self,
+        point: &Point,
+        dist: &dyn Fn(&Point, &Ball<Point>) -> f64,
+    ) -> Vec<BallNode<Point>>;
+}
+
+/// A cell of the grid and the balls which center lies inside.
+#[derive(Default)]
+struct Cell {
+    balls: Vec<BallNode<RealPoint>>,
+    max_radius: f64,
+}
+
+impl Cell {
+    fn update_max_radius(&mut self) {
+        self.max_radius = self
+            .balls
+            .iter()
+            .map(|v| v.deref_data().radius)
+            .fold(0., f64::max);
+    }
+}
+
+/// A regular grid over the ball centers.
+///
+/// The normalized distance from a point to the balls of a cell is bounded below
+/// by the distance to the nearest point of the cell divided by the greatest radius in the cell,
+/// thus the cells are visited by increasing bound until the bound exceeds the second nearest ball.
+pub(crate) struct GridIndex {
+    cell_size: f64,
+    cells: HashMap<Vec<i64>, Cell>,
+    keys: HashMap<u64, Vec<i64>>,
+}
+
+impl GridIndex {
+    /// Builds an empty grid which cells have the given side.
+    /// Panics if the side is not positive and finite.
+    pub(crate) fn new(cell_size: f64) -> Self {
+        assert!(
+            cell_size > 0. && cell_size.is_finite(),
+            "cell size must be positive."
+        );
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Gets the key of the cell that contains the point.
+    fn key(&self, point: &RealPoint) -> Vec<i64> {
+        point
+            .iter()
+            .map(|x| (x / self.cell_size).floor() as i64)
+            .collect()
+    }
+
+    /// Gets the lower bound of the normalized distance from the point to the balls of the cell.
+    /// The raw distance is the normalized distance to a ball of unit radius centered on the nearest point of the cell,
+    /// which assumes the distance grows with the gap along each coordinate, like [crate::space::euclid_dist].
+    fn bound(
+        &self,
+        point: &RealPoint,
+        key: &[i64],
+        cell: &Cell,
+        nearest: &mut Ball<RealPoint>,
+        dist: &dyn Fn(&RealPoint, &Ball<RealPoint>) -> f64,
+    ) -> f64 {
+        for ((c, x), k) in nearest.center.iter_mut().zip(point).zip(key) {
+            let low = *k as f64 * self.cell_size;
+            *c = x.clamp(low, low + self.cell_size);
+        }
+        let raw = dist(point, nearest);
+        if raw == 0. {
+            0.
+        } else {
+            raw / cell.max_radius
+        }
+    }
+}
+
+impl SpatialIndex<RealPoint> for GridIndex {
+    fn insert(&mut self, vertex: &BallNode<RealPoint>) {
+        let (id, key, radius) = {
+            let ball = vertex.deref_data();
+            (ball.id, self.key(&ball.center), ball.radius)
+        };
+        if self.keys.get(&id) != Some(&key) {
+            self.remove(id);
+            self.cells
+                .entry(key.clone())
+                .or_default()
+                .balls
+                .push(vertex.clone());
+            self.keys.insert(id, key.clone());
+        }
+        // the greatest radius may be overestimated until a ball leaves the cell,
+        // which only makes the bound less tight
+        if let Some(cell) = self.cells.get_mut(&key) {
+            cell.max_radius = cell.max_radius.max(radius);
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        let key = match self.keys.remove(&id) {
+            Some(key) => key,
+            None => return,
+        };
+        if let Some(cell) = self.cells.get_mut(&key) {
+            cell.balls.retain(|v| v.deref_data().id != id);
+            if cell.balls.is_empty() {
+                self.cells.remove(&key);
+            } else {
+                cell.update_max_radius();
+            }
+        }
+    }
+
+    fn get_neighborhood(
+        &self,
+        point: &RealPoint,
+        dist: &dyn Fn(&RealPoint, &Ball<RealPoint>) -> f64,
+    ) -> Vec<BallNode<RealPoint>> {
+        let mut nearest = Ball::new(point.clone(), 1., 0.);
+        let mut cells: Vec<(f64, &Cell)> = self
+            .cells
+            .iter()
+            .map(|(key, cell)| (self.bound(point, key, cell, &mut nearest, dist), cell))
+            .collect();
+        cells.sort_by(|(b1, _), (b2, _)| b1.total_cmp(b2));
+        let mut neighbors: Vec<(f64, &BallNode<RealPoint>)> = Vec::with_capacity(3);
+        for (bound, cell) in cells {
+            if matches!(neighbors.get(1), Some((d, _)) if bound > *d) {
+                break;
+            }
+            for vertex in cell.balls.iter() {
+                let d = dist(point, &vertex.deref_data());
+                let rank = neighbors.iter().take_while(|(n, _)| *n <= d).count();
+                if rank < 2 {
+                    neighbors.insert(rank, (d, vertex));
+                    neighbors.truncate(2);
+                }
+            }
+        }
+        neighbors.into_iter().map(|(_, v)| v.clone()).collect()
+    }
+}
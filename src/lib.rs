@@ -78,13 +78,15 @@
 //! use std::error::Error;
 //! use fluent_data::{service, Streamer};
 //!
-//! fn get_streamer() -> Streamer<
-//!     impl Iterator<Item = Result<String, Box<dyn Error>>>,
-//!     impl FnMut(String) -> Result<(), Box<dyn Error>>,
-//! >
-//! {
-//!     let (points, write) = service::backend();
-//!     Streamer::new(points, write)
+//! fn get_streamer() -> Result<
+//!     Streamer<
+//!         impl Iterator<Item = Result<String, Box<dyn Error>>>,
+//!         impl FnMut(String) -> Result<(), Box<dyn Error>>,
+//!     >,
+//!     Box<dyn Error>,
+//! > {
+//!     let (points, write) = service::backend()?;
+//!     Ok(Streamer::new(points, write))
 //! }
 //! ```
 //!
@@ -155,6 +157,10 @@
 //! The generated models could be saved to a persistent store by writing a custom write closure
 //! or decorating an existing one (see section above).
 //! A saved model may be loaded at system startup thanks to [Model::load].
+//! Alternatively, [Model::save_to_file] and [Model::load_from_file] save and load
+//! models to and from json files in the same format as the models produced by the [Streamer].
+//! When the `bincode` feature is enabled, `Model::to_bytes` and `Model::from_bytes`
+//! provide an exact and compact binary representation of the model.
 //! ```
 //! use fluent_data::{Model, Algo, space, model::Ball};
 //! use fluent_data::{service, Streamer};
@@ -166,18 +172,20 @@
 //!     (model, algo)
 //! }
 //!
-//! fn get_streamer() -> Streamer<
-//!     impl Iterator<Item = Result<String, Box<dyn Error>>>,
-//!     impl FnMut(String) -> Result<(), Box<dyn Error>>,
-//! >
-//! {
-//!     let (points, mut write) = service::backend();
+//! fn get_streamer() -> Result<
+//!     Streamer<
+//!         impl Iterator<Item = Result<String, Box<dyn Error>>>,
+//!         impl FnMut(String) -> Result<(), Box<dyn Error>>,
+//!     >,
+//!     Box<dyn Error>,
+//! > {
+//!     let (points, mut write) = service::backend()?;
 //!     let decorated_write = move |model| {
 //!         // save model to persistent store
 //!         todo!();
 //!         write(model)
 //!     };
-//!     Streamer::new(points, decorated_write)
+//!     Ok(Streamer::new(points, decorated_write))
 //! }
 //! ```
 //!
@@ -193,6 +201,8 @@
 //! See the project [README on crates.io](https://crates.io/crates/fluent_data) for more information.
 
 pub mod algorithm;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod model;
 pub mod neighborhood;
 pub mod service;
@@ -200,6 +210,7 @@ pub mod space;
 pub mod streamer;
 
 mod graph;
+mod index;
 
 pub use algorithm::Algo;
 pub use model::Model;
@@ -1,49 +1,618 @@
-use std::error::Error;
+use std::{
+    cell::RefCell,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use fluent_data::algorithm::{AlgoParams, CapPolicy};
+use fluent_data::neighborhood::Neighborhood;
+use fluent_data::space::RealPoint;
+use fluent_data::streamer::{Codec, CsvCodec, JsonCodec};
 use fluent_data::{service, space, streamer};
 use fluent_data::{Algo, Model, Streamer};
+use serde_json::{json, Value};
+use signal_hook::consts::{SIGINT, SIGTERM};
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// starts in service mode.
-    #[clap(short, long, value_parser)]
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// starts in service mode, deprecated: use the `serve` subcommand.
+    #[clap(short, long, value_parser, conflicts_with_all = &["input", "output"])]
     service: bool,
+
+    #[clap(flatten)]
+    run: RunArgs,
+
+    #[clap(flatten)]
+    listen: ListenArgs,
+}
+
+impl Args {
+    /// Gets the subcommand, the arguments without subcommand run the streamer
+    /// or start the service if the deprecated `--service` flag is set.
+    fn command(self) -> Command {
+        match self.command {
+            Some(command) => command,
+            None if self.service => {
+                eprintln!("warning: --service is deprecated, use the serve subcommand.");
+                Command::Serve(ServeArgs {
+                    listen: self.listen,
+                    fit: self.run.fit,
+                })
+            }
+            None => Command::Run(self.run),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// fits the model to the points read from the standard input and writes the models to the standard output,
+    /// this is the default when no subcommand is given.
+    Run(RunArgs),
+    /// fits the model to the points received from websockets and dispatches the models to websockets.
+    Serve(ServeArgs),
+    /// loads a saved model and writes the ball closest to the given point.
+    Predict(PredictArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// reads the data points from this file instead of the standard input, `-` reads the standard input.
+    #[clap(long, value_parser)]
+    input: Option<PathBuf>,
+
+    /// writes the models to this file, one per line, instead of the standard output.
+    #[clap(long, value_parser)]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    fit: FitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    #[clap(flatten)]
+    listen: ListenArgs,
+
+    #[clap(flatten)]
+    fit: FitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListenArgs {
+    /// host the service listens on in service mode, 0.0.0.0 exposes the service to other machines.
+    #[clap(long, value_parser, env = "HOST", default_value = service::DEFAULT_HOST)]
+    host: String,
+
+    /// port the service listens on in service mode.
+    #[clap(long, value_parser, env = "PORT", default_value_t = service::DEFAULT_PORT)]
+    port: u16,
+}
+
+#[derive(clap::Args, Debug)]
+struct FitArgs {
+    /// format of the input data points.
+    #[clap(long, value_enum, default_value_t = InputFormat::Json)]
+    input_format: InputFormat,
+
+    /// writes the model every N points instead of after each point,
+    /// the final model is always written whatever the interval.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), default_value_t = 1)]
+    emit_every: u64,
+
+    /// writes the models, the ball which received each point, or both.
+    #[clap(long, value_enum, default_value_t = Emit::Model)]
+    emit: Emit,
+
+    /// distance between the data points.
+    #[clap(long, value_enum, default_value_t = Distance::Euclid)]
+    distance: Distance,
+
+    /// reads the thresholds of the algorithm from this json file,
+    /// e.g. `{"intra_threshold":9.0,"extra_threshold":16.0,"decay_factor":0.9}`.
+    #[clap(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// caps the number of balls of the model, the two closest balls are merged when a new ball exceeds the cap.
+    #[clap(long, value_parser)]
+    max_balls: Option<usize>,
+
+    /// starts from the model saved in this json file instead of an empty model.
+    #[clap(long, value_parser)]
+    load: Option<PathBuf>,
+
+    /// saves the final model to this json file when the input ends or on Ctrl-C.
+    #[clap(long, value_parser)]
+    save: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct PredictArgs {
+    /// the model saved in this json file.
+    #[clap(long, value_parser)]
+    load: PathBuf,
+
+    /// distance between the data points, which must be the distance the model was fitted with.
+    #[clap(long, value_enum, default_value_t = Distance::Euclid)]
+    distance: Distance,
+
+    /// the json point, e.g. `[1.0,2.5]`.
+    #[clap(value_parser)]
+    point: String,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum InputFormat {
+    /// json arrays, e.g. `[1.0,2.5]`.
+    Json,
+    /// comma separated values, e.g. `1.0,2.5`, after a header line in a file or the standard input.
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum Emit {
+    /// the models.
+    Model,
+    /// the ball which received each point, e.g. `{"point":[1.0],"ball_id":4,"dist":0.5,"created":false}`.
+    Assignments,
+    /// the ball which received each point followed by the model with the ball ids.
+    Both,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Distance {
+    /// euclidian distance, see `space::euclid_dist`.
+    Euclid,
+    /// cosine distance, which compares the directions of the points, see `space::cosine_dist`.
+    Cosine,
+    /// manhattan distance, which is less sensitive to outliers, see `space::manhattan_dist`.
+    Manhattan,
+}
+
+type Dist = fn(&RealPoint, &RealPoint) -> f64;
+type Combine = fn(&RealPoint, f64, &RealPoint, f64) -> RealPoint;
+
+impl Distance {
+    /// Gets the distance function and the matching combine function.
+    fn functions(&self) -> (Dist, Combine) {
+        match self {
+            Distance::Euclid => (space::euclid_dist, space::real_combine),
+            Distance::Cosine => (space::cosine_dist, space::cosine_combine),
+            Distance::Manhattan => (space::manhattan_dist, space::manhattan_combine),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let (algo, mut model) = get_algo_model();
-    let streamer = get_streamer(&args);
+    let frozen = Arc::new(AtomicBool::new(false));
+    let (io, fit, serve) = match Args::parse().command() {
+        Command::Run(args) => (run_io(&args), args.fit, false),
+        Command::Serve(args) => (serve_io(&args.listen, frozen.clone()), args.fit, true),
+        Command::Predict(args) => {
+            let prediction = predict(&args).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1)
+            });
+            println!("{}", prediction);
+            return Ok(());
+        }
+    };
+    let (algo, mut model) = get_algo_model(&fit, frozen).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1)
+    });
+    let (points, write) = io.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1)
+    });
+    // the points are read in another thread, so that a signal also stops a streamer waiting for a point
+    let points = Box::new(streamer::interruptible(points, stop_on_signal()?));
+    let mut streamer = get_streamer((points, write), &fit, !serve);
+    if serve {
+        // a point sent by a client must not stop the service
+        streamer = streamer.with_log_errors();
+    }
     Streamer::run(streamer, algo, &mut model)?;
+    if let Some(path) = fit.save {
+        model.save_to_file(path)?;
+    }
     Ok(())
 }
 
-type BoxedInOut = (
-    Box<dyn Iterator<Item = Result<String, Box<dyn Error>>>>,
-    Box<dyn FnMut(String) -> Result<(), Box<dyn Error>>>,
+/// Returns a flag set on SIGINT or SIGTERM, so that the streamer stops after the current point,
+/// or right away if it waits for a point, and the final model is written and saved.
+/// A second signal terminates the process immediately.
+fn stop_on_signal() -> Result<Arc<AtomicBool>, Box<dyn Error>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&stop))?;
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+type BoxedPoints = Box<dyn Iterator<Item = Result<String, Box<dyn Error>>>>;
+
+type BoxedWrite = Box<dyn FnMut(String) -> Result<(), Box<dyn Error>>>;
+
+type BoxedInOut = (BoxedPoints, BoxedWrite);
+
+type SendInOut = (
+    Box<dyn Iterator<Item = Result<String, Box<dyn Error>>> + Send>,
+    BoxedWrite,
 );
 
-fn get_streamer(
-    args: &Args,
-) -> Streamer<
-    Box<dyn Iterator<Item = Result<String, Box<dyn Error>>>>,
-    Box<dyn FnMut(String) -> Result<(), Box<dyn Error>>>,
-> {
-    let (points, write): BoxedInOut = if args.service {
-        let (points, write) = service::backend();
-        (Box::new(points), Box::new(write))
-    } else {
-        let (points, write) = streamer::stdio();
-        (Box::new(points), Box::new(write))
+type BoxedStreamer = Streamer<BoxedPoints, BoxedWrite, Box<dyn Codec<Vec<f64>>>>;
+
+/// Reads the points from the standard input or a file and writes the models to the standard output or a file.
+fn run_io(args: &RunArgs) -> Result<SendInOut, Box<dyn Error>> {
+    let (_, write) = streamer::stdio();
+    let input = args.input.clone().unwrap_or_else(|| PathBuf::from("-"));
+    Ok((
+        Box::new(streamer::file_reader(input)?),
+        match &args.output {
+            Some(path) => streamer::file_writer(path.clone())?,
+            None => Box::new(write),
+        },
+    ))
+}
+
+/// Receives the points from websockets and dispatches the models to websockets.
+fn serve_io(args: &ListenArgs, frozen: Arc<AtomicBool>) -> Result<SendInOut, Box<dyn Error>> {
+    let (points, write) = service::backend_with_control(frozen, &args.host, args.port)?;
+    Ok((points, write))
+}
+
+/// Builds the streamer for the input format.
+/// Csv input starts with a header line only if it is read from a file or the standard input,
+/// the points sent by websocket clients have no header.
+fn get_streamer((points, write): BoxedInOut, args: &FitArgs, header: bool) -> BoxedStreamer {
+    let codec: Box<dyn Codec<Vec<f64>>> = match args.input_format {
+        InputFormat::Json => Box::new(JsonCodec),
+        InputFormat::Csv if header => Box::new(CsvCodec::with_header()),
+        InputFormat::Csv => Box::new(CsvCodec::new()),
+    };
+    let (write, assignments): (BoxedWrite, Option<BoxedWrite>) = match args.emit {
+        Emit::Model => (write, None),
+        Emit::Assignments => (Box::new(|_| Ok(())), Some(write)),
+        Emit::Both => {
+            let shared = Rc::new(RefCell::new(write));
+            let models = shared.clone();
+            (
+                Box::new(move |model| (models.borrow_mut())(model)),
+                Some(Box::new(move |assignment| {
+                    (shared.borrow_mut())(assignment)
+                })),
+            )
+        }
+    };
+    let streamer =
+        Streamer::new_with_codec(points, write, codec).emit_every(args.emit_every as usize);
+    match assignments {
+        Some(write) if args.emit == Emit::Both => streamer
+            .with_ball_ids()
+            .with_assignment_writer::<RealPoint, _>(write),
+        Some(write) => streamer.with_assignment_writer::<RealPoint, _>(write),
+        None => streamer,
+    }
+}
+
+type AlgoModel = (Algo<Vec<f64>>, Model<Vec<f64>>);
+
+fn get_algo_model(args: &FitArgs, frozen: Arc<AtomicBool>) -> Result<AlgoModel, String> {
+    let (dist, combine) = args.distance.functions();
+    let mut algo = Algo::new(dist, combine)
+        .with_point_validation(space::check_finite)
+        .with_freeze_flag(frozen);
+    if let Some(path) = &args.config {
+        algo = algo.with_params(read_config(path)?);
+    }
+    if let Some(max_balls) = args.max_balls {
+        algo = algo
+            .with_max_balls(max_balls)
+            .with_cap_policy(CapPolicy::MergeClosest);
+    }
+    #[cfg(feature = "rayon")]
+    {
+        algo = algo.with_parallel_decay();
+    }
+    let model = match &args.load {
+        Some(path) => Model::load_from_file(dist, path)
+            .map_err(|err| format!("cannot load the model from {}: {}", path.display(), err))?,
+        None => Model::new(dist),
+    };
+    Ok((algo, model))
+}
+
+/// Reads the thresholds of the algorithm from a json file.
+/// Unknown keys are reported but ignored, missing keys take their default value.
+fn read_config(path: &Path) -> Result<AlgoParams, String> {
+    let read = || -> Result<AlgoParams, Box<dyn Error>> {
+        let config: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let known = serde_json::to_value(AlgoParams::default())?;
+        if let (Some(config), Some(known)) = (config.as_object(), known.as_object()) {
+            for key in config.keys().filter(|key| !known.contains_key(*key)) {
+                eprintln!("warning: unknown configuration key {}.", key);
+            }
+        }
+        Ok(serde_json::from_value(config)?)
     };
-    let streamer = Streamer::new(points, write);
-    streamer
+    read().map_err(|err| {
+        format!(
+            "cannot read the configuration from {}: {}",
+            path.display(),
+            err
+        )
+    })
 }
 
-fn get_algo_model() -> (Algo<Vec<f64>>, Model<Vec<f64>>) {
-    let algo = Algo::new(space::euclid_dist, space::real_combine);
-    let model = Model::new(space::euclid_dist);
-    (algo, model)
+/// Loads the model and gets the ball closest to the point, e.g.
+/// `{"center":[1.0,2.0],"distance":0.5,"index":3,"radius":2.0,"weight":10.0}`
+/// where `index` is the index of the ball in the model and `distance` the normalized distance to the ball.
+fn predict(args: &PredictArgs) -> Result<Value, String> {
+    let (dist, _) = args.distance.functions();
+    let model = Model::load_from_file(dist, &args.load).map_err(|err| {
+        format!(
+            "cannot load the model from {}: {}",
+            args.load.display(),
+            err
+        )
+    })?;
+    let point: RealPoint = serde_json::from_str(&args.point)
+        .map_err(|err| format!("invalid point {}: {}", args.point, err))?;
+    let closest = match model.predict(&point) {
+        Neighborhood::Two(closest, _) | Neighborhood::One(closest) => closest,
+        Neighborhood::None => return Err(String::from("the model is empty.")),
+    };
+    let ball = closest.coord();
+    let index = model.iter_balls().position(|b| b.id() == ball.id());
+    let mut prediction = json!({
+        "index": index,
+        "center": ball.center(),
+        "radius": ball.radius(),
+        "weight": ball.weight(),
+        "distance": closest.dist(),
+    });
+    // the radius of the initial ball is infinite, it is omitted as in the models
+    if ball.radius().is_infinite() {
+        prediction.as_object_mut().unwrap().remove("radius");
+    }
+    Ok(prediction)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpStream;
+
+    use clap::Parser;
+
+    use super::*;
+
+    fn run_args(args: &[&str]) -> RunArgs {
+        match Args::parse_from(args).command() {
+            Command::Run(args) => args,
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    fn serve_args(args: &[&str]) -> ServeArgs {
+        match Args::parse_from(args).command() {
+            Command::Serve(args) => args,
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    /// Gets a port that no other test listens on.
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn test_port_arg() {
+        let port = free_port();
+        let args = serve_args(&["fluent_data", "--service", "--port", &port.to_string()]);
+        assert_eq!(port, args.listen.port);
+        let io = serve_io(&args.listen, Arc::new(AtomicBool::new(false))).unwrap();
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_ok());
+        drop(io);
+    }
+
+    #[test]
+    fn test_load_arg() {
+        let path = std::env::temp_dir().join("fluent_data_test_load_arg.json");
+        let (algo, mut model) = get_algo_model(
+            &run_args(&["fluent_data"]).fit,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        algo.fit(&mut model, vec![1., 1.]);
+        algo.fit(&mut model, vec![2., 2.]);
+        model.save_to_file(&path).unwrap();
+        let args = run_args(&["fluent_data", "--load", path.to_str().unwrap()]).fit;
+        let (_algo, loaded) = get_algo_model(&args, Arc::new(AtomicBool::new(false))).unwrap();
+        assert_eq!(model.to_json()["balls"], loaded.to_json()["balls"]);
+        std::fs::remove_file(&path).unwrap();
+        let err = get_algo_model(&args, Arc::new(AtomicBool::new(false)))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("cannot load the model from"));
+    }
+
+    #[test]
+    fn test_config_arg() {
+        let path = std::env::temp_dir().join("fluent_data_test_config_arg.json");
+        let config =
+            r#"{"intra_threshold":9.0,"extra_threshold":16.0,"decay_factor":0.9,"unknown":1}"#;
+        std::fs::write(&path, config).unwrap();
+        let args = run_args(&["fluent_data", "--config", path.to_str().unwrap()]).fit;
+        let (algo, _model) = get_algo_model(&args, Arc::new(AtomicBool::new(false))).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = AlgoParams {
+            intra_threshold: 9.,
+            extra_threshold: 16.,
+            decay_factor: 0.9,
+            ..AlgoParams::default()
+        };
+        assert_eq!(&expected, algo.params());
+        let err = get_algo_model(&args, Arc::new(AtomicBool::new(false)))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("cannot read the configuration"));
+    }
+
+    #[test]
+    fn test_input_arg() {
+        let path = std::env::temp_dir().join("fluent_data_test_missing_input.json");
+        let args = run_args(&["fluent_data", "--input", path.to_str().unwrap()]);
+        let err = run_io(&args).err().unwrap();
+        assert!(err.to_string().starts_with("cannot open"));
+        assert!(Args::try_parse_from(["fluent_data", "--service", "--input", "-"]).is_err());
+    }
+
+    #[test]
+    fn test_csv_input_arg() {
+        let args = run_args(&["fluent_data", "--input-format", "csv"]).fit;
+        for (header, expected) in [(true, 1), (false, 2)] {
+            let (algo, mut model) =
+                get_algo_model(&args, Arc::new(AtomicBool::new(false))).unwrap();
+            let points: BoxedPoints = Box::new(["1,1", "2,2"].into_iter().map(|p| Ok(p.into())));
+            let models = Rc::new(RefCell::new(0));
+            let count = models.clone();
+            let write: BoxedWrite = Box::new(move |_| {
+                *count.borrow_mut() += 1;
+                Ok(())
+            });
+            let streamer = get_streamer((points, write), &args, header);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+            assert_eq!(expected, *models.borrow());
+        }
+    }
+
+    #[test]
+    fn test_host_arg() {
+        let port = free_port();
+        let args = serve_args(&[
+            "fluent_data",
+            "--service",
+            "--host",
+            "0.0.0.0",
+            "--port",
+            &port.to_string(),
+        ]);
+        assert_eq!("0.0.0.0", args.listen.host);
+        let io = serve_io(&args.listen, Arc::new(AtomicBool::new(false))).unwrap();
+        let address = TcpStream::connect(("127.0.0.1", port))
+            .unwrap()
+            .peer_addr()
+            .unwrap();
+        assert_eq!(port, address.port());
+        drop(io);
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let args = serve_args(&["fluent_data", "serve", "--port", "9004", "--max-balls", "3"]);
+        assert_eq!(9004, args.listen.port);
+        assert_eq!(Some(3), args.fit.max_balls);
+        let args = run_args(&["fluent_data", "run", "--input", "-", "--emit-every", "10"]);
+        assert!(args.input.is_some());
+        assert_eq!(10, args.fit.emit_every);
+        let args = Args::parse_from(["fluent_data", "predict", "--load", "model.json", "[1.0]"]);
+        match args.command() {
+            Command::Predict(args) => assert_eq!("[1.0]", args.point),
+            command => panic!("unexpected command {:?}", command),
+        }
+        for args in [
+            &["fluent_data", "serve", "--input", "-"][..],
+            &["fluent_data", "--max-balls", "3", "run"],
+            &["fluent_data", "predict", "[1.0]"],
+        ] {
+            assert!(Args::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_predict() {
+        use fluent_data::model::Ball;
+
+        let path = std::env::temp_dir().join("fluent_data_test_predict.json");
+        let data = vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![10.], 4., 2.)];
+        Model::load(space::euclid_dist, data)
+            .save_to_file(&path)
+            .unwrap();
+        let predict_with = |point: &str| {
+            let args = [
+                "fluent_data",
+                "predict",
+                "--load",
+                path.to_str().unwrap(),
+                point,
+            ];
+            match Args::parse_from(args).command() {
+                Command::Predict(args) => predict(&args),
+                command => panic!("unexpected command {:?}", command),
+            }
+        };
+        let expected = json!({
+            "index": 1,
+            "center": [10.0],
+            "radius": 2.0,
+            "weight": 2.0,
+            "distance": 1.0,
+        });
+        assert_eq!(expected, predict_with("[8.0]").unwrap());
+        assert!(predict_with("[8.0")
+            .err()
+            .unwrap()
+            .starts_with("invalid point"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(predict_with("[8.0]")
+            .err()
+            .unwrap()
+            .starts_with("cannot load the model from"));
+    }
+
+    #[test]
+    fn test_predict_one_point() {
+        let path = std::env::temp_dir().join("fluent_data_test_predict_one_point.json");
+        let (algo, mut model) = get_algo_model(
+            &run_args(&["fluent_data"]).fit,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        algo.fit(&mut model, vec![1.]);
+        model.save_to_file(&path).unwrap();
+        let args = [
+            "fluent_data",
+            "predict",
+            "--load",
+            path.to_str().unwrap(),
+            "[3.0]",
+        ];
+        let prediction = match Args::parse_from(args).command() {
+            Command::Predict(args) => predict(&args).unwrap(),
+            command => panic!("unexpected command {:?}", command),
+        };
+        std::fs::remove_file(&path).unwrap();
+        let expected = json!({
+            "index": 0,
+            "center": [1.0],
+            "weight": 0.0,
+            "distance": 0.0,
+        });
+        assert_eq!(expected, prediction);
+    }
 }
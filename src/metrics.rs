@@ -0,0 +1,146 @@
+//! The operational metrics of the [service](crate::service), exposed at `GET /metrics`
+//! in the Prometheus text exposition format, see [ServiceConfig::with_metrics](crate::service::ServiceConfig::with_metrics).
+//!
+//! The [Metrics] are shared by the service, which counts the broadcast models and the connected clients,
+//! and by the [Streamer](crate::Streamer), which counts the received points and the parse failures
+//! and reports the ball count and the fit latency, see [Streamer::with_metrics](crate::Streamer::with_metrics).
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The upper bounds in seconds of the buckets of the fit latency histogram.
+const LATENCY_BUCKETS: [f64; 7] = [1E-6, 1E-5, 1E-4, 1E-3, 1E-2, 1E-1, 1.];
+
+/// The counters and gauges of the service, shared with other threads.
+/// Clones share the same counters.
+/// ```
+/// use fluent_data::{metrics::Metrics, streamer, Streamer};
+///
+/// let metrics = Metrics::new();
+/// let (points, write) = streamer::stdio();
+/// let streamer = Streamer::new(points, write).with_metrics(metrics.clone());
+/// assert_eq!(0, metrics.points_received());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    points_received: AtomicU64,
+    parse_failures: AtomicU64,
+    models_broadcast: AtomicU64,
+    balls: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_nanos: AtomicU64,
+}
+
+impl Metrics {
+    /// Builds new counters set to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of points read by the streamer.
+    pub fn points_received(&self) -> u64 {
+        self.inner.points_received.load(Ordering::Relaxed)
+    }
+
+    /// The number of points that could not be decoded or were rejected.
+    pub fn parse_failures(&self) -> u64 {
+        self.inner.parse_failures.load(Ordering::Relaxed)
+    }
+
+    /// The number of models dispatched to the model consumers.
+    pub fn models_broadcast(&self) -> u64 {
+        self.inner.models_broadcast.load(Ordering::Relaxed)
+    }
+
+    /// The number of balls of the model after the last fit.
+    pub fn balls(&self) -> u64 {
+        self.inner.balls.load(Ordering::Relaxed)
+    }
+
+    /// The number of points fitted, i.e. the number of observations of the fit latency.
+    pub fn fits(&self) -> u64 {
+        self.inner.latency_count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_point(&self) {
+        self.inner.points_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_parse_failure(&self) {
+        self.inner.parse_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_broadcast(&self) {
+        self.inner.models_broadcast.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the duration of a fit and the number of balls of the fitted model.
+    pub(crate) fn record_fit(&self, latency: Duration, balls: usize) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.inner.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .latency_sum_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        self.inner.balls.store(balls as u64, Ordering::Relaxed);
+    }
+
+    /// The upper bounds in seconds of the buckets of the fit latency histogram with their cumulated counts.
+    pub(crate) fn latency_buckets(&self) -> Vec<(f64, u64)> {
+        let mut cumulated = 0;
+        LATENCY_BUCKETS
+            .iter()
+            .zip(&self.inner.latency_buckets)
+            .map(|(&le, count)| {
+                cumulated += count.load(Ordering::Relaxed);
+                (le, cumulated)
+            })
+            .collect()
+    }
+
+    /// The total duration of the fits in seconds.
+    pub(crate) fn latency_sum(&self) -> f64 {
+        self.inner.latency_sum_nanos.load(Ordering::Relaxed) as f64 / 1E9
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn test_record() {
+        let metrics = Metrics::new();
+        metrics.record_point();
+        metrics.record_point();
+        metrics.record_parse_failure();
+        metrics.record_broadcast();
+        metrics.record_fit(Duration::from_micros(50), 3);
+        metrics.record_fit(Duration::from_secs(2), 4);
+        assert_eq!(2, metrics.points_received());
+        assert_eq!(1, metrics.parse_failures());
+        assert_eq!(1, metrics.models_broadcast());
+        assert_eq!(4, metrics.balls());
+        assert_eq!(2, metrics.fits());
+        let buckets = metrics.latency_buckets();
+        assert_eq!((1E-5, 0), buckets[1]);
+        assert_eq!((1E-4, 1), buckets[2]);
+        assert_eq!((1., 1), buckets[6]);
+        assert_eq!(2.00005, metrics.latency_sum());
+    }
+}
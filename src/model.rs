@@ -1,21 +1,94 @@
 //! The [Model] struct represents the set of balls model.
 //!
 //! The model can be loaded with existing balls by the [Model::load] method.
+//! It can also be saved to and loaded from a json file
+//! by the [Model::save_to_file] and [Model::load_from_file] methods,
+//! or serialized to json with its neighborhood graph by the [Model::to_json] and [Model::from_json] methods.
+//! The [parse_snapshot] function reads the balls of a model in any of these json formats.
 //! It can also be used to predict the balls that most probably contains a given point
 //! by using the [Model::predict] method.
-use std::ops::Deref;
+//! The [Model::ball_covariance] method estimates the covariance of a ball from its recent points,
+//! kept if enabled by [Model::with_reservoirs], which reveals clusters that are not isotropic,
+//! and the [Model::downsample] method summarizes the model in fewer balls.
+//! The [Model::with_grid_index] method speeds up the fitting of models with many balls.
+//!
+//! A model is `Send + Sync` when its points are, thus it can be fitted by a thread
+//! while others use it for predictions, e.g. through an `Arc<RwLock<Model>>`.
+#[cfg(feature = "bincode")]
+use std::io::{Read, Write};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    error::Error,
+    fs,
+    ops::Deref,
+    path::Path,
+    sync::Arc,
+};
+
+#[cfg(feature = "bincode")]
+use serde::Deserialize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
 
 use crate::{
-    graph::{Neighbor, Vertex},
-    neighborhood::{GetNeighborhood, Neighborhood},
+    graph::{IterEdges, Neighbor, Vertex},
+    index::{GridIndex, SpatialIndex},
+    neighborhood::{k_smallest, GetNeighborhood, NeighborDist, NeighborFinder, Neighborhood},
+    space::RealPoint,
+    streamer,
 };
 
+/// The initial state of the [Fnv] hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// The 64 bits FNV-1a hash function, which is stable unlike the hashers of the standard library.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
 /// A ball in the set of balls model.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug)]
 pub struct Ball<Point: PartialEq> {
     pub(crate) center: Point,
     pub(crate) radius: f64,
     pub(crate) weight: f64,
+    pub(crate) id: u64,
+}
+
+/// The binary representation of a ball, see [Model::to_bytes].
+/// Unlike the json models, which write the square root of the radius under the `radius` key,
+/// it holds the radius as it is stored, i.e. squared.
+#[cfg(feature = "bincode")]
+#[derive(Serialize, Deserialize)]
+struct WireBall<Center> {
+    center: Center,
+    radius_squared: f64,
+    weight: f64,
+}
+
+#[cfg(feature = "bincode")]
+impl<'a, Point: PartialEq> From<&'a Ball<Point>> for WireBall<&'a Point> {
+    fn from(ball: &'a Ball<Point>) -> Self {
+        Self {
+            center: &ball.center,
+            radius_squared: ball.radius,
+            weight: ball.weight,
+        }
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<Point: PartialEq> From<WireBall<Point>> for Ball<Point> {
+    fn from(ball: WireBall<Point>) -> Self {
+        Ball::new(ball.center, ball.radius_squared, ball.weight)
+    }
 }
 
 impl<Point: PartialEq> Ball<Point> {
@@ -25,6 +98,7 @@ impl<Point: PartialEq> Ball<Point> {
             center,
             radius,
             weight,
+            id: 0,
         }
     }
 
@@ -42,30 +116,87 @@ impl<Point: PartialEq> Ball<Point> {
     pub fn weight(&self) -> f64 {
         self.weight
     }
+
+    /// Ball id, which identifies the ball in its model until the ball is removed.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// Balls are equal when their center, radius and weight are equal, whatever their model id.
+impl<Point: PartialEq> PartialEq for Ball<Point> {
+    fn eq(&self, other: &Self) -> bool {
+        self.center == other.center && self.radius == other.radius && self.weight == other.weight
+    }
 }
 
 /// A graph node which represents a ball.
 pub(crate) type BallNode<Point> = Vertex<Ball<Point>>;
 
+/// A distance between two points, shared with the closures built from it.
+type SpaceDist<Point> = Arc<dyn Fn(&Point, &Point) -> f64 + Send + Sync>;
+
 /// A set of balls model.
 pub struct Model<Point: PartialEq> {
-    pub(crate) dist: Box<dyn Fn(&Point, &Ball<Point>) -> f64>,
+    pub(crate) dist: Arc<dyn Fn(&Point, &Ball<Point>) -> f64 + Send + Sync>,
+    /// The distance between two points the normalized distance is built from, see [Model::merge_from].
+    space_dist: SpaceDist<Point>,
     pub(crate) graph: Vec<BallNode<Point>>,
+    finder: Option<Box<dyn NeighborFinder<Point, Ball<Point>>>>,
+    index: Option<Box<dyn SpatialIndex<Point>>>,
+    next_id: u64,
+    reservoirs: HashMap<u64, VecDeque<Point>>,
+    reservoir_size: usize,
+    changes: Option<Changes>,
+}
+
+/// The changes of the model balls since they were last taken, see [Model::track_changes].
+pub(crate) struct Changes {
+    /// Ids of the balls created or updated.
+    pub(crate) upserted: BTreeSet<u64>,
+    /// Ids of the balls removed.
+    pub(crate) deleted: BTreeSet<u64>,
+    /// Factor by which the weight of the other balls has been multiplied.
+    pub(crate) decay: f64,
+}
+
+impl Default for Changes {
+    fn default() -> Self {
+        Self {
+            upserted: BTreeSet::new(),
+            deleted: BTreeSet::new(),
+            decay: 1.,
+        }
+    }
 }
 
 impl<Point: PartialEq + 'static> Model<Point> {
     /// Build a new model.
     pub fn new<Dist>(space_dist: Dist) -> Self
     where
-        Dist: Fn(&Point, &Point) -> f64 + 'static,
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
     {
+        let space_dist = Arc::new(space_dist);
+        let normalized = space_dist.clone();
         Self {
-            dist: Box::new(Model::normalize(space_dist)),
+            dist: Arc::new(Model::normalize(move |p1: &Point, p2: &Point| {
+                normalized(p1, p2)
+            })),
+            space_dist,
             graph: vec![],
+            finder: None,
+            index: None,
+            next_id: 0,
+            reservoirs: HashMap::new(),
+            reservoir_size: 0,
+            changes: None,
         }
     }
 
     /// Load an existing model.
+    /// The neighborhood graph is recomputed from the ball centers, which is quadratic in the number of balls
+    /// and may differ from the graph the model had when it was saved;
+    /// use [Model::load_with_neighbors] to restore a saved graph.
     /// ```
     /// use fluent_data::{Model, model::Ball, space};
     ///
@@ -80,19 +211,292 @@ impl<Point: PartialEq + 'static> Model<Point> {
     /// ```
     pub fn load<Dist>(space_dist: Dist, data: Vec<Ball<Point>>) -> Self
     where
-        Dist: Fn(&Point, &Point) -> f64 + 'static,
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
     {
         let mut model = Self::new(space_dist);
         for ball in data {
             model.add_ball(ball, vec![]);
         }
-        for vertex in model.graph.iter() {
-            let neighborhood = model
+        model.rebuild_neighbors();
+        model
+    }
+
+    /// Inserts the balls of another model, e.g. trained on another shard of the data,
+    /// with their recent points, then recomputes the neighborhood graph like [Model::load] does.
+    /// Balls are kept as is, use [Model::merge_from] or [Algo::merge](crate::Algo::merge)
+    /// to also merge the balls that are close enough.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let mut model = Model::load(space::euclid_dist, vec![Ball::new(vec![0.], 1., 2.)]);
+    ///     let other = Model::load(space::euclid_dist, vec![Ball::new(vec![9.], 1., 3.)]);
+    ///     model.merge(other);
+    ///     assert_eq!(2, model.iter_balls().count());
+    ///     assert_eq!(5., model.total_weight());
+    /// }
+    /// ```
+    pub fn merge(&mut self, mut other: Model<Point>) {
+        for vertex in other.graph.drain(..) {
+            let id = self.next_id;
+            self.next_id += 1;
+            let points = {
+                let mut ball = vertex.deref_data_mut();
+                let points = other.reservoirs.remove(&ball.id);
+                ball.id = id;
+                points
+            };
+            if let Some(points) = points.filter(|_| self.reservoir_size > 0) {
+                self.reservoirs.insert(id, points);
+            }
+            self.graph.push(vertex.clone());
+            self.touch(&vertex);
+        }
+        self.rebuild_neighbors();
+    }
+
+    /// Inserts the balls of another model like [Model::merge] does,
+    /// then merges each ball with its closest neighbor if they are close enough,
+    /// with the same criterion and the same combination of centers and radii as [Algo::merge](crate::Algo::merge),
+    /// which calls this method with the combination function and the merge threshold of the algorithm.
+    /// They are parameters because a model does not know the algorithm that fits it:
+    /// the threshold is [AlgoParams::merge_threshold](crate::algorithm::AlgoParams::merge_threshold),
+    /// 1 by default. The weights of merged balls add up. Returns the number of merged balls.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let mut model = Model::load(space::euclid_dist, vec![Ball::new(vec![0.], 1., 2.)]);
+    ///     let other = Model::load(
+    ///         space::euclid_dist,
+    ///         vec![Ball::new(vec![0.5], 1., 3.), Ball::new(vec![9.], 1., 1.)],
+    ///     );
+    ///     assert_eq!(1, model.merge_from(other, &space::real_combine, 1.));
+    ///     assert_eq!(2, model.iter_balls().count());
+    ///     assert_eq!(6., model.total_weight());
+    /// }
+    /// ```
+    pub fn merge_from<Combine>(
+        &mut self,
+        other: Model<Point>,
+        combine: &Combine,
+        merge_threshold: f64,
+    ) -> usize
+    where
+        Combine: Fn(&Point, f64, &Point, f64) -> Point + ?Sized,
+    {
+        self.merge(other);
+        let dist = self.space_dist.clone();
+        self.merge_close_balls(&*dist, combine, merge_threshold)
+    }
+
+    /// Summarizes the model in at most `max_balls` balls, e.g. for low-bandwidth clients.
+    /// The two balls with the closest centers, according to the distance of the model,
+    /// are merged into a ball that includes both of them until at most `max_balls` remain.
+    /// The centers are merged with the given combination function, as [Model::merge_from] does,
+    /// and the radius is computed like the radii of the model, i.e. as the square root of the distance.
+    /// The model is left unchanged.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![
+    ///         Ball::new(vec![0.], 1., 1.),
+    ///         Ball::new(vec![2.], 1., 3.),
+    ///         Ball::new(vec![10.], 1., 1.),
+    ///     ];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     let balls = model.downsample(2, &space::real_combine);
+    ///     assert_eq!(&vec![1.5], balls[0].center());
+    ///     assert_eq!(2.5, balls[0].radius());
+    ///     assert_eq!(4., balls[0].weight());
+    /// }
+    /// ```
+    pub fn downsample<Combine>(&self, max_balls: usize, combine: &Combine) -> Vec<Ball<Point>>
+    where
+        Point: Clone,
+        Combine: Fn(&Point, f64, &Point, f64) -> Point + ?Sized,
+    {
+        let mut balls: Vec<Option<Ball<Point>>> =
+            self.cloned_balls().into_iter().map(Some).collect();
+        // the closest other ball of each ball, only recomputed when it is merged
+        let mut closest: Vec<_> = (0..balls.len())
+            .map(|i| self.closest_ball(&balls, i))
+            .collect();
+        let mut count = balls.len();
+        while count > max_balls.max(1) {
+            let (i, j) = match closest
+                .iter()
+                .enumerate()
+                .filter_map(|(i, c)| c.map(|(j, d)| (i, j, d)))
+                .min_by(|(_, _, d1), (_, _, d2)| d1.total_cmp(d2))
+            {
+                Some((i, j, _)) => (i, j),
+                None => break,
+            };
+            let (first, second) = match (balls[i].take(), balls[j].take()) {
+                (Some(first), Some(second)) => (first, second),
+                _ => unreachable!("the closest balls are not merged yet."),
+            };
+            balls[i] = Some(self.bounding_ball(&first, &second, combine));
+            closest[j] = None;
+            count -= 1;
+            for k in 0..balls.len() {
+                closest[k] = match (&balls[k], closest[k]) {
+                    (None, _) => None,
+                    (Some(_), Some((c, _))) if k == i || c == i || c == j => {
+                        self.closest_ball(&balls, k)
+                    }
+                    (Some(ball), Some((c, d))) => {
+                        let merged = balls[i].as_ref().map(|b| &b.center);
+                        let d_merged = merged.map_or(d, |m| (self.space_dist)(&ball.center, m));
+                        if d_merged < d {
+                            Some((i, d_merged))
+                        } else {
+                            Some((c, d))
+                        }
+                    }
+                    (Some(_), None) => self.closest_ball(&balls, k),
+                };
+            }
+        }
+        balls.into_iter().flatten().collect()
+    }
+
+    /// Gets the index of the ball with the closest center to the center of the ball at the given index
+    /// and the distance between their centers, if any.
+    fn closest_ball(&self, balls: &[Option<Ball<Point>>], index: usize) -> Option<(usize, f64)> {
+        let center = &balls[index].as_ref()?.center;
+        balls
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != index)
+            .filter_map(|(j, ball)| Some((j, (self.space_dist)(center, &ball.as_ref()?.center))))
+            .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+    }
+
+    /// Builds the ball centered on the weighted center of two balls that includes both of them.
+    fn bounding_ball<Combine>(
+        &self,
+        first: &Ball<Point>,
+        second: &Ball<Point>,
+        combine: &Combine,
+    ) -> Ball<Point>
+    where
+        Combine: Fn(&Point, f64, &Point, f64) -> Point + ?Sized,
+    {
+        let weight = first.weight + second.weight;
+        let center = if weight > 0. {
+            combine(&first.center, first.weight, &second.center, second.weight)
+        } else {
+            combine(&first.center, 1., &second.center, 1.)
+        };
+        let reach = |ball: &Ball<Point>| {
+            (self.space_dist)(&center, &ball.center).sqrt() + ball.radius.sqrt()
+        };
+        let radius = f64::max(reach(first), reach(second));
+        Ball::new(center, radius * radius, weight)
+    }
+
+    /// Merges each ball with its closest neighbor if they are close enough,
+    /// then removes the merged balls and recomputes the neighborhood graph.
+    /// Returns the number of merged balls.
+    pub(crate) fn merge_close_balls<Dist, Combine>(
+        &mut self,
+        dist: &Dist,
+        combine: &Combine,
+        merge_threshold: f64,
+    ) -> usize
+    where
+        Dist: Fn(&Point, &Point) -> f64 + ?Sized,
+        Combine: Fn(&Point, f64, &Point, f64) -> Point + ?Sized,
+    {
+        let mut merged = HashSet::new();
+        for vertex in self.graph.clone() {
+            if merged.contains(&vertex.deref_data().id) {
+                continue;
+            }
+            let neighbor = match vertex.iter_neighbors().next() {
+                Some(neighbor) => neighbor,
+                None => continue,
+            };
+            let id = neighbor.deref_data().id;
+            let unfitted = vertex.deref_data().radius.is_infinite()
+                || neighbor.deref_data().radius.is_infinite();
+            if unfitted || merged.contains(&id) {
+                continue;
+            }
+            let (should_merge, d) = Self::should_merge(&vertex, &neighbor, dist, merge_threshold);
+            if should_merge {
+                Self::merge_balls(&vertex, &neighbor, d, combine);
+                self.touch(&vertex);
+                self.merge_reservoirs(&vertex, &neighbor);
+                merged.insert(id);
+            }
+        }
+        if !merged.is_empty() {
+            self.retain_balls(|v| !merged.contains(&v.deref_data().id));
+            self.rebuild_neighbors();
+        }
+        merged.len()
+    }
+
+    /// Decides if two balls are close enough to merge and returns the squared distance between their centers.
+    /// The squared distance is compared to the sum of the squared radii (the radii are stored squared),
+    /// i.e. `sqrt(d) < sqrt(merge_threshold * (r1 + r2))`.
+    pub(crate) fn should_merge<Dist>(
+        first: &BallNode<Point>,
+        second: &BallNode<Point>,
+        dist: &Dist,
+        merge_threshold: f64,
+    ) -> (bool, f64)
+    where
+        Dist: Fn(&Point, &Point) -> f64 + ?Sized,
+    {
+        let current_data = first.deref_data();
+        let neighbor_data = second.deref_data();
+        let d = dist(&current_data.center, &neighbor_data.center);
+        let should_merge = d < (current_data.radius + neighbor_data.radius) * merge_threshold;
+        (should_merge, d)
+    }
+
+    /// Merge two balls.
+    /// The new center is the weighted center of the ball centers
+    /// and the new radius is the weighted average of the balls variances.
+    pub(crate) fn merge_balls<Combine>(
+        vertex: &BallNode<Point>,
+        neighbor: &BallNode<Point>,
+        d: f64,
+        combine: &Combine,
+    ) where
+        Combine: Fn(&Point, f64, &Point, f64) -> Point + ?Sized,
+    {
+        let mut current_data = vertex.deref_data_mut();
+        let mut neighbor_data = neighbor.deref_data_mut();
+        current_data.center = combine(
+            &current_data.center,
+            current_data.weight,
+            &neighbor_data.center,
+            neighbor_data.weight,
+        );
+        current_data.radius = d
+            + (current_data.radius * current_data.weight
+                + neighbor_data.radius * neighbor_data.weight)
+                / (current_data.weight + neighbor_data.weight);
+        current_data.weight += neighbor_data.weight;
+        neighbor_data.weight = 0.;
+    }
+
+    /// Recomputes the neighborhood graph from the ball centers, which is quadratic in the number of balls.
+    pub(crate) fn rebuild_neighbors(&self) {
+        for vertex in self.graph.iter() {
+            let id = vertex.deref_data().id;
+            let neighborhood = self
                 .graph
                 .iter()
-                .filter(|v| v.ne(&vertex))
+                .filter(|v| v.deref_data().id != id)
                 .get_neighborhood(&vertex.deref_data().center, |v1, v2| {
-                    (model.dist)(v1, &v2.deref_data())
+                    (self.dist)(v1, &v2.deref_data())
                 });
             let neighbors = {
                 let mut neighbors = vec![];
@@ -110,7 +514,390 @@ impl<Point: PartialEq + 'static> Model<Point> {
             };
             vertex.set_neighbors(neighbors.iter().map(|v| v.as_neighbor()).collect());
         }
-        model
+    }
+
+    /// Saves the model to a json file.
+    /// The file format is the same as the models produced by the [Streamer](crate::Streamer).
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>>
+    where
+        Point: Serialize,
+    {
+        let balls = streamer::serialize_model(self);
+        fs::write(path, serde_json::to_string(&balls)?)?;
+        Ok(())
+    }
+
+    /// Loads a model from a json file, e.g. a file written by [Model::save_to_file]
+    /// or a model produced by the [Streamer](crate::Streamer), see [parse_snapshot].
+    /// The neighborhood graph is restored as it was serialized if the file was written by [Model::to_json].
+    /// ```no_run
+    /// use fluent_data::{Model, space};
+    ///
+    /// fn main() {
+    ///     let model = Model::load_from_file(space::euclid_dist, "model.json").unwrap();
+    /// }
+    /// ```
+    pub fn load_from_file<Dist, P>(space_dist: Dist, path: P) -> Result<Self, Box<dyn Error>>
+    where
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
+        P: AsRef<Path>,
+        Point: DeserializeOwned,
+    {
+        let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if value.get("neighbors").is_some() {
+            return Self::from_json(space_dist, value);
+        }
+        Ok(Self::load(space_dist, snapshot_balls(value)?))
+    }
+
+    /// Serializes the model to json.
+    /// The `balls` array is in the same format as the models produced by the [Streamer](crate::Streamer),
+    /// the `neighbors` array contains the neighbors of each ball as indices in the `balls` array.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![4.], 9., 1.), Ball::new(vec![5.], 4., 2.)];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     let json = model.to_json();
+    ///     assert_eq!(
+    ///         r#"{"balls":[{"center":[4.0],"radius":3.0,"weight":1.0},{"center":[5.0],"radius":2.0,"weight":2.0}],"neighbors":[[1],[0]]}"#,
+    ///         json.to_string()
+    ///     );
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value
+    where
+        Point: Serialize,
+    {
+        json!({
+            "balls": streamer::serialize_model(self),
+            "neighbors": self.neighbor_indices(),
+        })
+    }
+
+    /// Loads a model serialized by [Model::to_json].
+    /// Unlike [Model::load], the neighborhood graph is restored as it was serialized.
+    pub fn from_json<Dist>(space_dist: Dist, mut value: Value) -> Result<Self, Box<dyn Error>>
+    where
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
+        Point: DeserializeOwned,
+    {
+        let mut take = |key| value.get_mut(key).map(Value::take).unwrap_or_default();
+        let balls = streamer::deserialize_balls(take("balls"))?;
+        let neighbors: Vec<Vec<usize>> = serde_json::from_value(take("neighbors"))?;
+        if balls.len() != neighbors.len() {
+            return Err("balls and neighbors lengths differ".into());
+        }
+        Self::load_with_neighbors(space_dist, balls.into_iter().zip(neighbors).collect())
+    }
+
+    /// Loads a model from balls and the indices of their neighbors,
+    /// e.g. as exported by [Model::export_balls_with_neighbors].
+    /// Unlike [Model::load], the neighborhood graph is restored as it was exported.
+    /// Fails if a neighbor index is out of range.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// let data = vec![
+    ///     (Ball::new(vec![4.], 3., 1.), vec![1]),
+    ///     (Ball::new(vec![5.], 2., 2.), vec![0]),
+    /// ];
+    /// let model = Model::load_with_neighbors(space::euclid_dist, data.clone()).unwrap();
+    /// assert_eq!(data, model.export_balls_with_neighbors());
+    /// ```
+    pub fn load_with_neighbors<Dist>(
+        space_dist: Dist,
+        data: Vec<(Ball<Point>, Vec<usize>)>,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
+    {
+        let mut model = Self::new(space_dist);
+        let (balls, neighbors): (Vec<_>, Vec<_>) = data.into_iter().unzip();
+        for ball in balls {
+            model.add_ball(ball, vec![]);
+        }
+        for (vertex, indices) in model.graph.iter().zip(neighbors) {
+            let neighbors = indices
+                .into_iter()
+                .map(|i| model.graph.get(i).map(|v| v.as_neighbor()))
+                .collect::<Option<Vec<_>>>()
+                .ok_or("neighbor index out of range")?;
+            vertex.set_neighbors(neighbors);
+        }
+        Ok(model)
+    }
+
+    /// Exports the balls with the indices of their neighbors,
+    /// which can be loaded by [Model::load_with_neighbors].
+    pub fn export_balls_with_neighbors(&self) -> Vec<(Ball<Point>, Vec<usize>)>
+    where
+        Point: Clone,
+    {
+        self.iter_balls()
+            .map(|ball| ball.clone())
+            .zip(self.neighbor_indices())
+            .collect()
+    }
+
+    /// Writes the neighborhood graph in the GraphViz DOT language, e.g. to debug the clustering.
+    /// Each ball is a node labeled with its center and weight, and each neighbor relation is an edge,
+    /// written once even if both balls are neighbors of each other.
+    /// ```
+    /// use fluent_data::{model::Ball, space, Model};
+    ///
+    /// let data = vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![2.], 1., 3.)];
+    /// let model = Model::load(space::euclid_dist, data);
+    /// let dot = model.to_dot();
+    /// assert!(dot.starts_with("graph model {"));
+    /// assert!(dot.contains("0 [label=\"[0.0]\\nweight: 1\"];"));
+    /// assert!(dot.contains("0 -- 1;"));
+    /// ```
+    pub fn to_dot(&self) -> String
+    where
+        Point: Serialize,
+    {
+        let mut dot = String::from("graph model {\n");
+        for (i, ball) in self.iter_balls().enumerate() {
+            let center = serde_json::to_string(&ball.center).unwrap_or_default();
+            dot.push_str(&format!(
+                "    {} [label=\"{}\\nweight: {}\"];\n",
+                i,
+                center.replace('\\', "\\\\").replace('"', "\\\""),
+                ball.weight
+            ));
+        }
+        for (i, j) in self.edges() {
+            dot.push_str(&format!("    {} -- {};\n", i, j));
+        }
+        dot.push('}');
+        dot
+    }
+
+    /// Groups the balls which are connected by neighbor relations, whatever their direction,
+    /// e.g. to check whether the model has fragmented into clusters with no neighbor relation between them.
+    /// Each group holds the indices of its balls in increasing order and groups are ordered by their first index.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// let data = vec![
+    ///     (Ball::new(vec![0.], 1., 1.), vec![1]),
+    ///     (Ball::new(vec![1.], 1., 1.), vec![]),
+    ///     (Ball::new(vec![9.], 1., 1.), vec![]),
+    /// ];
+    /// let model = Model::load_with_neighbors(space::euclid_dist, data).unwrap();
+    /// assert_eq!(vec![vec![0, 1], vec![2]], model.connected_components());
+    /// ```
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = vec![vec![]; self.graph.len()];
+        for (i, j) in self.edges() {
+            adjacency[i].push(j);
+            adjacency[j].push(i);
+        }
+        let mut visited = vec![false; self.graph.len()];
+        let mut components = vec![];
+        for start in 0..self.graph.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut component = vec![];
+            let mut queue = VecDeque::from([start]);
+            while let Some(i) = queue.pop_front() {
+                component.push(i);
+                for &j in &adjacency[i] {
+                    if !visited[j] {
+                        visited[j] = true;
+                        queue.push_back(j);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+
+    /// Gets the balls reachable from the ball at the given index through neighbor relations,
+    /// as indices in the model in breadth-first order, starting with the given index.
+    /// Unlike [Model::connected_components], neighbor relations are followed in their direction only.
+    /// The result is empty if the index is out of range.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// let data = vec![
+    ///     (Ball::new(vec![0.], 1., 1.), vec![1, 2]),
+    ///     (Ball::new(vec![1.], 1., 1.), vec![]),
+    ///     (Ball::new(vec![2.], 1., 1.), vec![3]),
+    ///     (Ball::new(vec![3.], 1., 1.), vec![]),
+    /// ];
+    /// let model = Model::load_with_neighbors(space::euclid_dist, data).unwrap();
+    /// assert_eq!(vec![0, 1, 2, 3], model.bfs_from(0));
+    /// assert_eq!(vec![2, 3], model.bfs_from(2));
+    /// ```
+    pub fn bfs_from(&self, start: usize) -> Vec<usize> {
+        let start = match self.graph.get(start) {
+            Some(vertex) => vertex,
+            None => return vec![],
+        };
+        let indices = self.indices();
+        start
+            .bfs()
+            .iter()
+            .filter_map(|v| indices.get(&v.deref_data().id).copied())
+            .collect()
+    }
+
+    /// Gets the neighbor relations as pairs of indices in the model, the lowest index first, each pair once.
+    pub(crate) fn edges(&self) -> BTreeSet<(usize, usize)> {
+        let indices = self.indices();
+        self.graph
+            .iter_edges()
+            .filter_map(|(vertex, neighbor)| {
+                let i = indices[&vertex.deref_data().id];
+                let j = *indices.get(&neighbor.deref_data().id)?;
+                Some((i.min(j), i.max(j)))
+            })
+            .collect()
+    }
+
+    /// Gets the index in the model of each ball by its id.
+    fn indices(&self) -> HashMap<u64, usize> {
+        self.graph
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.deref_data().id, i))
+            .collect()
+    }
+
+    /// Gets the neighbors of each ball as indices in the model.
+    pub(crate) fn neighbor_indices(&self) -> Vec<Vec<usize>> {
+        let indices = self.indices();
+        self.graph
+            .iter()
+            .map(|vertex| {
+                vertex
+                    .iter_neighbors()
+                    .filter_map(|n| indices.get(&n.deref_data().id).copied())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Computes a digest of the balls, i.e. their ids, centers, radii and weights,
+    /// and of the neighborhood graph of the model, e.g. for clients to detect that the model changed.
+    /// Models with the same balls and the same graph have the same checksum,
+    /// which is stable across runs and platforms.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// algo.fit(&mut model, vec![1.]);
+    /// let checksum = model.checksum();
+    /// algo.fit(&mut model, vec![2.]);
+    /// assert_ne!(checksum, model.checksum());
+    /// ```
+    pub fn checksum(&self) -> u64
+    where
+        Point: Serialize,
+    {
+        let mut hasher = Fnv(FNV_OFFSET_BASIS);
+        for vertex in self.graph.iter() {
+            let ball = vertex.deref_data();
+            hasher.write(&ball.id.to_le_bytes());
+            hasher.write(&serde_json::to_vec(&ball.center).unwrap_or_default());
+            hasher.write(&ball.radius.to_bits().to_le_bytes());
+            hasher.write(&ball.weight.to_bits().to_le_bytes());
+            for neighbor in vertex.iter_neighbors() {
+                hasher.write(&neighbor.deref_data().id.to_le_bytes());
+            }
+            hasher.write(&[0xff]);
+        }
+        hasher.0
+    }
+
+    /// Serializes the model balls to a compact binary representation.
+    /// Unlike json, the representation is exact and can be restored by [Model::from_bytes].
+    #[cfg(feature = "bincode")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error>
+    where
+        Point: Serialize,
+    {
+        let balls: Vec<_> = self.iter_balls().collect();
+        let balls: Vec<WireBall<&Point>> = balls.iter().map(|b| WireBall::from(&**b)).collect();
+        bincode::serialize(&balls)
+    }
+
+    /// Loads a model from its binary representation produced by [Model::to_bytes].
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![4.], 3., 1.), Ball::new(vec![5.], 2., 2.)];
+    ///     let model = Model::load(space::euclid_dist, data.clone());
+    ///     let bytes = model.to_bytes().unwrap();
+    ///     let model = Model::from_bytes(space::euclid_dist, &bytes).unwrap();
+    ///     assert_eq!(data[1], *model.iter_balls().nth(1).unwrap());
+    /// }
+    /// ```
+    #[cfg(feature = "bincode")]
+    pub fn from_bytes<Dist>(space_dist: Dist, bytes: &[u8]) -> Result<Self, bincode::Error>
+    where
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
+        Point: DeserializeOwned,
+    {
+        let data: Vec<WireBall<Point>> = bincode::deserialize(bytes)?;
+        Ok(Self::load(
+            space_dist,
+            data.into_iter().map(Ball::from).collect(),
+        ))
+    }
+
+    /// Writes a binary snapshot of the model, including its neighborhood graph.
+    /// This is cheaper than json for checkpointing large models.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![4.], 3., 1.), Ball::new(vec![5.], 2., 2.)];
+    ///     let model = Model::load(space::euclid_dist, data.clone());
+    ///     let mut snapshot = vec![];
+    ///     model.write_snapshot(&mut snapshot).unwrap();
+    ///     let model = Model::read_snapshot(space::euclid_dist, &snapshot[..]).unwrap();
+    ///     assert_eq!(data[0], *model.iter_balls().next().unwrap());
+    /// }
+    /// ```
+    #[cfg(feature = "bincode")]
+    pub fn write_snapshot<W: Write>(&self, w: W) -> Result<(), Box<dyn Error>>
+    where
+        Point: Serialize,
+    {
+        let balls: Vec<_> = self.iter_balls().collect();
+        let snapshot: Vec<(WireBall<&Point>, Vec<usize>)> = balls
+            .iter()
+            .map(|b| WireBall::from(&**b))
+            .zip(self.neighbor_indices())
+            .collect();
+        bincode::serialize_into(w, &snapshot)?;
+        Ok(())
+    }
+
+    /// Reads a binary snapshot written by [Model::write_snapshot].
+    /// The neighborhood graph is restored as it was written.
+    #[cfg(feature = "bincode")]
+    pub fn read_snapshot<Dist, R: Read>(space_dist: Dist, r: R) -> Result<Self, Box<dyn Error>>
+    where
+        Dist: Fn(&Point, &Point) -> f64 + Send + Sync + 'static,
+        Point: DeserializeOwned,
+    {
+        let snapshot: Vec<(WireBall<Point>, Vec<usize>)> = bincode::deserialize_from(r)?;
+        let snapshot = snapshot
+            .into_iter()
+            .map(|(ball, neighbors)| (Ball::from(ball), neighbors))
+            .collect();
+        Self::load_with_neighbors(space_dist, snapshot)
     }
 
     /// Normalize the given distance function by dividing by the radius.
@@ -122,7 +909,11 @@ impl<Point: PartialEq + 'static> Model<Point> {
     }
 
     /// Get the vertices associated to balls which the given point most probably belongs to.
+    /// The balls are shortlisted by the spatial index if any, see [Model::with_grid_index].
     pub(crate) fn get_neighborhood(&self, point: &Point) -> Vec<BallNode<Point>> {
+        if let Some(index) = &self.index {
+            return index.get_neighborhood(point, &*self.dist);
+        }
         let mut neighbors = vec![];
         let neighborhood = self
             .graph
@@ -150,48 +941,592 @@ impl<Point: PartialEq + 'static> Model<Point> {
         ball: Ball<Point>,
         neighbors: Vec<Neighbor<Ball<Point>>>,
     ) -> BallNode<Point> {
+        let ball = Ball {
+            id: self.next_id,
+            ..ball
+        };
+        self.next_id += 1;
         let vertex = Vertex::new(ball);
         vertex.set_neighbors(neighbors);
         self.graph.push(vertex.clone());
+        self.touch(&vertex);
         vertex
     }
 
-    /// Gets an iterator over the balls of this model.
-    pub fn iter_balls(&self) -> impl Iterator<Item = impl Deref<Target = Ball<Point>> + '_> {
-        self.graph.iter().map(|v| v.deref_data())
+    /// Starts recording the changes of the model balls, if not already started.
+    /// Changes are not recorded by default because nobody would collect them.
+    pub(crate) fn track_changes(&mut self) {
+        self.changes.get_or_insert_with(Changes::default);
     }
 
-    /// Gets the balls that most probably include the given point.
-    /// ```
-    /// use fluent_data::{Model, model::Ball, space, neighborhood::{GetNeighborhood, Neighborhood}};
-    ///
-    /// fn main() {
-    ///     let data = vec![
-    ///         Ball::new(vec![4.], 3., 1.),
-    ///         Ball::new(vec![5.], 2., 2.),
-    ///         Ball::new(vec![3.], 3., 3.),
-    ///     ];
-    ///     let model = Model::load(space::euclid_dist, data.clone());
-    ///     let neighborhood = model.predict(&vec![6.]);
-    ///     if let Neighborhood::Two(n1, n2) = neighborhood {
-    ///         assert_eq!(&data[1], n1.coord());
-    ///         assert_eq!(1./2., n1.dist());
-    ///         assert_eq!(&data[0], n2.coord());
-    ///         assert_eq!(4./3., n2.dist());
-    ///     } else {
-    ///         panic!()
-    ///     }
-    /// }
+    /// Takes the changes recorded since the last call and starts recording anew.
+    /// Returns `None` if changes are not recorded.
+    pub(crate) fn take_changes(&mut self) -> Option<Changes> {
+        self.changes.as_mut().map(std::mem::take)
+    }
+
+    /// Records that the ball has been created or updated.
+    pub(crate) fn touch(&mut self, vertex: &BallNode<Point>) {
+        if let Some(index) = self.index.as_mut() {
+            index.insert(vertex);
+        }
+        if let Some(changes) = self.changes.as_mut() {
+            changes.upserted.insert(vertex.deref_data().id);
+        }
+    }
+
+    /// Records that the weight of all balls but the touched ones has been multiplied by `factor`.
+    pub(crate) fn record_decay(&mut self, factor: f64) {
+        if let Some(changes) = self.changes.as_mut() {
+            changes.decay *= factor;
+        }
+    }
+
+    /// Keeps the given point among the recent points of the ball, if the reservoirs are enabled.
+    /// The oldest point is dropped when the reservoir is full.
+    pub(crate) fn remember(&mut self, vertex: &BallNode<Point>, point: Point) {
+        if self.reservoir_size == 0 {
+            return;
+        }
+        let id = vertex.deref_data().id;
+        let reservoir = self.reservoirs.entry(id).or_default();
+        if reservoir.len() == self.reservoir_size {
+            reservoir.pop_front();
+        }
+        reservoir.push_back(point);
+    }
+
+    /// Moves the recent points of a ball merged into another one.
+    pub(crate) fn merge_reservoirs(&mut self, into: &BallNode<Point>, from: &BallNode<Point>) {
+        let from_id = from.deref_data().id;
+        if let Some(points) = self.reservoirs.remove(&from_id) {
+            for point in points {
+                self.remember(into, point);
+            }
+        }
+    }
+
+    /// Removes the balls that do not satisfy the predicate, with their recent points.
+    pub(crate) fn retain_balls<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&BallNode<Point>) -> bool,
+    {
+        let reservoirs = &mut self.reservoirs;
+        let changes = &mut self.changes;
+        let index = &mut self.index;
+        self.graph.retain(|v| {
+            let kept = keep(v);
+            if !kept {
+                let id = v.deref_data().id;
+                reservoirs.remove(&id);
+                if let Some(index) = index.as_mut() {
+                    index.remove(id);
+                }
+                if let Some(changes) = changes.as_mut() {
+                    changes.upserted.remove(&id);
+                    changes.deleted.insert(id);
+                }
+            }
+            kept
+        })
+    }
+
+    /// Gets an iterator over the balls of this model.
+    pub fn iter_balls(&self) -> impl Iterator<Item = impl Deref<Target = Ball<Point>> + '_> {
+        self.graph.iter().map(|v| v.deref_data())
+    }
+
+    /// Gets a copy of the balls of this model.
+    /// The items of [Model::iter_balls] are guards rather than references, which `Iterator::cloned` does not take.
+    pub(crate) fn cloned_balls(&self) -> Vec<Ball<Point>>
+    where
+        Point: Clone,
+    {
+        self.graph.iter().map(|v| v.deref_data().clone()).collect()
+    }
+
+    /// Gets the balls that most probably include the given point.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space, neighborhood::{GetNeighborhood, Neighborhood}};
+    ///
+    /// fn main() {
+    ///     let data = vec![
+    ///         Ball::new(vec![4.], 3., 1.),
+    ///         Ball::new(vec![5.], 2., 2.),
+    ///         Ball::new(vec![3.], 3., 3.),
+    ///     ];
+    ///     let model = Model::load(space::euclid_dist, data.clone());
+    ///     let neighborhood = model.predict(&vec![6.]);
+    ///     if let Neighborhood::Two(n1, n2) = neighborhood {
+    ///         assert_eq!(&data[1], n1.coord());
+    ///         assert_eq!(1./2., n1.dist());
+    ///         assert_eq!(&data[0], n2.coord());
+    ///         assert_eq!(4./3., n2.dist());
+    ///     } else {
+    ///         panic!()
+    ///     }
+    /// }
     /// ```
     pub fn predict(
         &self,
         point: &Point,
     ) -> Neighborhood<Ball<Point>, impl Deref<Target = Ball<Point>> + '_> {
+        let finder = match &self.finder {
+            Some(finder) => finder,
+            None => {
+                return self
+                    .iter_balls()
+                    .get_neighborhood(point, |p, m| (self.dist)(p, m))
+            }
+        };
+        let mut balls: Vec<_> = self.iter_balls().map(Some).collect();
+        let refs: Vec<&Ball<Point>> = balls.iter().flatten().map(|b| b.deref()).collect();
+        let indices = finder.find(point, &refs, &|p, m| (self.dist)(p, m));
+        drop(refs);
+        let mut neighbors = indices
+            .into_iter()
+            .filter_map(|i| balls.get_mut(i).and_then(Option::take))
+            .map(|b| {
+                let dist = (self.dist)(point, &b);
+                NeighborDist(b, dist)
+            });
+        match (neighbors.next(), neighbors.next()) {
+            (Some(n1), Some(n2)) => Neighborhood::Two(n1, n2),
+            (Some(n1), None) => Neighborhood::One(n1),
+            _ => Neighborhood::None,
+        }
+    }
+
+    /// Keeps the last `size` points fitted to each ball, which [Model::ball_covariance] needs.
+    /// No point is kept by default, as it costs up to `size` points of memory per ball.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// fn main() {
+    ///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+    ///     let mut model = Model::new(space::euclid_dist);
+    ///     let mut kept = Model::new(space::euclid_dist).with_reservoirs(64);
+    ///     for i in 0..10 {
+    ///         algo.fit(&mut model, vec![i as f64 / 10., 1.]);
+    ///         algo.fit(&mut kept, vec![i as f64 / 10., 1.]);
+    ///     }
+    ///     assert!(model.ball_covariance(0).is_none());
+    ///     assert!(kept.ball_covariance(0).is_some());
+    /// }
+    /// ```
+    pub fn with_reservoirs(mut self, size: usize) -> Self {
+        self.reservoir_size = size;
+        self
+    }
+
+    /// Uses the given neighbor finder instead of computing the distance to each ball
+    /// in [Model::predict], e.g. an approximate nearest neighbors index for huge models.
+    /// See [NeighborFinder].
+    pub fn with_neighbor_finder<Finder>(mut self, finder: Finder) -> Self
+    where
+        Finder: NeighborFinder<Point, Ball<Point>> + 'static,
+    {
+        self.finder = Some(Box::new(finder));
+        self
+    }
+
+    /// Gets the balls that most probably contain each of the given points, see [Model::predict].
+    pub fn predict_batch(
+        &self,
+        points: &[Point],
+    ) -> Vec<Neighborhood<Ball<Point>, impl Deref<Target = Ball<Point>> + '_>> {
+        points.iter().map(|point| self.predict(point)).collect()
+    }
+
+    /// Gets the probability that the given point belongs to each ball, in the order of [Model::iter_balls].
+    /// The responsibility of a ball is proportional to `w.exp(-d)`
+    /// where `w` is the weight of the ball and `d` the normalized distance from the point to the ball.
+    /// Balls with an infinite radius or a zero weight have a zero responsibility.
+    pub fn responsibilities(&self, point: &Point) -> Vec<f64> {
+        self.memberships(point, |d| (-d).exp())
+    }
+
+    /// Gets the probability that the given point belongs to each ball, with the index of the ball.
+    /// The probabilities are the [responsibilities](Model::responsibilities) of the balls.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![2.], 1., 1.)];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     let proba = model.predict_proba(&vec![1.]);
+    ///     assert_eq!(vec![(0, 0.5), (1, 0.5)], proba);
+    /// }
+    /// ```
+    pub fn predict_proba(&self, point: &Point) -> Vec<(usize, f64)> {
+        self.predict_proba_with(point, |d| (-d).exp())
+    }
+
+    /// Gets the probability that the given point belongs to each ball, with the index of the ball.
+    /// The probability of a ball is proportional to `w.kernel(d)`
+    /// where `w` is the weight of the ball and `d` the normalized distance from the point to the ball.
+    /// Balls with an infinite radius or a zero weight have a zero probability.
+    pub fn predict_proba_with<Kernel>(&self, point: &Point, kernel: Kernel) -> Vec<(usize, f64)>
+    where
+        Kernel: Fn(f64) -> f64,
+    {
+        self.memberships(point, kernel)
+            .into_iter()
+            .enumerate()
+            .collect()
+    }
+
+    /// Computes the normalized membership weights `w.kernel(d)` of a point for all balls.
+    fn memberships<Kernel>(&self, point: &Point, kernel: Kernel) -> Vec<f64>
+    where
+        Kernel: Fn(f64) -> f64,
+    {
+        let scores = self.scores(point, kernel);
+        let total: f64 = scores.iter().sum();
+        if total > 0. {
+            scores.into_iter().map(|s| s / total).collect()
+        } else {
+            scores
+        }
+    }
+
+    /// Computes the membership weights `w.kernel(d)` of a point for all balls.
+    fn scores<Kernel>(&self, point: &Point, kernel: Kernel) -> Vec<f64>
+    where
+        Kernel: Fn(f64) -> f64,
+    {
         self.iter_balls()
-            .get_neighborhood(point, |p, m| (self.dist)(p, m))
+            .map(|ball| {
+                if ball.radius.is_infinite() || ball.weight == 0. {
+                    0.
+                } else {
+                    ball.weight * kernel((self.dist)(point, &ball))
+                }
+            })
+            .collect()
+    }
+
+    /// Gets a pseudo-likelihood of the given point, i.e. the sum of `w.exp(-d)` over all balls
+    /// where `w` is the weight of the ball and `d` the normalized distance from the point to the ball.
+    /// Points far from every ball have a density near zero, e.g. for anomaly detection.
+    /// Balls with an infinite radius do not contribute to the density.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![0.], 1., 2.), Ball::new(vec![10.], 1., 1.)];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     assert!(model.density(&vec![0.]) > 1.9);
+    ///     assert!(model.density(&vec![100.]) < 1E-6);
+    /// }
+    /// ```
+    pub fn density(&self, point: &Point) -> f64 {
+        self.scores(point, |d| (-d).exp()).into_iter().sum()
+    }
+
+    /// Gets the entropy of the [responsibilities](Model::responsibilities) of the balls for the given point.
+    /// A high entropy means that the point is ambiguous, i.e. it lies between balls.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![10.], 1., 1.)];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     assert!(model.assignment_entropy(&vec![0.]) < 1E-3);
+    ///     assert_eq!(f64::ln(2.), model.assignment_entropy(&vec![5.]));
+    /// }
+    /// ```
+    pub fn assignment_entropy(&self, point: &Point) -> f64 {
+        self.responsibilities(point)
+            .into_iter()
+            .filter(|&p| p > 0.)
+            .map(|p| -p * p.ln())
+            .sum()
+    }
+
+    /// Gets the number of neighbors of each ball in the neighborhood graph, in the order of [Model::iter_balls].
+    /// Neighbors that have been removed from the model are not counted.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// for x in [0., 1., 100., 200.] {
+    ///     algo.fit(&mut model, vec![x]);
+    /// }
+    /// let degrees = model.degrees();
+    /// assert_eq!(model.iter_balls().count(), degrees.len());
+    /// assert!(degrees.iter().all(|&d| d > 0));
+    /// ```
+    pub fn degrees(&self) -> Vec<usize> {
+        self.graph.iter().map(|v| v.live_degree()).collect()
+    }
+
+    /// Gets the sum of the weights of the balls.
+    pub fn total_weight(&self) -> f64 {
+        self.iter_balls().map(|b| b.weight).sum()
+    }
+
+    /// Gets the weight of each ball divided by the sum of weights,
+    /// i.e. the probability that an incoming point belongs to the ball.
+    /// All fractions are zero if the sum of weights is zero.
+    pub fn weight_fractions(&self) -> Vec<f64> {
+        let weights: Vec<f64> = self.iter_balls().map(|b| b.weight).collect();
+        let total: f64 = weights.iter().sum();
+        if total > 0. {
+            weights.into_iter().map(|w| w / total).collect()
+        } else {
+            weights.into_iter().map(|_| 0.).collect()
+        }
+    }
+
+    /// Gets the expected number of incoming points per unit of time for each ball,
+    /// given the total rate of incoming points.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![
+    ///         Ball::new(vec![0.], 1., 3.),
+    ///         Ball::new(vec![9.], 1., 1.),
+    ///     ];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     assert_eq!(vec![75., 25.], model.expected_load(100.));
+    /// }
+    /// ```
+    pub fn expected_load(&self, total_rate: f64) -> Vec<f64> {
+        self.weight_fractions()
+            .into_iter()
+            .map(|f| f * total_rate)
+            .collect()
+    }
+
+    /// Gets the indices of the most and the least loaded balls, i.e. the heaviest and the lightest.
+    /// Returns `None` if the model is empty.
+    pub fn load_extremes(&self) -> Option<(usize, usize)> {
+        let weights: Vec<f64> = self.iter_balls().map(|b| b.weight).collect();
+        let by_weight = |(_, w1): &(usize, &f64), (_, w2): &(usize, &f64)| w1.total_cmp(w2);
+        let hot = weights.iter().enumerate().max_by(by_weight)?.0;
+        let cold = weights.iter().enumerate().min_by(by_weight)?.0;
+        Some((hot, cold))
+    }
+
+    /// Gets the `k` balls that most probably include the given point with their normalized distances,
+    /// ordered by distance. Fewer balls are returned if the model has less than `k` balls.
+    ///
+    /// Balls with an infinite radius, i.e. the initial ball of a model that has seen a single point,
+    /// are skipped because their normalized distance to any point is zero.
+    /// Use [Model::predict_k_filtered] to select balls differently.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![
+    ///         Ball::new(vec![4.], 3., 1.),
+    ///         Ball::new(vec![5.], 2., 2.),
+    ///         Ball::new(vec![3.], 3., 3.),
+    ///     ];
+    ///     let model = Model::load(space::euclid_dist, data.clone());
+    ///     let prediction = model.predict_k(&vec![6.], 3);
+    ///     assert_eq!(&data[1], &*prediction[0].0);
+    ///     assert_eq!(1. / 2., prediction[0].1);
+    ///     assert_eq!(&data[0], &*prediction[1].0);
+    ///     assert_eq!(&data[2], &*prediction[2].0);
+    /// }
+    /// ```
+    pub fn predict_k(
+        &self,
+        point: &Point,
+        k: usize,
+    ) -> Vec<(impl Deref<Target = Ball<Point>> + '_, f64)> {
+        self.predict_k_filtered(point, k, |ball| ball.radius.is_finite())
+    }
+
+    /// Gets the `k` balls that satisfy `filter` and most probably include the given point
+    /// with their normalized distances, ordered by distance.
+    pub fn predict_k_filtered<Filter>(
+        &self,
+        point: &Point,
+        k: usize,
+        filter: Filter,
+    ) -> Vec<(impl Deref<Target = Ball<Point>> + '_, f64)>
+    where
+        Filter: Fn(&Ball<Point>) -> bool,
+    {
+        let candidates = self.iter_balls().filter(|ball| filter(ball)).map(|ball| {
+            let d = (self.dist)(point, &ball);
+            (ball, d)
+        });
+        k_smallest(candidates, k)
+    }
+
+    /// Gets the balls which normalized distance to the given point is at most `r`, in the model order.
+    /// With `r = 1` these are the balls that contain the point.
+    /// Unlike [Model::predict_k], balls with an infinite radius are not skipped: they contain any point.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![Ball::new(vec![0.], 4., 1.), Ball::new(vec![5.], 1., 1.)];
+    ///     let model = Model::load(space::euclid_dist, data.clone());
+    ///     let balls = model.balls_within_radius(&vec![1.], 1.);
+    ///     assert_eq!(1, balls.len());
+    ///     assert_eq!(&data[0], &*balls[0]);
+    /// }
+    /// ```
+    pub fn balls_within_radius(
+        &self,
+        point: &Point,
+        r: f64,
+    ) -> Vec<impl Deref<Target = Ball<Point>> + '_> {
+        self.iter_balls()
+            .filter(|ball| (self.dist)(point, ball) <= r)
+            .collect()
     }
 }
 
+impl Model<RealPoint> {
+    /// Indexes the ball centers in a grid which cells have the given side,
+    /// so that fitting a point only computes its distance to the balls of the nearest cells.
+    /// The index only pays off when there are many balls;
+    /// a good cell size is about the typical distance between neighbor balls.
+    ///
+    /// The fitted model is the same as without the index provided the distance of the model grows
+    /// with the gap between the points along each coordinate, like [space::euclid_dist](crate::space::euclid_dist)
+    /// and [space::manhattan_dist](crate::space::manhattan_dist) do.
+    /// With other distances, the index may miss the closest balls and the fitted model may differ.
+    /// Panics if the cell size is not positive and finite.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// fn main() {
+    ///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+    ///     let mut model = Model::new(space::euclid_dist);
+    ///     let mut indexed = Model::new(space::euclid_dist).with_grid_index(1.);
+    ///     for point in [vec![0., 0.], vec![5., 5.], vec![0., 1.], vec![9., 2.]] {
+    ///         algo.fit(&mut model, point.clone());
+    ///         algo.fit(&mut indexed, point);
+    ///     }
+    ///     let balls = |m: &Model<Vec<f64>>| m.iter_balls().map(|b| b.clone()).collect::<Vec<_>>();
+    ///     assert_eq!(balls(&model), balls(&indexed));
+    /// }
+    /// ```
+    pub fn with_grid_index(mut self, cell_size: f64) -> Self {
+        let mut index = GridIndex::new(cell_size);
+        for vertex in self.graph.iter() {
+            index.insert(vertex);
+        }
+        self.index = Some(Box::new(index));
+        self
+    }
+
+    /// Gets the indices and densities of the `k` densest balls, by decreasing density.
+    /// The density of a ball is its weight divided by its volume, i.e. `w / r^n` in R^n.
+    /// ```
+    /// use fluent_data::{Model, model::Ball, space};
+    ///
+    /// fn main() {
+    ///     let data = vec![
+    ///         Ball::new(vec![0., 0.], 4., 8.),
+    ///         Ball::new(vec![9., 9.], 1., 4.),
+    ///     ];
+    ///     let model = Model::load(space::euclid_dist, data);
+    ///     let densest = model.densest(1);
+    ///     assert_eq!(vec![(1, 4.)], densest);
+    /// }
+    /// ```
+    pub fn densest(&self, k: usize) -> Vec<(usize, f64)> {
+        let mut densities: Vec<(usize, f64)> = self
+            .iter_balls()
+            .map(|ball| {
+                let dim = ball.center.len() as f64;
+                // the radius is stored squared
+                ball.weight / ball.radius.powf(dim / 2.)
+            })
+            .enumerate()
+            .collect();
+        densities.sort_by(|(_, d1), (_, d2)| d2.total_cmp(d1));
+        densities.truncate(k);
+        densities
+    }
+
+    /// Estimates the covariance matrix of the ball at the given index from its recent points.
+    /// As the model assumes isotropic balls, unequal diagonal terms or large off-diagonal terms
+    /// reveal an elongated cluster.
+    /// Returns `None` when there is no such ball, when less than two points were kept for it,
+    /// e.g. because the reservoirs are not enabled by [Model::with_reservoirs],
+    /// or when the kept points do not have the same dimension.
+    /// ```
+    /// use fluent_data::{Algo, Model, space};
+    ///
+    /// fn main() {
+    ///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+    ///     let mut model = Model::new(space::euclid_dist).with_reservoirs(64);
+    ///     for i in 0..10 {
+    ///         algo.fit(&mut model, vec![i as f64 / 10., 1.]);
+    ///     }
+    ///     let covariance = model.ball_covariance(0).unwrap();
+    ///     assert_eq!(0., covariance[1][1]);
+    /// }
+    /// ```
+    pub fn ball_covariance(&self, index: usize) -> Option<Vec<Vec<f64>>> {
+        let id = self.graph.get(index)?.deref_data().id;
+        let points = self.reservoirs.get(&id).filter(|r| r.len() > 1)?;
+        let n = points.len() as f64;
+        let dim = points[0].len();
+        if points.iter().any(|p| p.len() != dim) {
+            return None;
+        }
+        let mean: Vec<f64> = (0..dim)
+            .map(|i| points.iter().map(|p| p[i]).sum::<f64>() / n)
+            .collect();
+        let covariance = (0..dim)
+            .map(|i| {
+                (0..dim)
+                    .map(|j| {
+                        points
+                            .iter()
+                            .map(|p| (p[i] - mean[i]) * (p[j] - mean[j]))
+                            .sum::<f64>()
+                            / (n - 1.)
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(covariance)
+    }
+}
+
+/// Parses the balls of a json model written by the [Streamer](crate::Streamer) or by [Model::save_to_file],
+/// i.e. an array of balls, possibly wrapped in an envelope or a delta snapshot,
+/// or by [Model::to_json], in which case the neighbors are ignored.
+/// ```
+/// use fluent_data::{model::{self, Ball}, space, Model};
+///
+/// let json = r#"{"balls":[{"center":[4.0],"radius":3.0,"weight":1.0}],"neighbors":[[]]}"#;
+/// let balls: Vec<Ball<Vec<f64>>> = model::parse_snapshot(json).unwrap();
+/// assert_eq!(vec![Ball::new(vec![4.], 9., 1.)], balls);
+/// let model = Model::load(space::euclid_dist, balls);
+/// ```
+pub fn parse_snapshot<Point: PartialEq + DeserializeOwned>(
+    json: &str,
+) -> Result<Vec<Ball<Point>>, Box<dyn Error>> {
+    snapshot_balls(serde_json::from_str(json)?)
+}
+
+/// Gets the balls of a json model, see [parse_snapshot].
+fn snapshot_balls<Point: PartialEq + DeserializeOwned>(
+    mut value: Value,
+) -> Result<Vec<Ball<Point>>, Box<dyn Error>> {
+    if let Some(model) = value.get_mut("model") {
+        value = model.take();
+    }
+    if let Some(balls) = value.get_mut("balls") {
+        value = balls.take();
+    }
+    streamer::deserialize_balls(value)
+}
+
 pub(crate) trait GetNeighbors<Point: PartialEq> {
     fn get_neighbors(&self) -> Vec<Neighbor<Ball<Point>>>;
 }
@@ -204,6 +1539,8 @@ impl<Point: PartialEq> GetNeighbors<Point> for Vec<BallNode<Point>> {
 
 #[cfg(test)]
 mod tests {
+    use approx_eq::assert_approx_eq;
+
     use crate::{model::*, space};
 
     #[test]
@@ -273,6 +1610,538 @@ mod tests {
         assert!(n3.next().unwrap().deref_data().eq(&data[1]));
     }
 
+    #[test]
+    fn test_predict_k() {
+        let data = vec![
+            Ball::new(vec![0.], f64::INFINITY, 0.),
+            Ball::new(vec![4.], 1., 1.),
+            Ball::new(vec![8.], 1., 1.),
+            Ball::new(vec![7.], 4., 1.),
+        ];
+        let model = Model::load(space::euclid_dist, data.clone());
+        let prediction = model.predict_k(&vec![6.], 2);
+        assert_eq!(2, prediction.len());
+        assert_eq!(&data[3], &*prediction[0].0);
+        assert_eq!(0.25, prediction[0].1);
+        assert_eq!(&data[1], &*prediction[1].0); // tie with data[2], first in model
+        assert_eq!(4., prediction[1].1);
+        let prediction = model.predict_k(&vec![6.], 10);
+        assert_eq!(3, prediction.len());
+        assert_eq!(&data[2], &*prediction[2].0);
+        let prediction = model.predict_k_filtered(&vec![6.], 10, |_| true);
+        assert_eq!(4, prediction.len());
+        assert_eq!(&data[0], &*prediction[0].0);
+        assert_eq!(0., prediction[0].1);
+    }
+
+    #[test]
+    fn test_responsibilities() {
+        let data = vec![
+            Ball::new(vec![0.], 1., 1.),
+            Ball::new(vec![4.], f64::INFINITY, 0.),
+            Ball::new(vec![2.], 4., 3.),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let r = model.responsibilities(&vec![1.]);
+        let (s1, s3) = ((-1f64).exp(), 3. * (-0.25f64).exp());
+        assert_approx_eq!(s1 / (s1 + s3), r[0]);
+        assert_eq!(0., r[1]);
+        assert_approx_eq!(s3 / (s1 + s3), r[2]);
+    }
+
+    #[test]
+    fn test_predict_proba() {
+        let data = vec![
+            Ball::new(vec![0.], 4., 1.),
+            Ball::new(vec![3.], 1., 3.),
+            Ball::new(vec![4.], 1., 0.),
+            Ball::new(vec![5.], f64::INFINITY, 0.),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let proba = model.predict_proba(&vec![2.]);
+        // scores: 1 * exp(-1) and 3 * exp(-1)
+        assert_eq!(vec![(0, 0.25), (1, 0.75), (2, 0.), (3, 0.)], proba);
+        let proba = model.predict_proba_with(&vec![2.], |d| 1. / (1. + d));
+        // scores: 1 * 1/2 and 3 * 1/2
+        assert_eq!(vec![(0, 0.25), (1, 0.75), (2, 0.), (3, 0.)], proba);
+    }
+
+    #[test]
+    fn test_predict_proba_sum() {
+        use rand::{Rng, SeedableRng};
+
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(846513548);
+        for _ in 0..1000 {
+            algo.fit(
+                &mut model,
+                vec![rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.)],
+            );
+        }
+        for _ in 0..100 {
+            let point = vec![rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.)];
+            let total: f64 = model.predict_proba(&point).iter().map(|(_, p)| p).sum();
+            assert_approx_eq!(1., total);
+        }
+    }
+
+    #[test]
+    fn test_assignment_entropy() {
+        let data = vec![
+            Ball::new(vec![0., 0.], 1., 2.),
+            Ball::new(vec![6., 0.], 1., 2.),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let center = model.assignment_entropy(&vec![0., 0.]);
+        let between = model.assignment_entropy(&vec![3., 1.]);
+        assert!(center < 1E-6);
+        assert_approx_eq!(f64::ln(2.), between);
+        assert_eq!(
+            0.,
+            Model::new(space::euclid_dist).assignment_entropy(&vec![0.])
+        );
+    }
+
+    #[test]
+    fn test_expected_load() {
+        let data = vec![
+            Ball::new(vec![0.], 1., 2.),
+            Ball::new(vec![4.], 1., 16.),
+            Ball::new(vec![8.], 1., 0.5),
+            Ball::new(vec![12.], 1., 1.5),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let load = model.expected_load(200.);
+        assert_eq!(vec![20., 160., 5., 15.], load);
+        assert_eq!(Some((1, 2)), model.load_extremes());
+        let empty = Model::<Vec<f64>>::new(space::euclid_dist);
+        assert!(empty.expected_load(200.).is_empty());
+        assert_eq!(None, empty.load_extremes());
+    }
+
+    #[test]
+    fn test_balls_within_radius() {
+        let data = vec![
+            Ball::new(vec![0.], 1., 1.),
+            Ball::new(vec![3.], 4., 2.),
+            Ball::new(vec![4.], 1., 3.),
+            Ball::new(vec![10.], 9., 4.),
+        ];
+        let model = Model::load(space::euclid_dist, data.clone());
+        let balls = model.balls_within_radius(&vec![4.5], 1.);
+        assert_eq!(2, balls.len());
+        assert_eq!(data[1], *balls[0]);
+        assert_eq!(data[2], *balls[1]);
+        assert!(model.balls_within_radius(&vec![4.5], 0.2).is_empty());
+        assert_eq!(4, model.balls_within_radius(&vec![4.5], 100.).len());
+    }
+
+    #[test]
+    fn test_save_load_file() {
+        let data = vec![
+            Ball::new(vec![4.], 3., 1.),
+            Ball::new(vec![5.], 2., 2.),
+            Ball::new(vec![3.], f64::INFINITY, 0.),
+        ];
+        let model = Model::load(space::euclid_dist, data.clone());
+        let path = std::env::temp_dir().join("fluent_data_test_save_load_file.json");
+        model.save_to_file(&path).unwrap();
+        let loaded = Model::load_from_file(space::euclid_dist, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(3, loaded.iter_balls().count());
+        for (ball, expected) in loaded.iter_balls().zip(data.iter()) {
+            assert_eq!(expected.center(), ball.center());
+            assert_eq!(expected.weight(), ball.weight());
+            assert_approx_eq!(expected.radius(), ball.radius());
+        }
+    }
+
+    #[test]
+    fn test_ball_covariance() {
+        use crate::Algo;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2938475610);
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist).with_reservoirs(64);
+        for _ in 0..500 {
+            let t: f64 = rng.gen_range(-1. ..1.);
+            let noise: f64 = rng.gen_range(-0.05..0.05);
+            algo.fit(&mut model, vec![3. * t, t + noise]);
+        }
+        let (index, _) = model
+            .iter_balls()
+            .enumerate()
+            .max_by(|(_, b1), (_, b2)| b1.weight.total_cmp(&b2.weight))
+            .unwrap();
+        let covariance = model.ball_covariance(index).unwrap();
+        assert!(covariance[0][0] > 4. * covariance[1][1]);
+        assert!(covariance[0][1] > 0.);
+        assert_eq!(covariance[0][1], covariance[1][0]);
+        assert!(model.ball_covariance(model.graph.len()).is_none());
+        let mut mixed = Model::new(space::euclid_dist).with_reservoirs(4);
+        let vertex = mixed.add_ball(Ball::new(vec![0., 0.], 1., 3.), vec![]);
+        for point in [vec![0., 0.], vec![1., 0.], vec![0., 1., 2.]] {
+            mixed.remember(&vertex, point);
+        }
+        assert!(mixed.ball_covariance(0).is_none());
+        let mut unkept = Model::new(space::euclid_dist);
+        let vertex = unkept.add_ball(Ball::new(vec![0., 0.], 1., 3.), vec![]);
+        for point in [vec![0., 0.], vec![1., 0.], vec![0., 1.]] {
+            unkept.remember(&vertex, point);
+        }
+        assert!(unkept.reservoirs.is_empty());
+    }
+
+    #[test]
+    fn test_neighbor_finder() {
+        use crate::neighborhood::ExactFinder;
+
+        struct Reversed;
+
+        impl NeighborFinder<RealPoint, Ball<RealPoint>> for Reversed {
+            fn find(
+                &self,
+                _point: &RealPoint,
+                balls: &[&Ball<RealPoint>],
+                _dist: &dyn Fn(&RealPoint, &Ball<RealPoint>) -> f64,
+            ) -> Vec<usize> {
+                (0..balls.len()).rev().take(2).collect()
+            }
+        }
+
+        let data = vec![
+            Ball::new(vec![0.], 1., 1.),
+            Ball::new(vec![2.], 1., 1.),
+            Ball::new(vec![8.], 4., 1.),
+        ];
+        let model = Model::load(space::euclid_dist, data.clone()).with_neighbor_finder(Reversed);
+        let prediction = model.predict(&vec![1.]);
+        match prediction {
+            Neighborhood::Two(n1, n2) => {
+                assert_eq!(&data[2], n1.coord());
+                assert_eq!(49. / 4., n1.dist());
+                assert_eq!(&data[1], n2.coord());
+                assert_eq!(1., n2.dist());
+            }
+            _ => panic!(),
+        }
+        let model = Model::load(space::euclid_dist, data.clone()).with_neighbor_finder(ExactFinder);
+        let prediction = model.predict(&vec![1.]);
+        match prediction {
+            Neighborhood::Two(n1, n2) => {
+                assert_eq!(&data[0], n1.coord());
+                assert_eq!(&data[1], n2.coord());
+            }
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn test_checksum() {
+        let data = vec![
+            Ball::new(vec![0.], 1., 1.),
+            Ball::new(vec![2.], 1., 3.),
+            Ball::new(vec![10.], 1., 1.),
+        ];
+        let mut model = Model::load(space::euclid_dist, data.clone());
+        let checksum = model.checksum();
+        assert_eq!(
+            checksum,
+            Model::load(space::euclid_dist, data.clone()).checksum()
+        );
+        model.predict(&vec![1.]);
+        assert_eq!(checksum, model.checksum());
+        let reordered = vec![data[1].clone(), data[0].clone(), data[2].clone()];
+        assert_ne!(
+            checksum,
+            Model::load(space::euclid_dist, reordered).checksum()
+        );
+        crate::Algo::new(space::euclid_dist, space::real_combine).fit(&mut model, vec![1.]);
+        assert_ne!(checksum, model.checksum());
+    }
+
+    #[test]
+    fn test_downsample() {
+        let data: Vec<Ball<RealPoint>> = (0..10)
+            .map(|i| Ball::new(vec![(i * i) as f64, i as f64], 1. + i as f64, 1.))
+            .collect();
+        let model = Model::load(space::euclid_dist, data.clone());
+        let balls = model.downsample(3, &space::real_combine);
+        assert_eq!(3, balls.len());
+        assert_approx_eq!(10., balls.iter().map(|b| b.weight).sum::<f64>());
+        for original in data.iter() {
+            let bounded = balls.iter().any(|b| {
+                let d = space::euclid_dist(&b.center, &original.center).sqrt();
+                d + original.radius() <= b.radius() + 1E-9
+            });
+            assert!(bounded);
+        }
+        assert_eq!(10, model.iter_balls().count());
+        assert_eq!(data, model.downsample(10, &space::real_combine));
+    }
+
+    #[test]
+    fn test_downsample_distance() {
+        // the closest pair is ([0, 0], [0, -7]) for the manhattan distance
+        // but ([0, 0], [4, 4]) for the euclidian distance
+        let data = vec![
+            Ball::new(vec![0., 0.], 1., 1.),
+            Ball::new(vec![4., 4.], 1., 1.),
+            Ball::new(vec![0., -7.], 1., 1.),
+        ];
+        let model = Model::load(space::manhattan_dist, data);
+        let balls = model.downsample(2, &space::manhattan_combine);
+        assert_eq!(2, balls.len());
+        assert_eq!(Ball::new(vec![4., 4.], 1., 1.), balls[1]);
+    }
+
+    #[test]
+    fn test_densest() {
+        let data = vec![
+            Ball::new(vec![0., 0.], 100., 10.),
+            Ball::new(vec![4., 4.], f64::INFINITY, 0.),
+            Ball::new(vec![9., 9.], 1., 9.),
+            Ball::new(vec![2., 9.], 4., 0.2),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let densest = model.densest(2);
+        assert_eq!(vec![(2, 9.), (0, 0.1)], densest);
+        assert_eq!(4, model.densest(10).len());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let dataset = vec![
+            vec![5., -1.],
+            vec![1., 1.],
+            vec![15., -13.],
+            vec![11., 23.],
+            vec![31., -3.],
+            vec![10., -9.],
+        ];
+        for point in dataset {
+            algo.fit(&mut model, point);
+        }
+        let loaded = Model::from_json(space::euclid_dist, model.to_json()).unwrap();
+        assert_eq!(model.graph.len(), loaded.graph.len());
+        for (v1, v2) in model.graph.iter().zip(loaded.graph.iter()) {
+            assert_eq!(v1.deref_data().center, v2.deref_data().center);
+            let n1: Vec<_> = v1
+                .iter_neighbors()
+                .map(|n| n.deref_data().center.clone())
+                .collect();
+            let n2: Vec<_> = v2
+                .iter_neighbors()
+                .map(|n| n.deref_data().center.clone())
+                .collect();
+            assert_eq!(n1, n2);
+        }
+        assert_eq!(model.neighbor_indices(), loaded.neighbor_indices());
+    }
+
+    #[test]
+    fn test_from_json_invalid_neighbor() {
+        let json = serde_json::json!({
+            "balls": [{"center": [1.0], "radius": 1.0, "weight": 1.0}],
+            "neighbors": [[1]],
+        });
+        let result = Model::<Vec<f64>>::from_json(space::euclid_dist, json);
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bytes_round_trip() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6531468713545);
+        for _ in 0..20 {
+            let data: Vec<_> = (0..rng.gen_range(0..200))
+                .map(|_| {
+                    let center = (0..3).map(|_| rng.gen_range(-1E3..1E3)).collect();
+                    Ball::new(center, rng.gen_range(0. ..1E2), rng.gen_range(0. ..1E2))
+                })
+                .collect();
+            let model = Model::load(space::euclid_dist, data.clone());
+            let bytes = model.to_bytes().unwrap();
+            let loaded = Model::from_bytes(space::euclid_dist, &bytes).unwrap();
+            let balls: Vec<_> = loaded.iter_balls().map(|b| b.clone()).collect();
+            assert_eq!(data, balls);
+        }
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bytes_compactness() {
+        let data: Vec<_> = (0..100)
+            .map(|i| Ball::new(vec![i as f64 / 3., -i as f64 / 7.], 1. / 3., 2. / 3.))
+            .collect();
+        let model = Model::load(space::euclid_dist, data);
+        let bytes = model.to_bytes().unwrap();
+        let json = serde_json::to_string(&streamer::serialize_model(&model)).unwrap();
+        assert!(bytes.len() * 2 < json.len());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_snapshot_round_trip() {
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        for point in [[5., -1.], [1., 1.], [15., -13.], [11., 23.], [31., -3.]] {
+            algo.fit(&mut model, point.to_vec());
+        }
+        let mut snapshot = vec![];
+        model.write_snapshot(&mut snapshot).unwrap();
+        let loaded = Model::read_snapshot(space::euclid_dist, &snapshot[..]).unwrap();
+        let balls: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+        let loaded_balls: Vec<_> = loaded.iter_balls().map(|b| b.clone()).collect();
+        assert_eq!(balls, loaded_balls);
+        assert_eq!(model.neighbor_indices(), loaded.neighbor_indices());
+    }
+
+    #[test]
+    fn test_balls_with_neighbors_round_trip() {
+        use rand::{Rng, SeedableRng};
+
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(9613541687);
+        for i in 0..500 {
+            let offset = if i % 2 == 0 { 0. } else { 20. };
+            let point = (0..2).map(|_| offset + rng.gen_range(-3. ..3.)).collect();
+            algo.fit(&mut model, point);
+        }
+        let exported = model.export_balls_with_neighbors();
+        let loaded = Model::load_with_neighbors(space::euclid_dist, exported.clone()).unwrap();
+        assert_eq!(exported, loaded.export_balls_with_neighbors());
+        assert_eq!(model.neighbor_indices(), loaded.neighbor_indices());
+        let invalid = vec![(Ball::new(vec![1.], 1., 1.), vec![1])];
+        assert!(Model::load_with_neighbors(space::euclid_dist, invalid).is_err());
+    }
+
+    #[test]
+    fn test_density() {
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        assert_eq!(0., model.density(&vec![0., 0.]));
+        for i in 0..200 {
+            let point = vec![(i % 5) as f64 - 2., (i % 3) as f64 - 1.];
+            algo.fit(&mut model, point);
+        }
+        let center = model.density(&vec![0., 0.]);
+        let edge = model.density(&vec![3., 0.]);
+        let far = model.density(&vec![100., 100.]);
+        assert!(center > edge);
+        assert!(edge > far);
+        assert!(far < 1E-6);
+    }
+
+    #[test]
+    fn test_connected_components() {
+        let data = vec![
+            (Ball::new(vec![0.], 1., 1.), vec![2]),
+            (Ball::new(vec![10.], 1., 1.), vec![4]),
+            (Ball::new(vec![1.], 1., 1.), vec![0]),
+            (Ball::new(vec![11.], 1., 1.), vec![1]),
+            (Ball::new(vec![12.], 1., 1.), vec![]),
+            (Ball::new(vec![2.], 1., 1.), vec![2, 0]),
+        ];
+        let model = Model::load_with_neighbors(space::euclid_dist, data).unwrap();
+        let components = model.connected_components();
+        assert_eq!(2, components.len());
+        assert_eq!(vec![vec![0, 2, 5], vec![1, 3, 4]], components);
+        assert!(Model::new(space::euclid_dist)
+            .connected_components()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let data = vec![
+            Ball::new(vec![0.], 1., 1.),
+            Ball::new(vec![2.], 1., 3.),
+            Ball::new(vec![10.], 1., 2.),
+        ];
+        let model = Model::load(space::euclid_dist, data);
+        let dot = model.to_dot();
+        let expected = "graph model {
+    0 [label=\"[0.0]\\nweight: 1\"];
+    1 [label=\"[2.0]\\nweight: 3\"];
+    2 [label=\"[10.0]\\nweight: 2\"];
+    0 -- 1;
+    0 -- 2;
+    1 -- 2;
+}";
+        assert_eq!(expected, dot);
+        let model: Model<String> = Model::load(|_, _| 1., vec![Ball::new("\"a\"".into(), 1., 1.)]);
+        assert!(model
+            .to_dot()
+            .contains(r#"[label="\"\\\"a\\\"\"\nweight: 1"]"#));
+    }
+
+    #[test]
+    fn test_grid_index_neighborhood() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4864213);
+        let mut data: Vec<Ball<Vec<f64>>> = (0..300)
+            .map(|_| {
+                let center = (0..2).map(|_| rng.gen_range(-50. ..50.)).collect();
+                Ball::new(center, rng.gen_range(0.1..20.), 1.)
+            })
+            .collect();
+        data.push(Ball::new(vec![0., 0.], f64::INFINITY, 0.));
+        let mut linear = Model::load(space::euclid_dist, data.clone());
+        let mut indexed = Model::load(space::euclid_dist, data).with_grid_index(7.);
+        let ids = |neighborhood: Vec<BallNode<Vec<f64>>>| -> Vec<u64> {
+            neighborhood.iter().map(|v| v.deref_data().id).collect()
+        };
+        for _ in 0..200 {
+            let point = (0..2).map(|_| rng.gen_range(-60. ..60.)).collect();
+            assert_eq!(
+                ids(linear.get_neighborhood(&point)),
+                ids(indexed.get_neighborhood(&point))
+            );
+        }
+        linear.retain_balls(|v| !v.deref_data().radius.is_infinite());
+        indexed.retain_balls(|v| !v.deref_data().radius.is_infinite());
+        let far = vec![100., 100.];
+        assert_eq!(
+            ids(linear.get_neighborhood(&far)),
+            ids(indexed.get_neighborhood(&far))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cell size must be positive.")]
+    fn test_grid_index_cell_size() {
+        Model::new(space::euclid_dist).with_grid_index(0.);
+    }
+
+    #[test]
+    fn test_grid_index_fit() {
+        use rand::{Rng, SeedableRng};
+
+        let algo = crate::Algo::new(space::euclid_dist, space::real_combine);
+        let mut linear = Model::new(space::euclid_dist);
+        let mut indexed = Model::new(space::euclid_dist).with_grid_index(2.);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(77412);
+        for i in 0..2000 {
+            let offset = (i % 4) as f64 * 10.;
+            let point: Vec<f64> = (0..2).map(|_| offset + rng.gen_range(-3. ..3.)).collect();
+            algo.fit(&mut linear, point.clone());
+            algo.fit(&mut indexed, point);
+        }
+        assert_eq!(
+            linear.export_balls_with_neighbors(),
+            indexed.export_balls_with_neighbors()
+        );
+    }
+
     fn build_model() -> (Model<Vec<f64>>, Ball<Vec<f64>>, Ball<Vec<f64>>) {
         let mut model = Model::new(space::euclid_dist);
         let n1 = Ball::new(vec![4.], f64::INFINITY, 0.);
@@ -302,4 +2171,34 @@ mod tests {
             panic!()
         }
     }
+
+    #[test]
+    fn test_shared_model() {
+        use std::{
+            sync::{Arc, RwLock},
+            thread,
+        };
+
+        use crate::Algo;
+
+        let model = Arc::new(RwLock::new(Model::new(space::euclid_dist)));
+        let fitted_model = model.clone();
+        let fitter = thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            for i in 0..1000 {
+                let point = vec![(i % 17) as f64, (i % 5) as f64];
+                algo.fit(&mut fitted_model.write().unwrap(), point);
+            }
+        });
+        while !fitter.is_finished() {
+            let model = model.read().unwrap();
+            let prediction = model.predict(&vec![3., 2.]);
+            let empty = matches!(prediction, Neighborhood::None);
+            assert_eq!(empty, model.iter_balls().next().is_none());
+        }
+        fitter.join().unwrap();
+        let model = model.read().unwrap();
+        let prediction = model.predict(&vec![3., 2.]);
+        assert!(!matches!(prediction, Neighborhood::None));
+    }
 }
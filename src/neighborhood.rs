@@ -1,12 +1,15 @@
 //! This module defines the neighborhood of some point in space.
 //!
-//! To get neighbors of a point, use [GetNeighborhood::get_neighborhood] method.
+//! To get neighbors of a point, use [GetNeighborhood::get_neighborhood] method,
+//! or [GetKNeighborhood::get_k_neighborhood] to get more than two neighbors.
+//! The [NeighborFinder] trait lets [Model::predict](crate::Model::predict) use another strategy,
+//! e.g. an approximate nearest neighbors index.
 
-use std::{mem::swap, ops::Deref};
+use std::{cmp::Ordering, collections::BinaryHeap, mem::swap, ops::Deref};
 
 /// A reference to a neighbor and its distance from some point in space.
 #[derive(PartialEq, Debug)]
-pub struct NeighborDist<Model, RefModel>(RefModel, f64)
+pub struct NeighborDist<Model, RefModel>(pub(crate) RefModel, pub(crate) f64)
 where
     RefModel: Deref<Target = Model>;
 
@@ -81,6 +84,56 @@ where
     }
 }
 
+/// Defines a k nearest neighbors getter function, like [GetNeighborhood] for any number of neighbors.
+/// ```
+/// use fluent_data::{space, neighborhood::GetKNeighborhood};
+///
+/// let points = vec![vec![0.], vec![2.], vec![5.], vec![9.]];
+/// let neighbors = points.iter().get_k_neighborhood(&vec![3.], space::euclid_dist, 3);
+/// assert_eq!(&points[1], neighbors[0].coord());
+/// assert_eq!(1., neighbors[0].dist());
+/// assert_eq!(&points[2], neighbors[1].coord());
+/// assert_eq!(&points[0], neighbors[2].coord());
+/// ```
+pub trait GetKNeighborhood<Point, Model, RefModel, Dist>
+where
+    Dist: Fn(&Point, &Model) -> f64,
+    RefModel: Deref<Target = Model>,
+{
+    /// Get the `k` nearest neighbors, ordered by their distance from the given point,
+    /// or less if there are less than `k` models. Neighbors at the same distance are kept in their order.
+    fn get_k_neighborhood(
+        &mut self,
+        point: &Point,
+        dist: Dist,
+        k: usize,
+    ) -> Vec<NeighborDist<Model, RefModel>>;
+}
+
+/// Implementation of k nearest neighbors getter for an iterator over a set of models.
+impl<Iter, Point, Model, RefModel, Dist> GetKNeighborhood<Point, Model, RefModel, Dist> for Iter
+where
+    Iter: Iterator<Item = RefModel>,
+    RefModel: Deref<Target = Model>,
+    Dist: Fn(&Point, &Model) -> f64,
+{
+    fn get_k_neighborhood(
+        &mut self,
+        point: &Point,
+        dist: Dist,
+        k: usize,
+    ) -> Vec<NeighborDist<Model, RefModel>> {
+        let iter = self.map(|p| {
+            let dist = dist(point, &p);
+            (p, dist)
+        });
+        k_smallest(iter, k)
+            .into_iter()
+            .map(|(p, dist)| NeighborDist(p, dist))
+            .collect()
+    }
+}
+
 /// find neighbors given a (model, distance) couples iterator
 fn fold_0<Model, RefModel>(
     mut iter: impl Iterator<Item = NeighborDist<Model, RefModel>>,
@@ -149,6 +202,101 @@ where
     (d1, d2)
 }
 
+/// Finds the two nearest neighbors of a point among a set of `Model`.
+///
+/// [ExactFinder] computes the distance to each model,
+/// other implementations may use an index to find approximate neighbors in huge sets.
+/// ```
+/// use fluent_data::{Model, model::Ball, neighborhood::{NeighborFinder, Neighborhood}, space};
+///
+/// struct First;
+///
+/// impl NeighborFinder<Vec<f64>, Ball<Vec<f64>>> for First {
+///     fn find(
+///         &self,
+///         _point: &Vec<f64>,
+///         models: &[&Ball<Vec<f64>>],
+///         _dist: &dyn Fn(&Vec<f64>, &Ball<Vec<f64>>) -> f64,
+///     ) -> Vec<usize> {
+///         (0..models.len()).take(1).collect()
+///     }
+/// }
+///
+/// let data = vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![5.], 1., 1.)];
+/// let model = Model::load(space::euclid_dist, data).with_neighbor_finder(First);
+/// assert!(matches!(model.predict(&vec![5.]), Neighborhood::One(n) if n.dist() == 25.));
+/// ```
+pub trait NeighborFinder<Point, Model>: Send + Sync {
+    /// Gets the indices of the two nearest models of the point, ordered by their distance from the point,
+    /// or less if there are less than two models.
+    fn find(
+        &self,
+        point: &Point,
+        models: &[&Model],
+        dist: &dyn Fn(&Point, &Model) -> f64,
+    ) -> Vec<usize>;
+}
+
+/// Finds the exact two nearest neighbors by computing the distance to each model.
+pub struct ExactFinder;
+
+impl<Point, Model> NeighborFinder<Point, Model> for ExactFinder {
+    fn find(
+        &self,
+        point: &Point,
+        models: &[&Model],
+        dist: &dyn Fn(&Point, &Model) -> f64,
+    ) -> Vec<usize> {
+        let dists = models.iter().enumerate().map(|(i, m)| (i, dist(point, m)));
+        k_smallest(dists, 2).into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+/// An item in the heap used by [k_smallest], ordered by distance then by rank in the input.
+struct HeapItem<T> {
+    dist: f64,
+    rank: usize,
+    item: T,
+}
+
+impl<T> PartialEq for HeapItem<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for HeapItem<T> {}
+
+impl<T> PartialOrd for HeapItem<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for HeapItem<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then(self.rank.cmp(&other.rank))
+    }
+}
+
+/// find the k items with the smallest distances, ordered by distance.
+/// Items at the same distance are kept in the input order.
+pub(crate) fn k_smallest<T>(iter: impl Iterator<Item = (T, f64)>, k: usize) -> Vec<(T, f64)> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (rank, (item, dist)) in iter.enumerate() {
+        heap.push(HeapItem { dist, rank, item });
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|h| (h.item, h.dist))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::neighborhood::*;
@@ -215,6 +363,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_k_neighbors() {
+        let centers = [vec![1., 1.], vec![3.5, -1.6], vec![2.4, 4.], vec![-0.5, 1.]];
+        let point = &vec![0., 0.];
+        let nn = centers
+            .iter()
+            .get_k_neighborhood(point, space::euclid_dist, 3);
+        assert_eq!(
+            vec![
+                NeighborDist(&centers[3], 1.25),
+                NeighborDist(&centers[0], 2.),
+                NeighborDist(&centers[1], 14.81),
+            ],
+            nn
+        );
+        let nn = centers
+            .iter()
+            .get_k_neighborhood(point, space::euclid_dist, 10);
+        assert_eq!(4, nn.len());
+        assert_eq!(&centers[2], nn[3].coord());
+        let nn = centers
+            .iter()
+            .get_k_neighborhood(point, space::euclid_dist, 0);
+        assert!(nn.is_empty());
+    }
+
     #[test]
     fn test_smallest() {
         let p: Vec<f64> = vec![];
@@ -234,4 +408,14 @@ mod tests {
         let s = smallest(d1, d2, d3);
         assert_eq!((NeighborDist(&p, 4.), NeighborDist(&p, 7.)), s);
     }
+
+    #[test]
+    fn test_k_smallest() {
+        let items = vec![("a", 3.), ("b", 1.), ("c", 2.), ("d", 1.), ("e", 0.5)];
+        let s = k_smallest(items.clone().into_iter(), 3);
+        assert_eq!(vec![("e", 0.5), ("b", 1.), ("d", 1.)], s);
+        let s = k_smallest(items.into_iter(), 10);
+        assert_eq!(5, s.len());
+        assert_eq!(("a", 3.), s[4]);
+    }
 }
@@ -1,32 +1,617 @@
 //! A [backend] that receives data points from websockets and dispatches models to websockets.
 //!
 //! Use the [backend] function to start the service.
-//! The backend starts listening on port 9001 by default
-//! which can be changed by setting the `PORT`environment variable.
+//! The backend starts listening on 127.0.0.1:9001 by default
+//! which can be changed by setting the `HOST` and `PORT` environment variables.
+//! Use the [backend_on] function to listen on a given address.
+//! Besides the websockets, the last model can be polled by a plain http request `GET /model`
+//! and `GET /health` answers `{"status":"ok","ball_count":2}` while the streamer is running.
+//! The `/ws/stats` endpoint pushes a summary of the models, see [backend].
+//!
+//! The [backend_with_control] function also starts the service,
+//! with a control endpoint that freezes or unfreezes the topology of the model at runtime.
+//! The [backend_with_config] function starts the service for the clients that present a token
+//! and the [backend_with_metrics] function exposes the algorithm counters to Prometheus.
+//! These options compose, see [ServiceConfig], and all the functions honor the `FLUENT_DATA_TOKEN` environment variable.
+//! When the `metrics` feature is enabled, `ServiceConfig::with_metrics` also exposes
+//! the operational metrics of the service, see [metrics](crate::metrics).
+//! The [backend_with_handle] function starts the service with a [ServiceHandle] that shuts it down.
+//! The [multi_backend] function starts a service that fits one model per key, e.g. `/ws/points/sensor-1`.
+//!
+//! When the `tls` feature is enabled, the `tls_backend` function starts the service
+//! with secure websockets, i.e. wss://127.0.0.1:9001/ws/points and wss://127.0.0.1:9001/ws/models.
+//! When the `msgpack` feature is enabled, the points can also be sent as MessagePack binary frames
+//! and the `msgpack_backend` function starts the service that writes MessagePack models.
 
 use std::{
+    collections::HashMap,
     env,
     error::Error,
-    net::{TcpListener, TcpStream},
+    io::{self, Cursor, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
     sync::{
-        mpsc::{self, Receiver, Sender},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
+
 use tungstenite::{
     accept_hdr,
     handshake::server::{Request, Response},
-    Message, WebSocket,
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Error as WsError, Message, WebSocket,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use url::form_urlencoded;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::{
+    algorithm::AlgoStats,
+    streamer::{self, Frame},
+    Algo, Model, Streamer,
 };
 
-use crate::streamer;
+/// A stream that carries a websocket, e.g. a tcp stream or a tls session.
+trait ReadWrite: Read + Write + Send {}
+
+impl<S: Read + Write + Send> ReadWrite for S {}
+
+type Stream = Box<dyn ReadWrite>;
+
+/// Wraps the accepted tcp streams before the websocket handshake.
+type Wrap = Box<dyn Fn(TcpStream) -> Result<Stream, Box<dyn Error>> + Send + Sync>;
+
+type Peers<Data> = Arc<Mutex<Subscribers<Data>>>;
+
+/// The points received by a service, to be read by a [Streamer](crate::Streamer).
+pub type Points<Data = String> = Box<dyn Iterator<Item = Result<Data, Box<dyn Error>>> + Send>;
+
+/// The writer of the models dispatched by a service.
+pub type ModelWriter<Data = String> = Box<dyn FnMut(Data) -> Result<(), Box<dyn Error>> + Send>;
+
+/// The point iterator and the model writer of a service, e.g. returned by [backend].
+pub type Backend<Data = String> = (Points<Data>, ModelWriter<Data>);
+
+/// The point iterator and the model writer of a service with the handle that shuts it down.
+type HandledBackend<Data> = (Points<Data>, ModelWriter<Data>, ServiceHandle);
+
+/// The maximum size of the head of the http requests.
+const MAX_HEAD_SIZE: usize = 8192;
+
+/// The time a client is given to send its request and complete the websocket handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The host the service listens on when none is given.
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// The port the service listens on when none is given.
+pub const DEFAULT_PORT: u16 = 9001;
+
+/// An accepted connection: a websocket with its path and its tcp stream, or a plain http request.
+enum Connection {
+    WebSocket(String, Box<WebSocket<Stream>>, TcpStream),
+    Http(HttpRequest, Stream),
+}
+
+/// The request line of a plain http request, whether it asks for a websocket upgrade
+/// and whether it presents the expected api key.
+struct HttpRequest {
+    method: String,
+    path: String,
+    upgrade: bool,
+    authorized: bool,
+}
+
+impl HttpRequest {
+    /// Parses the head of an http request and checks the `Authorization: Bearer <api key>` header
+    /// or the `?token=<api key>` query parameter if an api key is expected.
+    fn parse(head: &[u8], api_key: Option<&str>) -> Self {
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let method = String::from(request_line.next().unwrap_or_default());
+        let target = request_line.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut tokens: Vec<String> = form_urlencoded::parse(query.as_bytes())
+            .filter(|(name, _)| name == "token")
+            .map(|(_, value)| value.into_owned())
+            .collect();
+        let mut upgrade = false;
+        for line in lines {
+            let (name, value) = line.split_once(':').unwrap_or((line, ""));
+            match name.trim().to_ascii_lowercase().as_str() {
+                "upgrade" => upgrade = true,
+                "authorization" => {
+                    tokens.extend(value.trim().strip_prefix("Bearer ").map(String::from))
+                }
+                _ => {}
+            }
+        }
+        let authorized = match api_key {
+            Some(key) => tokens.iter().any(|token| token == key),
+            None => true,
+        };
+        let path = String::from(path);
+        Self {
+            method,
+            path,
+            upgrade,
+            authorized,
+        }
+    }
+}
+
+/// A stream that replays the bytes already read before reading the underlying stream.
+struct Replay {
+    head: Cursor<Vec<u8>>,
+    stream: Stream,
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.head.read(buf)? {
+            0 => self.stream.read(buf),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// The number of dispatched models between two stats messages by default.
+const DEFAULT_STATS_EVERY: usize = 100;
+
+/// The interval between two pings sent to the model peers by default, in seconds.
+const DEFAULT_PING_INTERVAL: u64 = 30;
+
+/// The number of unanswered pings or failed writes after which a model peer is dropped by default.
+const DEFAULT_MAX_MISSED: usize = 3;
+
+/// The interval at which the dispatcher checks whether the service is shutting down.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// The peers that receive the models or the stats, the last dispatched model,
+/// the number of models dispatched since the last stats,
+/// whether the streamer still writes models, whether the service is shutting down
+/// and the number of connected point producers.
+struct Subscribers<Data> {
+    peers: Vec<Peer>,
+    stats: Vec<Peer>,
+    last: Option<Data>,
+    since_stats: usize,
+    running: bool,
+    liveness: Liveness,
+    shutdown: Arc<AtomicBool>,
+    producers: Arc<AtomicUsize>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl<Data> Subscribers<Data> {
+    /// Builds the state of a running service without peers.
+    fn new(liveness: Liveness) -> Self {
+        Self {
+            peers: vec![],
+            stats: vec![],
+            last: None,
+            since_stats: 0,
+            running: true,
+            liveness,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            producers: Arc::new(AtomicUsize::new(0)),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+}
+
+/// The counters exposed by `GET /metrics`, if any.
+#[derive(Clone, Debug, Default)]
+struct Exposed {
+    stats: Option<AlgoStats>,
+    #[cfg(feature = "metrics")]
+    service: Option<Metrics>,
+}
+
+impl Exposed {
+    /// Checks whether there is anything to expose.
+    fn is_empty(&self) -> bool {
+        #[cfg(feature = "metrics")]
+        if self.service.is_some() {
+            return false;
+        }
+        self.stats.is_none()
+    }
+}
+
+/// A peer that receives models, with its count of unanswered pings and of consecutive failed writes.
+/// Its socket is non blocking, a failed write is a write that would block.
+struct Peer {
+    websocket: WebSocket<Stream>,
+    missed: usize,
+    failures: usize,
+}
+
+/// How often the model peers are pinged and how many missed pongs or failed writes they are allowed.
+#[derive(Clone, Copy)]
+struct Liveness {
+    ping_every: Duration,
+    max_missed: usize,
+}
+
+impl Liveness {
+    /// Reads the ping interval in seconds from the `PING_INTERVAL` environment variable
+    /// and the allowed number of missed pongs from the `MAX_MISSED_PONGS` environment variable.
+    fn from_env() -> Self {
+        let ping_every = env::var("PING_INTERVAL")
+            .ok()
+            .and_then(|every| every.parse().ok())
+            .unwrap_or(DEFAULT_PING_INTERVAL);
+        let max_missed = env::var("MAX_MISSED_PONGS")
+            .ok()
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(DEFAULT_MAX_MISSED);
+        Self {
+            ping_every: Duration::from_secs(ping_every.max(1)),
+            max_missed,
+        }
+    }
+}
+
+/// A handle on a service started by [backend_with_handle].
+/// Dropping the handle leaves the service running.
+pub struct ServiceHandle {
+    addr: Option<SocketAddr>,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ServiceHandle {
+    /// Stops accepting connections, sends a close frame to the model and stats peers,
+    /// then waits for the server and the dispatcher threads to end.
+    /// The port is released when this function returns.
+    /// The points iterator ends when the clients sending points disconnect,
+    /// and writing a model fails once the service is shut down.
+    pub fn shutdown(self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(mut addr) = self.addr {
+            // wakes up the server blocked on accept
+            match addr {
+                SocketAddr::V4(_) if addr.ip().is_unspecified() => {
+                    addr.set_ip(Ipv4Addr::LOCALHOST.into())
+                }
+                SocketAddr::V6(_) if addr.ip().is_unspecified() => {
+                    addr.set_ip(Ipv6Addr::LOCALHOST.into())
+                }
+                _ => {}
+            }
+            let _ = TcpStream::connect(addr);
+        }
+        for thread in self.threads {
+            if thread.join().is_err() {
+                eprintln!("service thread panicked.");
+            }
+        }
+    }
+}
+
+/// The configuration of the service, i.e. the token that the clients must present, the control endpoint,
+/// the counters exposed by `GET /metrics` and, when the `tls` feature is enabled, the certificate.
+/// The options compose, e.g. a service can be secured by a token and tls and expose its metrics.
+/// Every `backend*` function starts from [ServiceConfig::from_env] and sets its own option.
+/// ```no_run
+/// use std::sync::{atomic::AtomicBool, Arc};
+///
+/// use fluent_data::{algorithm::AlgoStats, service::ServiceConfig};
+///
+/// let frozen = Arc::new(AtomicBool::new(false));
+/// let (points, write) = ServiceConfig::from_env()
+///     .with_auth_token("my-secret-token")
+///     .with_control(frozen)
+///     .with_stats(AlgoStats::new())
+///     .serve("127.0.0.1:9001")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ServiceConfig {
+    auth_token: Option<String>,
+    frozen: Option<Arc<AtomicBool>>,
+    metrics: Exposed,
+    #[cfg(feature = "tls")]
+    tls: Option<(PathBuf, PathBuf)>,
+    max_sessions: Option<usize>,
+    session_idle: Option<Duration>,
+}
+
+impl ServiceConfig {
+    /// Builds a configuration that accepts any client and exposes no counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the token from the `FLUENT_DATA_TOKEN` environment variable, if set and not empty.
+    pub fn from_env() -> Self {
+        let auth_token = env::var("FLUENT_DATA_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        Self {
+            auth_token,
+            ..Self::default()
+        }
+    }
+
+    /// Only accepts the clients that present the given token, in an `Authorization: Bearer <token>` header
+    /// or a `?token=<token>` query parameter.
+    /// The other clients get a `401 Unauthorized` response and their websockets are not upgraded.
+    /// The websocket endpoints and the model polled over http are protected, the health check is not.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Adds a control endpoint, e.g. ws://127.0.0.1:9001/ws/control, that sets or resets the given flag,
+    /// see [backend_with_control].
+    pub fn with_control(mut self, frozen: Arc<AtomicBool>) -> Self {
+        self.frozen = Some(frozen);
+        self
+    }
+
+    /// Exposes the counters of the algorithm at `GET /metrics`, see [backend_with_metrics].
+    pub fn with_stats(mut self, stats: AlgoStats) -> Self {
+        self.metrics.stats = Some(stats);
+        self
+    }
+
+    /// Exposes the operational metrics of the service at `GET /metrics`, see [metrics](crate::metrics):
+    /// `fluent_data_points_received_total`, `fluent_data_parse_failures_total`, `fluent_data_models_broadcast_total`,
+    /// `fluent_data_point_producers`, `fluent_data_model_consumers` and the `fluent_data_fit_latency_seconds` histogram,
+    /// along with the fitted points and the active balls if the counters of the algorithm are not exposed.
+    /// The same metrics must be given to the streamer, see [Streamer::with_metrics](crate::Streamer::with_metrics).
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics.service = Some(metrics);
+        self
+    }
+
+    /// Serves secure websockets with the certificate and the private key read from PEM files,
+    /// the key must be in PKCS #8 format. The files are read when the service starts.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.tls = Some((cert_path.into(), key_path.into()));
+        self
+    }
+
+    /// Limits the number of keys of a [multi_backend], 64 by default.
+    /// The clients that use a new key once the limit is reached get a close frame with the code 1013 (try again later).
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = Some(max_sessions);
+        self
+    }
+
+    /// Drops the session of a key of a [multi_backend], i.e. its streamer, its dispatcher and its model,
+    /// once no client is connected to the key and it was not used for the given duration, 10 minutes by default.
+    /// The idle sessions are dropped when a client connects.
+    pub fn with_session_idle(mut self, idle: Duration) -> Self {
+        self.session_idle = Some(idle);
+        self
+    }
+
+    /// Starts a backend like [backend] with this configuration that listens on the given address.
+    /// Fails if the certificate cannot be loaded or the address cannot be bound.
+    pub fn serve(self, addr: impl ToSocketAddrs) -> Result<Backend, Box<dyn Error>> {
+        self.listen(TcpListener::bind(addr)?)
+    }
+
+    /// Starts a backend like [ServiceConfig::serve] and also returns a handle that shuts it down,
+    /// see [backend_with_handle].
+    pub fn serve_with_handle(
+        self,
+        addr: impl ToSocketAddrs,
+    ) -> Result<(Points, ModelWriter, ServiceHandle), Box<dyn Error>> {
+        self.start(TcpListener::bind(addr)?)
+    }
+
+    /// Starts a backend like [ServiceConfig::serve] that reads and writes binary websocket frames,
+    /// see [msgpack_backend].
+    #[cfg(feature = "msgpack")]
+    pub fn serve_msgpack(
+        self,
+        addr: impl ToSocketAddrs,
+    ) -> Result<Backend<Vec<u8>>, Box<dyn Error>> {
+        self.listen(TcpListener::bind(addr)?)
+    }
+
+    /// Starts a service like [multi_backend] with this configuration that listens on the given address.
+    /// Fails if the certificate cannot be loaded or the address cannot be bound.
+    pub fn serve_sessions<Point, Make>(
+        self,
+        addr: impl ToSocketAddrs,
+        make_algo_model: Make,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + DeserializeOwned + 'static,
+        Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+    {
+        self.start_sessions(TcpListener::bind(addr)?, make_algo_model)
+    }
+
+    /// Starts the server on the given socket and returns the point iterator and the model writer.
+    fn listen<Data: Payload>(self, server: TcpListener) -> Result<Backend<Data>, Box<dyn Error>> {
+        let (points, write, _handle) = self.start(server)?;
+        Ok((points, write))
+    }
+
+    /// Starts the model dispatcher and the websocket server on the given socket,
+    /// and returns the point iterator, the model writer and the handle that shuts them down.
+    fn start<Data: Payload>(
+        self,
+        server: TcpListener,
+    ) -> Result<HandledBackend<Data>, Box<dyn Error>> {
+        let wrap = self.wrap()?;
+        let (point_producer, point_receiver) = mpsc::channel::<Data>();
+        let (model_producer, model_receiver) = mpsc::channel::<Data>();
+        let subscribers = Subscribers::new(Liveness::from_env());
+        #[cfg(feature = "metrics")]
+        let subscribers = Subscribers {
+            metrics: self.metrics.service.clone(),
+            ..subscribers
+        };
+        let peers = Arc::new(Mutex::new(subscribers));
+        let shutdown = peers.lock().unwrap().shutdown.clone();
+        let addr = server.local_addr().ok();
+        let dispatcher = start_dispatcher(peers.clone(), model_receiver, stats_every_env());
+        let websockets = thread::spawn(move || {
+            start_websockets(server, wrap, self, peers, point_producer);
+        });
+        let (points, write) = streamer::channels(point_receiver, model_producer);
+        let handle = ServiceHandle {
+            addr,
+            shutdown,
+            threads: vec![websockets, dispatcher],
+        };
+        Ok((Box::new(points), Box::new(write), handle))
+    }
+
+    /// Starts the thread that accepts the connections of a [multi_backend] on the given socket.
+    fn start_sessions<Point, Make>(
+        self,
+        server: TcpListener,
+        make_algo_model: Make,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + DeserializeOwned + 'static,
+        Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+    {
+        let wrap = self.wrap()?;
+        thread::spawn(move || start_sessions(server, wrap, self, Arc::new(make_algo_model)));
+        Ok(())
+    }
+
+    /// Builds the function that wraps the accepted tcp streams, in a tls session if a certificate is configured.
+    fn wrap(&self) -> Result<Wrap, Box<dyn Error>> {
+        #[cfg(feature = "tls")]
+        if let Some((cert_path, key_path)) = &self.tls {
+            let cert = std::fs::read(cert_path)?;
+            let key = std::fs::read(key_path)?;
+            let identity = native_tls::Identity::from_pkcs8(&cert, &key)?;
+            let acceptor = native_tls::TlsAcceptor::new(identity)?;
+            return Ok(Box::new(move |stream| {
+                Ok(Box::new(acceptor.accept(stream)?))
+            }));
+        }
+        Ok(plain())
+    }
+}
+
+/// The payload of the point and model messages: text by default or binary.
+trait Payload: Frame + AsRef<[u8]> + Clone + Send + 'static {
+    /// The content type of the models served over http.
+    const CONTENT_TYPE: &'static str;
+
+    /// Gets the payload of a data message, fails if the message type is not supported
+    /// or the message cannot be converted.
+    fn read(message: Message) -> Result<Self, Box<dyn Error>>;
+
+    /// Builds the message that carries the payload.
+    fn into_message(self) -> Message;
+
+    /// Decodes the json value carried by the payload.
+    fn to_json(&self) -> Result<Value, Box<dyn Error>>;
+
+    /// Splits a payload that carries several points, e.g. `[[1.0,1.0],[2.0,2.0]]`, into one payload per point.
+    /// Binary payloads carry a single point.
+    fn split(self) -> Result<Vec<Self>, Box<dyn Error>> {
+        Ok(vec![self])
+    }
+}
+
+impl Payload for String {
+    const CONTENT_TYPE: &'static str = "application/json";
+
+    fn read(message: Message) -> Result<Self, Box<dyn Error>> {
+        match message {
+            Message::Text(txt) => Ok(txt),
+            #[cfg(feature = "msgpack")]
+            Message::Binary(data) => Ok(rmp_serde::from_slice::<Value>(&data)?.to_string()),
+            _ => Err("unsupported binary message.".into()),
+        }
+    }
+
+    fn into_message(self) -> Message {
+        Message::Text(self)
+    }
+
+    fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(serde_json::from_str(self)?)
+    }
+
+    fn split(self) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut start = self.chars().filter(|c| !c.is_whitespace());
+        if start.next() != Some('[') || start.next() != Some('[') {
+            return Ok(vec![self]);
+        }
+        let points: Vec<Value> = serde_json::from_str(&self)?;
+        Ok(points.iter().map(Value::to_string).collect())
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Payload for Vec<u8> {
+    const CONTENT_TYPE: &'static str = "application/msgpack";
+
+    fn read(message: Message) -> Result<Self, Box<dyn Error>> {
+        match message {
+            Message::Binary(data) => Ok(data),
+            Message::Text(txt) => Ok(rmp_serde::to_vec(&serde_json::from_str::<Value>(&txt)?)?),
+            _ => Err("unsupported message.".into()),
+        }
+    }
+
+    fn into_message(self) -> Message {
+        Message::Binary(self)
+    }
 
-type Peers = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+    fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(rmp_serde::from_slice(self)?)
+    }
+}
 
-/// Starts a backend that accepts data on endpoint ws://0.0.0.0:9001/ws/points
-/// and dispatch models on endpoint ws://0.0.0.0:9001/ws/models.
+/// Starts a backend that accepts data on endpoint ws://127.0.0.1:9001/ws/points
+/// and dispatch models on endpoint ws://127.0.0.1:9001/ws/models.
+/// A text frame may carry a single point, e.g. `[1.0,1.0]`, or an array of points, e.g. `[[1.0,1.0],[2.0,2.0]]`.
+/// The last dispatched model is sent to the peers as soon as they connect to the models endpoint.
+/// It can also be polled on http://127.0.0.1:9001/model, which answers `204 No Content`
+/// until the first model is dispatched.
+/// The health check http://127.0.0.1:9001/health answers `{"status":"ok","ball_count":2}`
+/// with the number of balls of the last model, or `503 Service Unavailable` once the streamer stopped.
+///
+/// The endpoint ws://127.0.0.1:9001/ws/stats pushes a summary of the first model and then of every 100 models,
+/// e.g. `{"balls":2,"max_radius":2.0,"min_radius":1.0,"total_weight":3.5}`.
+/// The cadence can be changed by setting the `STATS_EVERY` environment variable.
+///
+/// The model and stats peers are pinged every 30 seconds and dropped after 3 unanswered pings,
+/// 3 consecutive writes that would block, a failed write or a close frame.
+/// The interval in seconds and the number of missed pongs can be changed by setting
+/// the `PING_INTERVAL` and `MAX_MISSED_PONGS` environment variables.
+/// The maximum radius is `null` while a ball has an infinite radius,
+/// and no summary is pushed for models that are not written as plain json arrays of balls, e.g. deltas.
 /// ```
 /// use std::{error::Error, io};
 ///
@@ -35,155 +620,1861 @@ type Peers = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
 /// fn main() -> Result<(), Box<dyn Error>> {
 ///     let algo = Algo::new(space::euclid_dist, space::real_combine);
 ///     let mut model = Model::new(space::euclid_dist);
-///     let (points, write) = service::backend();
+///     let (points, write) = service::backend()?;
 ///     let streamer = Streamer::new(points, write);
 ///     // this will endlessly consume data and produce models...
 ///     // Streamer::run(streamer, algo, &mut model)?;
 ///     Ok(())
 /// }
 /// ```
-/// The port can be changed by setting the `PORT` environment variable.
-pub fn backend() -> (
-    impl Iterator<Item = Result<String, Box<dyn Error>>>,
-    impl FnMut(String) -> Result<(), Box<dyn Error>>,
-) {
-    let (point_producer, point_receiver) = mpsc::channel::<String>();
-    let (model_producer, model_receiver) = mpsc::channel::<String>();
-    thread::spawn(move || start_server(point_producer, model_receiver));
-    streamer::channels(point_receiver, model_producer)
+/// The host and the port can be changed by setting the `HOST` and `PORT` environment variables,
+/// e.g. `HOST=0.0.0.0` accepts remote connections.
+/// When the `FLUENT_DATA_TOKEN` environment variable is set, only the clients presenting this token are accepted,
+/// see [ServiceConfig].
+/// Fails if the address cannot be bound, e.g. when the port is already in use.
+pub fn backend() -> Result<Backend, Box<dyn Error>> {
+    ServiceConfig::from_env().listen(bind_env()?)
 }
 
-/// Starts the model dispatcher and the websocket server.
-fn start_server(point_producer: Sender<String>, model_receiver: Receiver<String>) {
-    let peers: Peers = Arc::new(Mutex::new(vec![]));
-    start_dispatcher(peers.clone(), model_receiver);
-    start_websockets(peers.clone(), point_producer);
+/// Starts a backend like [backend] that listens on the given address,
+/// e.g. `("0.0.0.0", 9001)` to accept connections on all the interfaces of a container.
+/// The address is bound before the function returns, thus clients can connect right away.
+/// Fails if the address cannot be bound.
+/// ```no_run
+/// use fluent_data::service;
+///
+/// let (points, write) = service::backend_on("127.0.0.1:9101").unwrap();
+/// ```
+pub fn backend_on(addr: impl ToSocketAddrs) -> Result<Backend, Box<dyn Error>> {
+    ServiceConfig::from_env().serve(addr)
 }
 
-/// Starts the server that will accept websocket connections and listen for points.
-fn start_websockets(peers: Peers, point_producer: Sender<String>) {
-    let port = env::var("PORT").unwrap_or(String::from("9001"));
-    let endpoint = format!("0.0.0.0:{}", port);
-    let server = TcpListener::bind(endpoint).unwrap();
-    for stream in server.incoming() {
-        let (path, websocket) = get_websocket(stream);
-        if path.ends_with("/ws/points") {
-            handle_point_receiver(websocket, point_producer.clone());
-        } else if path.ends_with("/ws/models") {
-            handle_model_producer(websocket, peers.clone());
-        }
-    }
+/// Starts a backend like [backend] that listens on the given host and port,
+/// with an additional control endpoint, e.g. ws://127.0.0.1:9001/ws/control.
+/// The host `0.0.0.0` accepts connections from other machines.
+/// The control endpoint accepts `{"freeze":true}` or `{"freeze":false}` messages
+/// that set or reset the given flag, and answers with the current state, e.g. `{"freeze":true}`.
+/// The flag is meant to be passed to [Algo::with_freeze_flag](crate::Algo::with_freeze_flag).
+/// ```
+/// use std::sync::{atomic::AtomicBool, Arc};
+///
+/// use fluent_data::{service, space, Algo, Model, Streamer};
+///
+/// let frozen = Arc::new(AtomicBool::new(false));
+/// let algo = Algo::new(space::euclid_dist, space::real_combine).with_freeze_flag(frozen.clone());
+/// let mut model = Model::new(space::euclid_dist);
+/// let (points, write) = service::backend_with_control(frozen, "127.0.0.1", 9001).unwrap();
+/// let streamer = Streamer::new(points, write);
+/// // this will endlessly consume data and produce models...
+/// // Streamer::run(streamer, algo, &mut model).unwrap();
+/// ```
+pub fn backend_with_control(
+    frozen: Arc<AtomicBool>,
+    host: &str,
+    port: u16,
+) -> Result<Backend, Box<dyn Error>> {
+    ServiceConfig::from_env()
+        .with_control(frozen)
+        .listen(bind(host, port)?)
 }
 
-/// Gets the websocket struct and the associated query path.
-fn get_websocket(stream: Result<TcpStream, std::io::Error>) -> (String, WebSocket<TcpStream>) {
-    let mut path: String = String::new();
-    let callback = |req: &Request, response: Response| {
-        path = String::from(req.uri().path());
-        Ok(response)
-    };
-    let websocket = accept_hdr(stream.unwrap(), callback).unwrap();
-    (path, websocket)
+/// Starts a backend like [backend] with the given configuration,
+/// e.g. that only accepts the clients presenting a token.
+/// The clients that do not present the token get a `401 Unauthorized` response
+/// and their websockets are not upgraded.
+/// The `HOST` and `PORT` environment variables still apply, see [ServiceConfig::serve] to listen on a given address.
+/// ```no_run
+/// use fluent_data::service::{self, ServiceConfig};
+///
+/// let config = ServiceConfig::new().with_auth_token("my-secret-token");
+/// let (points, write) = service::backend_with_config(config).unwrap();
+/// ```
+pub fn backend_with_config(config: ServiceConfig) -> Result<Backend, Box<dyn Error>> {
+    config.listen(bind_env()?)
 }
 
-/// Registers that the peer ask for receiving models on dispatch.
-fn handle_model_producer(websocket: WebSocket<TcpStream>, peers: Peers) {
-    let mut peers = peers.lock().unwrap();
-    peers.push(websocket);
+/// Starts a backend like [backend] with an additional endpoint `GET /metrics`
+/// that exposes the given counters in the Prometheus text format:
+/// `fluent_data_points_processed_total`, `fluent_data_balls_active`,
+/// `fluent_data_merges_total` and `fluent_data_splits_total`.
+/// The counters are meant to be passed to [Algo::with_stats](crate::Algo::with_stats).
+/// ```no_run
+/// use fluent_data::{algorithm::AlgoStats, service, space, Algo, Model, Streamer};
+///
+/// let stats = AlgoStats::new();
+/// let algo = Algo::new(space::euclid_dist, space::real_combine).with_stats(stats.clone());
+/// let mut model = Model::new(space::euclid_dist);
+/// let (points, write) = service::backend_with_metrics(stats).unwrap();
+/// let streamer = Streamer::new(points, write);
+/// // this will endlessly consume data and produce models...
+/// // Streamer::run(streamer, algo, &mut model).unwrap();
+/// ```
+pub fn backend_with_metrics(stats: AlgoStats) -> Result<Backend, Box<dyn Error>> {
+    ServiceConfig::from_env()
+        .with_stats(stats)
+        .listen(bind_env()?)
 }
 
-/// Handles point listening and send them to the algorithm using the `point_producer` channel.
-fn handle_point_receiver(mut websocket: WebSocket<TcpStream>, point_producer: Sender<String>) {
-    thread::spawn(move || loop {
-        let msg = websocket.read_message();
-        match msg {
-            Ok(message) => {
-                if !read_point(message, &point_producer) {
-                    break;
-                }
-            }
-            Err(reason) => {
-                eprint!("{}", reason);
-                break;
-            }
-        };
-    });
+/// Starts a backend like [backend] and returns a handle that shuts it down,
+/// e.g. to embed the service in a larger application or to restart it on the same port.
+/// ```no_run
+/// use fluent_data::{service, space, Algo, Model, Streamer};
+///
+/// let (points, write, handle) = service::backend_with_handle().unwrap();
+/// let runner = std::thread::spawn(move || {
+///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+///     let mut model = Model::new(space::euclid_dist);
+///     let streamer = Streamer::new(points, write);
+///     Streamer::run(streamer, algo, &mut model).unwrap();
+/// });
+/// // later on...
+/// handle.shutdown();
+/// runner.join().unwrap();
+/// ```
+/// Fails if the address cannot be bound.
+pub fn backend_with_handle() -> Result<(Points, ModelWriter, ServiceHandle), Box<dyn Error>> {
+    ServiceConfig::from_env().start(bind_env()?)
 }
 
-/// Gets the point and send it to the algorithm.
-fn read_point(message: Message, point_producer: &Sender<String>) -> bool {
-    match message {
-        Message::Text(txt) => {
-            match point_producer.send(txt) {
-                Err(reason) => eprintln!("{:#?}", reason),
-                _ => {}
-            }
-            true
-        }
-        Message::Binary(_) => {
-            eprintln!("unsupported binary message.");
-            true
+/// Starts a service that fits one model per key, e.g. one model per sensor,
+/// on the address given by the `HOST` and `PORT` environment variables.
+/// Points are sent to ws://127.0.0.1:9001/ws/points/{key} and the models of the key are received
+/// from ws://127.0.0.1:9001/ws/models/{key}.
+/// The algorithm and the model of a key are built by the given function when a client first uses the key,
+/// then the model is fitted by a [Streamer](crate::Streamer) in its own thread.
+/// The un-keyed endpoints ws://127.0.0.1:9001/ws/points and ws://127.0.0.1:9001/ws/models use the `default` key,
+/// which is also the key of the model polled over http.
+/// At most 64 keys are served at a time and the session of a key is dropped, with its model,
+/// after 10 minutes without clients, see [ServiceConfig::with_max_sessions] and [ServiceConfig::with_session_idle].
+/// ```no_run
+/// use fluent_data::{service, space, Algo, Model};
+///
+/// service::multi_backend(|_key: &str| {
+///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+///     let model = Model::new(space::euclid_dist);
+///     (algo, model)
+/// })
+/// .unwrap();
+/// ```
+/// Fails if the address cannot be bound.
+pub fn multi_backend<Point, Make>(make_algo_model: Make) -> Result<(), Box<dyn Error>>
+where
+    Point: PartialEq + Serialize + DeserializeOwned + 'static,
+    Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+{
+    ServiceConfig::from_env().start_sessions(bind_env()?, make_algo_model)
+}
+
+/// Starts a service like [multi_backend] that listens on the given address.
+/// Fails if the address cannot be bound.
+pub fn multi_backend_on<Point, Make>(
+    addr: impl ToSocketAddrs,
+    make_algo_model: Make,
+) -> Result<(), Box<dyn Error>>
+where
+    Point: PartialEq + Serialize + DeserializeOwned + 'static,
+    Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+{
+    ServiceConfig::from_env().serve_sessions(addr, make_algo_model)
+}
+
+/// Starts a backend like [backend] with secure websockets, i.e. wss://127.0.0.1:9001/ws/points
+/// and wss://127.0.0.1:9001/ws/models.
+/// The certificate and its private key are read from PEM files, the key must be in PKCS #8 format.
+/// The `HOST` and `PORT` environment variables still apply.
+/// Fails if the certificate cannot be loaded or the address cannot be bound.
+/// ```no_run
+/// use fluent_data::service;
+///
+/// let (points, write) = service::tls_backend("cert.pem", "key.pem").unwrap();
+/// ```
+#[cfg(feature = "tls")]
+pub fn tls_backend(cert_path: &str, key_path: &str) -> Result<Backend, Box<dyn Error>> {
+    ServiceConfig::from_env()
+        .with_tls(cert_path, key_path)
+        .listen(bind_env()?)
+}
+
+/// Starts a backend like [backend] that reads and writes binary websocket frames,
+/// to be used with the [MsgPackCodec](crate::streamer::MsgPackCodec) codec.
+/// Points are MessagePack arrays of floats and models are written as MessagePack arrays of maps
+/// with center, radius and weight. Json text points are still accepted and converted.
+/// The `HOST` and `PORT` environment variables still apply.
+/// Fails if the address cannot be bound.
+/// ```no_run
+/// use fluent_data::{service, streamer::MsgPackCodec, Streamer};
+///
+/// let (points, write) = service::msgpack_backend().unwrap();
+/// let streamer = Streamer::new_with_codec(points, write, MsgPackCodec);
+/// ```
+#[cfg(feature = "msgpack")]
+pub fn msgpack_backend() -> Result<Backend<Vec<u8>>, Box<dyn Error>> {
+    ServiceConfig::from_env().listen(bind_env()?)
+}
+
+/// Binds the address given by the `HOST` and `PORT` environment variables.
+fn bind_env() -> Result<TcpListener, Box<dyn Error>> {
+    bind(&host_env(), port_env()?)
+}
+
+/// Reads the host from the `HOST` environment variable, 127.0.0.1 by default.
+fn host_env() -> String {
+    env::var("HOST").unwrap_or(String::from(DEFAULT_HOST))
+}
+
+/// Reads the port from the `PORT` environment variable, 9001 by default.
+fn port_env() -> Result<u16, Box<dyn Error>> {
+    match env::var("PORT") {
+        Ok(port) => Ok(port
+            .parse()
+            .map_err(|reason| format!("invalid port {}: {}", port, reason))?),
+        Err(_) => Ok(DEFAULT_PORT),
+    }
+}
+
+/// Binds the given host and port.
+fn bind(host: &str, port: u16) -> Result<TcpListener, Box<dyn Error>> {
+    let endpoint = format!("{}:{}", host, port);
+    let server = TcpListener::bind(&endpoint)
+        .map_err(|reason| format!("cannot bind {}: {}", endpoint, reason))?;
+    Ok(server)
+}
+
+/// Uses the accepted tcp streams as is.
+fn plain() -> Wrap {
+    Box::new(|stream| Ok(Box::new(stream)))
+}
+
+/// Starts the server that will accept websocket connections and listen for points,
+/// until the service is shut down.
+/// Each accepted connection is handled by its own thread, from the tls and websocket handshakes on,
+/// so that a slow client does not delay the others.
+fn start_websockets<Data: Payload>(
+    server: TcpListener,
+    wrap: Wrap,
+    config: ServiceConfig,
+    peers: Peers<Data>,
+    point_producer: Sender<Data>,
+) {
+    let (shutdown, producers) = {
+        let subscribers = peers.lock().unwrap();
+        (subscribers.shutdown.clone(), subscribers.producers.clone())
+    };
+    let wrap = Arc::new(wrap);
+    for stream in server.incoming() {
+        if shutdown.load(Ordering::Relaxed) {
+            break;
         }
-        Message::Close(_) => false,
-        _ => true,
+        let wrap = wrap.clone();
+        let config = config.clone();
+        let peers = peers.clone();
+        let point_producer = point_producer.clone();
+        let producers = producers.clone();
+        thread::spawn(move || {
+            let api_key = config.auth_token.as_deref();
+            let (path, websocket, tcp) = match get_connection(stream, &wrap, api_key) {
+                Ok(Connection::WebSocket(path, websocket, tcp)) => (path, *websocket, tcp),
+                Ok(Connection::Http(request, stream)) => {
+                    handle_http(request, stream, peers, config.metrics);
+                    return;
+                }
+                Err(reason) => {
+                    eprintln!("rejected connection: {}", reason);
+                    return;
+                }
+            };
+            if path.ends_with("/ws/points") {
+                handle_point_receiver(websocket, point_producer, producers);
+            } else if path.ends_with("/ws/models") {
+                handle_model_producer(websocket, tcp, peers);
+            } else if path.ends_with("/ws/stats") {
+                handle_stats_producer(websocket, tcp, peers);
+            } else if path.ends_with("/ws/control") {
+                if let Some(frozen) = &config.frozen {
+                    handle_control(websocket, frozen.clone());
+                }
+            }
+        });
     }
 }
 
-/// Starts the dispatcher that will handle peers which asked for receiving models on dispatch.
-fn start_dispatcher(peers: Peers, model_receiver: Receiver<String>) {
-    thread::spawn(move || {
-        for msg in model_receiver {
-            let mut peers = peers.lock().unwrap();
-            peers.retain_mut(|peer| send_model(peer, msg.clone()));
+/// Accepts the websocket connections of a [multi_backend] and dispatches them to the session of their key.
+/// Like in [start_websockets], each accepted connection is handled by its own thread.
+fn start_sessions<Point, Make>(
+    server: TcpListener,
+    wrap: Wrap,
+    config: ServiceConfig,
+    make_algo_model: Arc<Make>,
+) where
+    Point: PartialEq + Serialize + DeserializeOwned + 'static,
+    Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+{
+    let max_sessions = config.max_sessions.unwrap_or(DEFAULT_MAX_SESSIONS);
+    let idle = config.session_idle.unwrap_or(DEFAULT_SESSION_IDLE);
+    let sessions: Arc<Mutex<HashMap<String, Session>>> = Arc::new(Mutex::new(HashMap::new()));
+    let session = Arc::new(move |key: &str| {
+        let mut sessions = sessions.lock().unwrap();
+        sessions.retain(|other, session| other == key || !session.is_idle(idle));
+        if !sessions.contains_key(key) && sessions.len() >= max_sessions {
+            return None;
         }
+        let session = sessions
+            .entry(String::from(key))
+            .or_insert_with(|| Session::start(key, make_algo_model.clone()));
+        session.last_used = Instant::now();
+        Some(session.clone())
     });
+    let wrap = Arc::new(wrap);
+    for stream in server.incoming() {
+        let wrap = wrap.clone();
+        let config = config.clone();
+        let session = session.clone();
+        thread::spawn(move || {
+            let api_key = config.auth_token.as_deref();
+            let (path, websocket, tcp) = match get_connection(stream, &wrap, api_key) {
+                Ok(Connection::WebSocket(path, websocket, tcp)) => (path, *websocket, tcp),
+                Ok(Connection::Http(request, mut stream)) => {
+                    match session(DEFAULT_SESSION) {
+                        Some(session) => {
+                            handle_http(request, stream, session.peers, config.metrics)
+                        }
+                        None => {
+                            let response = http_response("503 Service Unavailable", None);
+                            if let Err(reason) = stream.write_all(&response) {
+                                eprintln!("{}", reason);
+                            }
+                        }
+                    }
+                    return;
+                }
+                Err(reason) => {
+                    eprintln!("rejected connection: {}", reason);
+                    return;
+                }
+            };
+            match session_endpoint(&path) {
+                Some((endpoint, key)) => match (endpoint, session(key)) {
+                    ("points", Some(session)) => {
+                        let producers = session.peers.lock().unwrap().producers.clone();
+                        handle_point_receiver(websocket, session.points, producers)
+                    }
+                    (_, Some(session)) => handle_model_producer(websocket, tcp, session.peers),
+                    (_, None) => reject_session(websocket, key),
+                },
+                None => eprintln!("rejected connection: unknown path {}", path),
+            }
+        });
+    }
 }
 
-/// Sends the message ti the peer.
-fn send_model(peer: &mut WebSocket<TcpStream>, msg: String) -> bool {
-    if peer.can_write() {
-        match peer.write_message(Message::Text(msg)) {
-            Err(reason) => eprintln!("{:#?}", reason),
-            _ => {}
-        };
-        true
-    } else {
-        false
+/// Closes the websocket of a client that uses a new key when the number of sessions is at its limit.
+fn reject_session(mut websocket: WebSocket<Stream>, key: &str) {
+    eprintln!("rejected connection: too many sessions for key {}", key);
+    let frame = CloseFrame {
+        code: CloseCode::Again,
+        reason: "too many sessions".into(),
+    };
+    if websocket.close(Some(frame)).is_ok() {
+        let _ = websocket.write_pending();
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::thread;
+/// The key of the session of the un-keyed endpoints of a [multi_backend].
+const DEFAULT_SESSION: &str = "default";
 
-    use crate::{algorithm::Algo, model::Model, service::backend, space, streamer::*};
-    use tungstenite::{connect, Message};
-    use url::Url;
+/// The number of keys of a [multi_backend] by default.
+const DEFAULT_MAX_SESSIONS: usize = 64;
 
-    #[test]
-    fn test_streamer() {
+/// The duration after which an unused session of a [multi_backend] is dropped by default.
+const DEFAULT_SESSION_IDLE: Duration = Duration::from_secs(600);
+
+/// The channels to the streamer of a key of a [multi_backend] and the instant it was last used.
+#[derive(Clone)]
+struct Session {
+    points: Sender<String>,
+    peers: Peers<String>,
+    last_used: Instant,
+}
+
+impl Session {
+    /// Starts the dispatcher and the streamer of the given key.
+    fn start<Point, Make>(key: &str, make_algo_model: Arc<Make>) -> Self
+    where
+        Point: PartialEq + Serialize + DeserializeOwned + 'static,
+        Make: Fn(&str) -> (Algo<Point>, Model<Point>) + Send + Sync + 'static,
+    {
+        let (point_producer, point_receiver) = mpsc::channel::<String>();
+        let (model_producer, model_receiver) = mpsc::channel::<String>();
+        let peers = Arc::new(Mutex::new(Subscribers::new(Liveness::from_env())));
+        start_dispatcher(peers.clone(), model_receiver, stats_every_env());
+        let key = String::from(key);
         thread::spawn(move || {
-            let algo = Algo::new(space::euclid_dist, space::real_combine);
-            let mut model = Model::new(space::euclid_dist);
-            let (points, write) = backend();
+            let (algo, mut model) = make_algo_model(&key);
+            let (points, write) = streamer::channels(point_receiver, model_producer);
             let streamer = Streamer::new(points, write);
-            Streamer::run(streamer, algo, &mut model).unwrap();
+            if let Err(reason) = Streamer::run(streamer, algo, &mut model) {
+                eprintln!("session {} stopped: {}", key, reason);
+            }
         });
-        let points_url = "ws://localhost:9001/ws/points";
-        let (mut points_socket, _resp) =
-            connect(Url::parse(points_url).unwrap()).expect("Can't connect");
-        let models_url = "ws://localhost:9001/ws/models";
-        let (mut models_socket, _resp) =
-            connect(Url::parse(models_url).unwrap()).expect("Can't connect");
+        Self {
+            points: point_producer,
+            peers,
+            last_used: Instant::now(),
+        }
+    }
+
+    /// Checks whether no point producer nor model peer is connected
+    /// and the session was not used for the given duration.
+    fn is_idle(&self, idle: Duration) -> bool {
+        let subscribers = self.peers.lock().unwrap();
+        self.last_used.elapsed() >= idle
+            && subscribers.producers.load(Ordering::Relaxed) == 0
+            && subscribers.peers.is_empty()
+            && subscribers.stats.is_empty()
+    }
+}
+
+/// Gets the endpoint, `points` or `models`, and the key of a [multi_backend] websocket path,
+/// e.g. `/ws/points/sensor-1`. The un-keyed paths get the default key.
+fn session_endpoint(path: &str) -> Option<(&str, &str)> {
+    for endpoint in ["points", "models"] {
+        let prefix = format!("/ws/{}", endpoint);
+        if path.ends_with(&prefix) {
+            return Some((endpoint, DEFAULT_SESSION));
+        }
+        if let Some((_, key)) = path.rsplit_once(&format!("{}/", prefix)) {
+            if !key.is_empty() && !key.contains('/') {
+                return Some((endpoint, key));
+            }
+        }
+    }
+    None
+}
+
+/// Gets the websocket struct and the associated query path,
+/// or the plain http request if the client does not ask for a websocket upgrade.
+/// Fails if the connection or the handshake fails, if the client does not send its request within
+/// the handshake timeout, counted from the accept so that a client trickling its request is dropped too,
+/// or if the client does not present the api key, in which case it gets a `401 Unauthorized` response.
+fn get_connection(
+    stream: Result<TcpStream, std::io::Error>,
+    wrap: &Wrap,
+    api_key: Option<&str>,
+) -> Result<Connection, Box<dyn Error>> {
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let tcp = stream?;
+    tcp.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let control = tcp.try_clone()?;
+    let mut stream = wrap(tcp)?;
+    let head = read_head(&mut stream, &control, deadline)?;
+    let request = HttpRequest::parse(&head, api_key);
+    if !request.upgrade {
+        return Ok(Connection::Http(request, stream));
+    }
+    if !request.authorized {
+        stream.write_all(&http_response("401 Unauthorized", None))?;
+        return Err("unauthorized websocket".into());
+    }
+    let stream: Stream = Box::new(Replay {
+        head: Cursor::new(head),
+        stream,
+    });
+    let mut path: String = String::new();
+    let callback = |req: &Request, response: Response| {
+        path = String::from(req.uri().path());
+        Ok(response)
+    };
+    let websocket = accept_hdr(stream, callback).map_err(|reason| reason.to_string())?;
+    control.set_read_timeout(None)?;
+    Ok(Connection::WebSocket(path, Box::new(websocket), control))
+}
+
+/// Reads the head of an http request, i.e. the request line and the headers.
+/// Fails if the connection is closed, if the head is too large or if it is not complete by the deadline:
+/// the read timeout of the given tcp stream is shortened before each read.
+fn read_head(
+    stream: &mut Stream,
+    tcp: &TcpStream,
+    deadline: Instant,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut head = vec![];
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_HEAD_SIZE {
+            return Err("request head too large".into());
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err("request not received within the handshake timeout".into());
+        }
+        tcp.set_read_timeout(Some(remaining))?;
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Err("connection closed before the end of the request".into());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(head)
+}
+
+/// Answers a plain http request in a new thread:
+/// `GET /model` returns the last dispatched model, or no content if no model was dispatched yet,
+/// `GET /health` returns the health of the service
+/// and `GET /metrics` returns the algorithm counters and the service metrics, if the service exposes them.
+/// The model and the metrics are only returned to the clients presenting the api key, if any,
+/// while the health is public so that probes need no token.
+fn handle_http<Data: Payload>(
+    request: HttpRequest,
+    mut stream: Stream,
+    peers: Peers<Data>,
+    metrics: Exposed,
+) {
+    thread::spawn(move || {
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            (_, path)
+                if (path.ends_with("/model")
+                    || path.ends_with("/metrics") && !metrics.is_empty())
+                    && !request.authorized =>
+            {
+                http_response("401 Unauthorized", None)
+            }
+            ("GET", path) if path.ends_with("/model") => model_response(&peers),
+            ("GET", path) if path.ends_with("/health") => health_response(&peers),
+            ("GET", path) if path.ends_with("/metrics") && !metrics.is_empty() => {
+                metrics_response(&metrics, &peers)
+            }
+            (_, path) if path.ends_with("/model") || path.ends_with("/health") => {
+                http_response("405 Method Not Allowed", None)
+            }
+            _ => http_response("404 Not Found", None),
+        };
+        if let Err(reason) = stream.write_all(&response).and_then(|_| stream.flush()) {
+            eprintln!("{}", reason);
+        }
+    });
+}
+
+/// Builds the response that contains the last dispatched model.
+fn model_response<Data: Payload>(peers: &Peers<Data>) -> Vec<u8> {
+    let last = peers.lock().unwrap().last.clone();
+    match last {
+        Some(model) => http_response("200 OK", Some((Data::CONTENT_TYPE, model.as_ref()))),
+        None => http_response("204 No Content", None),
+    }
+}
+
+/// Builds the health response `{"status":"ok","ball_count":2}` with the number of balls of the last model,
+/// or a `503 Service Unavailable` response with status `unavailable` once the streamer stopped.
+/// The ball count is `null` if the models are not written as plain json arrays of balls.
+fn health_response<Data: Payload>(peers: &Peers<Data>) -> Vec<u8> {
+    let (running, last) = {
+        let subscribers = peers.lock().unwrap();
+        (subscribers.running, subscribers.last.clone())
+    };
+    let ball_count = match last {
+        Some(model) => model
+            .to_json()
+            .ok()
+            .as_ref()
+            .and_then(model_stats)
+            .map_or(Value::Null, |stats| stats["balls"].clone()),
+        None => json!(0),
+    };
+    let (status, health) = match running {
+        true => ("200 OK", "ok"),
+        false => ("503 Service Unavailable", "unavailable"),
+    };
+    let body = json!({ "status": health, "ball_count": ball_count }).to_string();
+    http_response(status, Some(("application/json", body.as_bytes())))
+}
+
+/// Builds the response that exposes the counters in the Prometheus text exposition format.
+fn metrics_response<Data: Payload>(metrics: &Exposed, peers: &Peers<Data>) -> Vec<u8> {
+    let (producers, consumers) = {
+        let subscribers = peers.lock().unwrap();
+        let producers = subscribers.producers.load(Ordering::Relaxed);
+        (producers, subscribers.peers.len())
+    };
+    let body = render_metrics(metrics, producers, consumers);
+    http_response(
+        "200 OK",
+        Some(("text/plain; version=0.0.4", body.as_bytes())),
+    )
+}
+
+/// Writes the counters of the algorithm and the metrics of the service, if any,
+/// in the Prometheus text exposition format, with the given numbers of connected point producers and model consumers.
+/// The fitted points and the active balls are written once, from the counters of the algorithm if they are exposed.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+fn render_metrics(metrics: &Exposed, producers: usize, consumers: usize) -> String {
+    let mut counters = vec![];
+    if let Some(stats) = &metrics.stats {
+        counters.extend([
+            (
+                "points_processed_total",
+                "counter",
+                "Number of points fitted.",
+                stats.points(),
+            ),
+            (
+                "balls_active",
+                "gauge",
+                "Number of balls of the model.",
+                stats.balls(),
+            ),
+            (
+                "merges_total",
+                "counter",
+                "Number of balls merged into a neighbor.",
+                stats.merges(),
+            ),
+            (
+                "splits_total",
+                "counter",
+                "Number of balls created.",
+                stats.splits(),
+            ),
+        ]);
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(service) = &metrics.service {
+        if metrics.stats.is_none() {
+            counters.extend([
+                (
+                    "points_processed_total",
+                    "counter",
+                    "Number of points fitted.",
+                    service.fits(),
+                ),
+                (
+                    "balls_active",
+                    "gauge",
+                    "Number of balls of the model.",
+                    service.balls(),
+                ),
+            ]);
+        }
+        counters.extend([
+            (
+                "points_received_total",
+                "counter",
+                "Number of points received.",
+                service.points_received(),
+            ),
+            (
+                "parse_failures_total",
+                "counter",
+                "Number of points that could not be decoded or were rejected.",
+                service.parse_failures(),
+            ),
+            (
+                "models_broadcast_total",
+                "counter",
+                "Number of models dispatched to the model consumers.",
+                service.models_broadcast(),
+            ),
+            (
+                "point_producers",
+                "gauge",
+                "Number of connected point producers.",
+                producers as u64,
+            ),
+            (
+                "model_consumers",
+                "gauge",
+                "Number of connected model consumers.",
+                consumers as u64,
+            ),
+        ]);
+    }
+    let mut body = String::new();
+    for (name, kind, help, value) in counters {
+        let name = format!("fluent_data_{}", name);
+        body.push_str(&format!("# HELP {} {}\n", name, help));
+        body.push_str(&format!("# TYPE {} {}\n", name, kind));
+        body.push_str(&format!("{} {}\n", name, value));
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(service) = &metrics.service {
+        let name = "fluent_data_fit_latency_seconds";
+        body.push_str(&format!(
+            "# HELP {} Duration of the fit of a point.\n",
+            name
+        ));
+        body.push_str(&format!("# TYPE {} histogram\n", name));
+        for (le, count) in service.latency_buckets() {
+            body.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, le, count));
+        }
+        let count = service.fits();
+        body.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, count));
+        body.push_str(&format!("{}_sum {}\n", name, service.latency_sum()));
+        body.push_str(&format!("{}_count {}\n", name, count));
+    }
+    body
+}
+
+/// Builds an http response with the given status and optional content type and body.
+fn http_response(status: &str, content: Option<(&str, &[u8])>) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status).into_bytes();
+    match content {
+        Some((content_type, body)) => {
+            let headers = format!(
+                "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                content_type,
+                body.len()
+            );
+            response.extend_from_slice(headers.as_bytes());
+            response.extend_from_slice(body);
+        }
+        None => response.extend_from_slice(b"Content-Length: 0\r\n\r\n"),
+    }
+    response
+}
+
+/// Registers that the peer ask for receiving models on dispatch
+/// and sends it the last dispatched model if any.
+fn handle_model_producer<Data: Payload>(
+    websocket: WebSocket<Stream>,
+    tcp: TcpStream,
+    peers: Peers<Data>,
+) {
+    let mut peer = match Peer::new(websocket, tcp) {
+        Ok(peer) => peer,
+        Err(reason) => return eprintln!("{}", reason),
+    };
+    let mut subscribers = peers.lock().unwrap();
+    let max_missed = subscribers.liveness.max_missed;
+    if let Some(msg) = subscribers.last.clone() {
+        if !peer.send(msg, max_missed) {
+            return;
+        }
+    }
+    subscribers.peers.push(peer);
+}
+
+/// Registers that the peer ask for receiving the stats of the models on dispatch.
+fn handle_stats_producer<Data: Payload>(
+    websocket: WebSocket<Stream>,
+    tcp: TcpStream,
+    peers: Peers<Data>,
+) {
+    match Peer::new(websocket, tcp) {
+        Ok(peer) => peers.lock().unwrap().stats.push(peer),
+        Err(reason) => eprintln!("{}", reason),
+    }
+}
+
+/// Handles point listening and send them to the algorithm using the `point_producer` channel.
+/// The peer is counted in `producers` while it is connected.
+fn handle_point_receiver<Data: Payload>(
+    mut websocket: WebSocket<Stream>,
+    point_producer: Sender<Data>,
+    producers: Arc<AtomicUsize>,
+) {
+    producers.fetch_add(1, Ordering::Relaxed);
+    thread::spawn(move || {
+        loop {
+            let msg = websocket.read_message();
+            match msg {
+                Ok(message) => {
+                    if !read_point(message, &point_producer) {
+                        break;
+                    }
+                }
+                Err(reason) => {
+                    eprint!("{}", reason);
+                    break;
+                }
+            };
+        }
+        producers.fetch_sub(1, Ordering::Relaxed);
+    });
+}
+
+/// Gets the point and send it to the algorithm.
+fn read_point<Data: Payload>(message: Message, point_producer: &Sender<Data>) -> bool {
+    match message {
+        Message::Text(_) | Message::Binary(_) => {
+            match Data::read(message).and_then(Payload::split) {
+                Ok(points) => {
+                    for point in points {
+                        if let Err(reason) = point_producer.send(point) {
+                            eprintln!("{:#?}", reason);
+                        }
+                    }
+                }
+                Err(reason) => eprintln!("{}", reason),
+            }
+            true
+        }
+        Message::Close(_) => false,
+        _ => true,
+    }
+}
+
+/// Handles control messages and answers with the current state.
+fn handle_control(mut websocket: WebSocket<Stream>, frozen: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        match websocket.read_message() {
+            Ok(Message::Text(txt)) => {
+                let state = read_control(&txt, &frozen);
+                if let Err(reason) = websocket.write_message(Message::Text(state)) {
+                    eprintln!("{}", reason);
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(reason) => {
+                eprint!("{}", reason);
+                break;
+            }
+        }
+    });
+}
+
+/// Applies a control message to the freeze flag and returns the current state.
+fn read_control(message: &str, frozen: &AtomicBool) -> String {
+    match serde_json::from_str::<Value>(message) {
+        Ok(control) => match control.get("freeze").and_then(Value::as_bool) {
+            Some(freeze) => frozen.store(freeze, Ordering::Relaxed),
+            None => eprintln!("unsupported control message."),
+        },
+        Err(reason) => eprintln!("{}", reason),
+    }
+    json!({ "freeze": frozen.load(Ordering::Relaxed) }).to_string()
+}
+
+/// Starts the dispatcher that will handle peers which asked for receiving models on dispatch.
+/// The last model is kept for the peers that connect later.
+/// The stats of the first model and then of every `stats_every` models are sent to the stats peers.
+/// The peers are pinged periodically and dropped when they miss too many pongs or writes, or close.
+/// The peers are sent a close frame when the service shuts down.
+fn start_dispatcher<Data: Payload>(
+    peers: Peers<Data>,
+    model_receiver: Receiver<Data>,
+    stats_every: usize,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let (liveness, shutdown) = {
+            let subscribers = peers.lock().unwrap();
+            (subscribers.liveness, subscribers.shutdown.clone())
+        };
+        let mut last_ping = Instant::now();
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                let mut subscribers = peers.lock().unwrap();
+                let subscribers = &mut *subscribers;
+                for peers in [&mut subscribers.peers, &mut subscribers.stats] {
+                    peers.drain(..).for_each(|mut peer| peer.close());
+                }
+                break;
+            }
+            match model_receiver.recv_timeout(liveness.ping_every.min(SHUTDOWN_POLL)) {
+                Ok(msg) => dispatch(&peers, msg, stats_every),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if last_ping.elapsed() >= liveness.ping_every {
+                let mut subscribers = peers.lock().unwrap();
+                let subscribers = &mut *subscribers;
+                for peers in [&mut subscribers.peers, &mut subscribers.stats] {
+                    peers.retain_mut(|peer| peer.poll() && peer.ping(liveness.max_missed));
+                }
+                last_ping = Instant::now();
+            }
+        }
+        peers.lock().unwrap().running = false;
+    })
+}
+
+/// Sends the model to the model peers, and its stats to the stats peers if they are due.
+fn dispatch<Data: Payload>(peers: &Peers<Data>, msg: Data, stats_every: usize) {
+    let mut subscribers = peers.lock().unwrap();
+    let max_missed = subscribers.liveness.max_missed;
+    subscribers
+        .peers
+        .retain_mut(|peer| peer.poll() && peer.send(msg.clone(), max_missed));
+    if subscribers.since_stats == 0 && !subscribers.stats.is_empty() {
+        match get_stats(&msg) {
+            Ok(Some(stats)) => subscribers
+                .stats
+                .retain_mut(|peer| peer.poll() && peer.send(stats.clone(), max_missed)),
+            Ok(None) => {}
+            Err(reason) => eprintln!("{}", reason),
+        }
+    }
+    subscribers.since_stats = (subscribers.since_stats + 1) % stats_every;
+    subscribers.last = Some(msg);
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = &subscribers.metrics {
+        metrics.record_broadcast();
+    }
+}
+
+/// Gets the number of dispatched models between two stats messages
+/// from the `STATS_EVERY` environment variable.
+fn stats_every_env() -> usize {
+    env::var("STATS_EVERY")
+        .ok()
+        .and_then(|every| every.parse().ok())
+        .unwrap_or(DEFAULT_STATS_EVERY)
+        .max(1)
+}
+
+/// Computes the stats message of the given model message.
+/// Returns `None` if the model is not in the default json format, e.g. csv or deltas.
+fn get_stats<Data: Payload>(msg: &Data) -> Result<Option<Data>, Box<dyn Error>> {
+    model_stats(&msg.to_json()?)
+        .map(|stats| Data::from_json(&stats))
+        .transpose()
+}
+
+/// Summarizes a model: the number of balls, their total weight and their maximum and minimum radius,
+/// e.g. `{"balls":2,"max_radius":2.0,"min_radius":1.0,"total_weight":3.5}`.
+/// The maximum radius is `null` if a ball has an infinite radius, i.e. no radius.
+/// Models wrapped in an envelope are supported.
+fn model_stats(model: &Value) -> Option<Value> {
+    let model = model.get("model").unwrap_or(model);
+    let balls = model.as_array()?;
+    let mut total_weight = 0.;
+    let mut radii = vec![];
+    for ball in balls {
+        total_weight += ball.get("weight")?.as_f64()?;
+        radii.push(ball.get("radius").and_then(Value::as_f64));
+    }
+    let finite = radii.iter().flatten().copied();
+    let max_radius = match radii.contains(&None) {
+        true => None,
+        false => finite.clone().reduce(f64::max),
+    };
+    let min_radius = finite.reduce(f64::min);
+    Some(json!({
+        "balls": balls.len(),
+        "total_weight": total_weight,
+        "max_radius": max_radius,
+        "min_radius": min_radius,
+    }))
+}
+
+impl Peer {
+    /// Builds a peer from the websocket and its tcp stream, which is made non blocking.
+    fn new(websocket: WebSocket<Stream>, tcp: TcpStream) -> io::Result<Self> {
+        tcp.set_nonblocking(true)?;
+        Ok(Self {
+            websocket,
+            missed: 0,
+            failures: 0,
+        })
+    }
+
+    /// Sends the message to the peer.
+    /// Returns false if the peer must be dropped, i.e. the write fails or too many writes would block.
+    fn send<Data: Payload>(&mut self, msg: Data, max_missed: usize) -> bool {
+        if !self.websocket.can_write() {
+            return false;
+        }
+        match self.websocket.write_message(msg.into_message()) {
+            Ok(()) => {
+                self.failures = 0;
+                true
+            }
+            Err(WsError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.failures += 1;
+                self.failures <= max_missed
+            }
+            Err(reason) => {
+                eprintln!("{}", reason);
+                false
+            }
+        }
+    }
+
+    /// Pings the peer.
+    /// Returns false if the peer must be dropped, i.e. it missed too many pongs or the write fails.
+    fn ping(&mut self, max_missed: usize) -> bool {
+        self.missed += 1;
+        if self.missed > max_missed {
+            return false;
+        }
+        match self.websocket.write_message(Message::Ping(vec![])) {
+            Ok(()) => true,
+            Err(WsError::Io(err)) => err.kind() == io::ErrorKind::WouldBlock,
+            Err(_) => false,
+        }
+    }
+
+    /// Sends a close frame to the peer, which is dropped without waiting for its answer.
+    fn close(&mut self) {
+        if self.websocket.close(None).is_ok() {
+            let _ = self.websocket.write_pending();
+        }
+    }
+
+    /// Reads the messages sent by the peer, i.e. pongs and close frames.
+    /// Returns false if the peer closed the connection or is gone.
+    fn poll(&mut self) -> bool {
+        loop {
+            match self.websocket.read_message() {
+                Ok(Message::Pong(_)) => self.missed = 0,
+                Ok(Message::Close(_)) => {
+                    let _ = self.websocket.write_pending();
+                    return false;
+                }
+                Ok(_) => {}
+                Err(WsError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        algorithm::Algo,
+        model::Model,
+        service::{
+            backend, backend_on, get_connection, handle_model_producer, model_stats, plain,
+            read_control, read_head, read_point, start_dispatcher, Connection, Liveness, Peers,
+            ServiceConfig, Subscribers, HANDSHAKE_TIMEOUT,
+        },
+        space,
+        streamer::*,
+    };
+    use serde_json::{json, Value};
+    use tungstenite::{connect, stream::MaybeTlsStream, Message, WebSocket};
+    use url::Url;
+
+    #[test]
+    fn test_streamer() {
+        let (points, write) = backend().unwrap();
+        run(points, write);
+        check_service(9001);
+    }
+
+    #[test]
+    fn test_backend_on() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        check_service(port);
+    }
+
+    #[test]
+    fn test_bad_connections() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        let garbage: [&[u8]; 3] = [
+            b"",
+            b"\x16\x03\x01\x02\x00\x01\x00\x01\xfc\x03\x03",
+            b"GET /ws/points HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\r\n",
+        ];
+        for bytes in garbage {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(bytes).unwrap();
+            drop(stream);
+        }
+        check_service(port);
+    }
+
+    #[test]
+    fn test_idle_client() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        // connects without sending a request, the other clients are served meanwhile
+        let idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let start = Instant::now();
+        check_service(port);
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT);
+        drop(idle);
+    }
+
+    #[test]
+    fn test_trickling_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut client = TcpStream::connect(addr).unwrap();
+            for _ in 0..20 {
+                if client.write_all(b"G").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let (tcp, _) = listener.accept().unwrap();
+        let control = tcp.try_clone().unwrap();
+        let mut stream = plain()(tcp).unwrap();
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(200);
+        assert!(read_head(&mut stream, &control, deadline).is_err());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_multi_backend() {
+        use crate::service::{multi_backend_on, session_endpoint};
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        multi_backend_on(("127.0.0.1", port), |_key: &str| {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let model = Model::new(space::euclid_dist);
+            (algo, model)
+        })
+        .unwrap();
+        let connect_to = |path: &str| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            connect(Url::parse(&url).unwrap()).unwrap().0
+        };
+        let mut models_a = connect_to("/ws/models/a");
+        let mut models_b = connect_to("/ws/models/b");
+        let mut points_a = connect_to("/ws/points/a");
+        let mut points_b = connect_to("/ws/points/b");
+        points_a.write_message(Message::from("[1.0]")).unwrap();
+        points_b.write_message(Message::from("[5.0]")).unwrap();
+        assert_eq!(
+            r#"[{"center":[1.0],"weight":0.0}]"#,
+            models_a.read_message().unwrap().into_text().unwrap()
+        );
+        assert_eq!(
+            r#"[{"center":[5.0],"weight":0.0}]"#,
+            models_b.read_message().unwrap().into_text().unwrap()
+        );
+        points_a.write_message(Message::from("[3.0]")).unwrap();
+        assert_eq!(
+            r#"[{"center":[3.0],"radius":2.0,"weight":1.0}]"#,
+            models_a.read_message().unwrap().into_text().unwrap()
+        );
+        check_sockets(connect_to("/ws/models"), connect_to("/ws/points"));
+        assert_eq!(Some(("points", "default")), session_endpoint("/ws/points"));
+        assert_eq!(
+            Some(("models", "s-1")),
+            session_endpoint("/api/ws/models/s-1")
+        );
+        assert_eq!(None, session_endpoint("/ws/models/a/b"));
+        assert_eq!(None, session_endpoint("/ws/other/a"));
+    }
+
+    #[test]
+    fn test_multi_backend_sessions() {
+        use tungstenite::protocol::frame::coding::CloseCode;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        ServiceConfig::new()
+            .with_auth_token("secret")
+            .with_max_sessions(1)
+            .with_session_idle(Duration::ZERO)
+            .start_sessions(server, |_key: &str| {
+                let algo = Algo::new(space::euclid_dist, space::real_combine);
+                let model = Model::new(space::euclid_dist);
+                (algo, model)
+            })
+            .unwrap();
+        let connect_to = |path: &str| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            connect(Url::parse(&url).unwrap())
+                .map(|(socket, _resp)| socket)
+                .map_err(|err| err.to_string())
+        };
+        assert!(connect_to("/ws/points/a").is_err());
+        let mut points_a = connect_to("/ws/points/a?token=secret").unwrap();
+        let mut points_b = connect_to("/ws/points/b?token=secret").unwrap();
+        match points_b.read_message() {
+            Ok(Message::Close(Some(frame))) => assert_eq!(CloseCode::Again, frame.code),
+            other => panic!("unexpected message {:?}", other),
+        }
+        points_a.close(None).unwrap();
+        while points_a.read_message().is_ok() {}
+        // the point receiver of the key a ends right after the close handshake
+        thread::sleep(Duration::from_millis(100));
+        let models_b = connect_to("/ws/models/b?token=secret").unwrap();
+        let points_b = connect_to("/ws/points/b?token=secret").unwrap();
+        check_sockets(models_b, points_b);
+    }
+
+    #[test]
+    fn test_backend_on_all_interfaces() {
+        let port = TcpListener::bind("0.0.0.0:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("0.0.0.0", port)).unwrap();
+        run(points, write);
+        check_service(port);
+    }
+
+    #[test]
+    fn test_last_model_on_connect() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        let mut dispatched = None;
+        for point in ["[1.0,1.0]", "[2.0,1.5]", "[1.5,0.5]"] {
+            points_socket
+                .write_message(Message::Text(point.into()))
+                .unwrap();
+            dispatched = Some(models_socket.read_message().unwrap());
+        }
+        let (mut late_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let result = late_socket.read_message().unwrap();
+        assert_eq!(dispatched, Some(result));
+        models_socket.close(None).unwrap();
+        late_socket.close(None).unwrap();
+        points_socket.close(None).unwrap();
+    }
+
+    #[test]
+    fn test_http_model() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        let response = http_get(port, "/model");
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        points_socket
+            .write_message(Message::Text("[1.0,1.0]".into()))
+            .unwrap();
+        let dispatched = models_socket.read_message().unwrap().into_text().unwrap();
+        let response = http_get(port, "/model");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", dispatched)));
+        assert!(http_get(port, "/other").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(http_get(port, "/metrics").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = http_get(port, "/health");
+        assert!(response.ends_with(r#"{"ball_count":1,"status":"ok"}"#));
+        models_socket.close(None).unwrap();
+        points_socket.close(None).unwrap();
+    }
+
+    #[test]
+    fn test_health() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        let response = http_get(port, "/health");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"ball_count":0,"status":"ok"}"#));
+        drop(points);
+        drop(write);
+        let stopped = (0..100).any(|_| {
+            thread::sleep(std::time::Duration::from_millis(10));
+            http_get(port, "/health").starts_with("HTTP/1.1 503 Service Unavailable\r\n")
+        });
+        assert!(stopped);
+    }
+
+    #[test]
+    fn test_backend_with_metrics() {
+        use crate::algorithm::AlgoStats;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let stats = AlgoStats::new();
+        let (points, write) = ServiceConfig::new()
+            .with_stats(stats.clone())
+            .listen(server)
+            .unwrap();
+        thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine).with_stats(stats);
+            let mut model = Model::new(space::euclid_dist);
+            let streamer = Streamer::new(points, write);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+        });
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) = connect(Url::parse(&models_url).unwrap()).unwrap();
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) = connect(Url::parse(&points_url).unwrap()).unwrap();
+        for point in ["[1]", "[2]", "[1000]"] {
+            points_socket.write_message(Message::from(point)).unwrap();
+            models_socket.read_message().unwrap();
+        }
+        let response = http_get(port, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("# TYPE fluent_data_points_processed_total counter\n"));
+        assert!(response.contains("\nfluent_data_points_processed_total 3\n"));
+        assert!(response.contains("\nfluent_data_balls_active 2\n"));
+        assert!(response.contains("\nfluent_data_merges_total 0\n"));
+        assert!(response.contains("\nfluent_data_splits_total 2\n"));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_service_metrics() {
+        use crate::metrics::Metrics;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let metrics = Metrics::new();
+        let (points, write) = ServiceConfig::new()
+            .with_metrics(metrics.clone())
+            .listen(server)
+            .unwrap();
+        thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let mut model = Model::new(space::euclid_dist);
+            let streamer = Streamer::new(points, write)
+                .with_error_handler(|_| {})
+                .with_metrics(metrics);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+        });
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) = connect(Url::parse(&models_url).unwrap()).unwrap();
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) = connect(Url::parse(&points_url).unwrap()).unwrap();
+        points_socket.write_message(Message::from("[1,")).unwrap();
+        for point in ["[1]", "[2]", "[1000]"] {
+            points_socket.write_message(Message::from(point)).unwrap();
+            models_socket.read_message().unwrap();
+        }
+        let response = http_get(port, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("# TYPE fluent_data_points_received_total counter\n"));
+        assert!(response.contains("\nfluent_data_points_received_total 4\n"));
+        assert!(response.contains("\nfluent_data_parse_failures_total 1\n"));
+        assert!(response.contains("\nfluent_data_models_broadcast_total 3\n"));
+        assert!(response.contains("\nfluent_data_point_producers 1\n"));
+        assert!(response.contains("\nfluent_data_model_consumers 1\n"));
+        assert!(response.contains("\nfluent_data_balls_active 2\n"));
+        assert!(response.contains("# TYPE fluent_data_fit_latency_seconds histogram\n"));
+        assert!(response.contains("\nfluent_data_fit_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(response.contains("\nfluent_data_fit_latency_seconds_count 3\n"));
+        assert!(response.contains("\nfluent_data_points_processed_total 3\n"));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_render_metrics() {
+        use std::time::Duration;
+
+        use crate::{
+            algorithm::AlgoStats,
+            metrics::Metrics,
+            service::{render_metrics, Exposed},
+        };
+
+        let stats = AlgoStats::new();
+        let service = Metrics::new();
+        service.record_point();
+        service.record_point();
+        service.record_parse_failure();
+        service.record_broadcast();
+        service.record_fit(Duration::from_micros(50), 3);
+        service.record_fit(Duration::from_secs(2), 4);
+        let metrics = Exposed {
+            stats: None,
+            service: Some(service.clone()),
+        };
+        let body = render_metrics(&metrics, 1, 2);
+        assert!(body.contains("# TYPE fluent_data_points_received_total counter\n"));
+        assert!(body.contains("\nfluent_data_points_received_total 2\n"));
+        assert!(body.contains("\nfluent_data_parse_failures_total 1\n"));
+        assert!(body.contains("\nfluent_data_models_broadcast_total 1\n"));
+        assert!(body.contains("\nfluent_data_point_producers 1\n"));
+        assert!(body.contains("\nfluent_data_model_consumers 2\n"));
+        assert!(body.contains("\nfluent_data_points_processed_total 2\n"));
+        assert!(body.contains("\nfluent_data_balls_active 4\n"));
+        assert!(body.contains("# TYPE fluent_data_fit_latency_seconds histogram\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_bucket{le=\"0.00001\"} 0\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_bucket{le=\"0.0001\"} 1\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_sum 2.00005\n"));
+        assert!(body.contains("\nfluent_data_fit_latency_seconds_count 2\n"));
+        let metrics = Exposed {
+            stats: Some(stats),
+            service: Some(service),
+        };
+        let body = render_metrics(&metrics, 1, 2);
+        assert_eq!(
+            1,
+            body.matches("# TYPE fluent_data_balls_active gauge\n")
+                .count()
+        );
+        assert_eq!(
+            1,
+            body.matches("# TYPE fluent_data_points_processed_total counter\n")
+                .count()
+        );
+        assert!(body.contains("\nfluent_data_points_processed_total 0\n"));
+        assert!(body.contains("\nfluent_data_merges_total 0\n"));
+        assert!(body.contains("\nfluent_data_points_received_total 2\n"));
+    }
+
+    #[test]
+    fn test_backend_with_auth() {
+        use tungstenite::{client::IntoClientRequest, Error as WsError};
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let (points, write) = ServiceConfig::new()
+            .with_auth_token("secret")
+            .listen(server)
+            .unwrap();
+        run(points, write);
+        let connect_with = |path: &str, token: Option<&str>| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            let mut request = url.into_client_request().unwrap();
+            if let Some(token) = token {
+                let header = format!("Bearer {}", token).parse().unwrap();
+                request.headers_mut().insert("Authorization", header);
+            }
+            connect(request).map_err(|err| match err {
+                WsError::Http(response) => response.status().as_u16(),
+                _ => 0,
+            })
+        };
+        for token in [None, Some("wrong")] {
+            assert_eq!(401, connect_with("/ws/points", token).unwrap_err());
+        }
+        assert!(http_get(port, "/model").starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        let (models_socket, _resp) = connect_with("/ws/models", Some("secret")).unwrap();
+        let (points_socket, _resp) = connect_with("/ws/points", Some("secret")).unwrap();
+        check_sockets(models_socket, points_socket);
+    }
+
+    #[test]
+    fn test_service_config_token() {
+        let start = |config: ServiceConfig| {
+            let server = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = server.local_addr().unwrap().port();
+            let (points, write) = config.listen(server).unwrap();
+            run(points, write);
+            port
+        };
+        let connect_to = |port: u16, path: &str| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            connect(Url::parse(&url).unwrap()).map_err(|err| err.to_string())
+        };
+        let port = start(ServiceConfig::new().with_auth_token("s3cr3t/+"));
+        for query in ["", "?token=wrong", "?token=s3cr3t/+", "?other=s3cr3t%2F%2B"] {
+            assert!(connect_to(port, &format!("/ws/points{}", query)).is_err());
+            assert!(connect_to(port, &format!("/ws/models{}", query)).is_err());
+        }
+        let (models_socket, _resp) = connect_to(port, "/ws/models?token=s3cr3t%2F%2B").unwrap();
+        let (points_socket, _resp) = connect_to(port, "/ws/points?token=s3cr3t%2F%2B").unwrap();
+        check_sockets(models_socket, points_socket);
+        let port = start(ServiceConfig::default());
+        let (models_socket, _resp) = connect_to(port, "/ws/models").unwrap();
+        let (points_socket, _resp) = connect_to(port, "/ws/points?token=any").unwrap();
+        check_sockets(models_socket, points_socket);
+    }
+
+    #[test]
+    fn test_service_config_compose() {
+        use crate::algorithm::AlgoStats;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let frozen = Arc::new(AtomicBool::new(false));
+        let (points, write) = ServiceConfig::new()
+            .with_auth_token("secret")
+            .with_control(frozen.clone())
+            .with_stats(AlgoStats::new())
+            .listen(server)
+            .unwrap();
+        run(points, write);
+        let connect_to = |path: &str| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            connect(Url::parse(&url).unwrap())
+                .map(|(socket, _resp)| socket)
+                .map_err(|err| err.to_string())
+        };
+        assert!(connect_to("/ws/control").is_err());
+        let mut control = connect_to("/ws/control?token=secret").unwrap();
+        control
+            .write_message(Message::from(r#"{"freeze":true}"#))
+            .unwrap();
+        control.read_message().unwrap();
+        assert!(frozen.load(Ordering::Relaxed));
+        assert!(http_get(port, "/metrics").starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        let metrics = http_get(port, "/metrics?token=secret");
+        assert!(metrics.contains("\nfluent_data_points_processed_total 0\n"));
+        let models_socket = connect_to("/ws/models?token=secret").unwrap();
+        let points_socket = connect_to("/ws/points?token=secret").unwrap();
+        check_sockets(models_socket, points_socket);
+    }
+
+    fn http_get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_backend_on_used_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(backend_on(("127.0.0.1", port)).is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_backend() {
+        use tungstenite::{client_tls_with_config, Connector};
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let (points, write) = ServiceConfig::new()
+            .with_tls("tests/fixtures/cert.pem", "tests/fixtures/key.pem")
+            .listen(server)
+            .unwrap();
+        run(points, write);
+        let connect = |path: &str| {
+            let connector = native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .build()
+                .unwrap();
+            let url = format!("wss://localhost:{}{}", port, path);
+            let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let connector = Some(Connector::NativeTls(connector));
+            let (socket, _resp) = client_tls_with_config(url, stream, None, connector).unwrap();
+            socket
+        };
+        let models_socket = connect("/ws/models");
+        let points_socket = connect("/ws/points");
+        check_sockets(models_socket, points_socket);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_binary_points() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        let point = rmp_serde::to_vec(&vec![1.0, 1.0]).unwrap();
+        points_socket.write_message(Message::Binary(point)).unwrap();
+        let result = models_socket.read_message().unwrap();
+        assert_eq!(
+            r#"[{"center":[1.0,1.0],"weight":0.0}]"#,
+            result.into_text().unwrap()
+        );
+        models_socket.close(None).unwrap();
+        points_socket.close(None).unwrap();
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_backend() {
+        use serde_json::Value;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let (points, write) = ServiceConfig::new().listen::<Vec<u8>>(server).unwrap();
+        thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let mut model = Model::new(space::euclid_dist);
+            let streamer = Streamer::new_with_codec(points, write, MsgPackCodec);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+        });
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (mut models_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        let point = rmp_serde::to_vec(&vec![1.0, 1.0]).unwrap();
+        points_socket.write_message(Message::Binary(point)).unwrap();
+        let result = models_socket.read_message().unwrap();
+        let model: Value = rmp_serde::from_slice(&result.into_data()).unwrap();
+        assert_eq!(json!([{"center":[1.0,1.0],"weight":0.0}]), model);
+        models_socket.close(None).unwrap();
+        points_socket.close(None).unwrap();
+    }
+
+    fn run(
+        points: impl Iterator<Item = Result<String, Box<dyn Error>>> + Send + 'static,
+        write: impl FnMut(String) -> Result<(), Box<dyn Error>> + Send + 'static,
+    ) {
+        thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let mut model = Model::new(space::euclid_dist);
+            let streamer = Streamer::new(points, write);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+        });
+    }
+
+    fn check_service(port: u16) {
+        // connections are accepted in order: the models peer is registered
+        // before the points socket handshake completes
+        let models_url = format!("ws://127.0.0.1:{}/ws/models", port);
+        let (models_socket, _resp) =
+            connect(Url::parse(&models_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        check_sockets(models_socket, points_socket);
+    }
+
+    fn check_sockets<S: Read + Write>(
+        mut models_socket: WebSocket<S>,
+        mut points_socket: WebSocket<S>,
+    ) {
         points_socket
             .write_message(Message::Text("[1.0,1.0]".into()))
             .unwrap();
         let result = models_socket.read_message().unwrap();
         assert_eq!(
-            r#"[{"center":[1.0,1.0],"radius":null,"weight":0.0}]"#,
+            r#"[{"center":[1.0,1.0],"weight":0.0}]"#,
             result.into_text().unwrap()
         );
         models_socket.close(None).unwrap();
         points_socket.close(None).unwrap();
     }
+
+    #[test]
+    fn test_model_stats() {
+        let model = json!([
+            {"center": [1.0], "radius": 2.0, "weight": 1.5},
+            {"center": [5.0], "radius": 1.0, "weight": 2.0},
+        ]);
+        assert_eq!(
+            Some(json!({"balls":2,"max_radius":2.0,"min_radius":1.0,"total_weight":3.5})),
+            model_stats(&model)
+        );
+        let model = json!([{"center": [1.0], "weight": 0.0}]);
+        assert_eq!(
+            Some(json!({"balls":1,"max_radius":null,"min_radius":null,"total_weight":0.0})),
+            model_stats(&model)
+        );
+        let model = json!([{"center": [1.0], "radius": null, "weight": 0.0}]);
+        assert_eq!(
+            Some(json!({"balls":1,"max_radius":null,"min_radius":null,"total_weight":0.0})),
+            model_stats(&model)
+        );
+        assert_eq!(None, model_stats(&json!({"op": "snapshot", "balls": []})));
+    }
+
+    #[test]
+    fn test_stats_endpoint() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (points, write) = backend_on(("127.0.0.1", port)).unwrap();
+        run(points, write);
+        let stats_url = format!("ws://127.0.0.1:{}/ws/stats", port);
+        let (mut stats_socket, _resp) =
+            connect(Url::parse(&stats_url).unwrap()).expect("Can't connect");
+        let points_url = format!("ws://127.0.0.1:{}/ws/points", port);
+        let (mut points_socket, _resp) =
+            connect(Url::parse(&points_url).unwrap()).expect("Can't connect");
+        points_socket
+            .write_message(Message::Text("[1.0,1.0]".into()))
+            .unwrap();
+        let result = stats_socket.read_message().unwrap();
+        assert_eq!(
+            r#"{"balls":1,"max_radius":null,"min_radius":null,"total_weight":0.0}"#,
+            result.into_text().unwrap()
+        );
+        stats_socket.close(None).unwrap();
+        points_socket.close(None).unwrap();
+    }
+
+    #[test]
+    fn test_read_point_batch() {
+        let (point_producer, point_receiver) = mpsc::channel::<String>();
+        assert!(read_point(
+            Message::Text("[1.0,1.0]".into()),
+            &point_producer
+        ));
+        assert!(read_point(
+            Message::Text(" [ [1,1], [2.5,-2.0] ] ".into()),
+            &point_producer
+        ));
+        assert!(read_point(Message::Text("[[1,1".into()), &point_producer));
+        assert!(!read_point(Message::Close(None), &point_producer));
+        drop(point_producer);
+        let points: Vec<_> = point_receiver.into_iter().collect();
+        assert_eq!(vec!["[1.0,1.0]", "[1,1]", "[2.5,-2.0]"], points);
+    }
+
+    #[test]
+    fn test_drop_dead_peers() {
+        let peers = Arc::new(Mutex::new(Subscribers::new(Liveness {
+            ping_every: Duration::from_secs(3600),
+            max_missed: 3,
+        })));
+        let (model_producer, model_receiver) = mpsc::channel::<String>();
+        start_dispatcher(peers.clone(), model_receiver, 1);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut alive = accept_model_peer(&listener, &peers);
+        let dead = accept_model_peer(&listener, &peers);
+        assert_eq!(2, peers.lock().unwrap().peers.len());
+        drop(dead);
+        let mut count = 0;
+        while peers.lock().unwrap().peers.len() > 1 && count < 100 {
+            model_producer.send(format!("[{}]", count)).unwrap();
+            let msg = alive.read_message().unwrap();
+            assert_eq!(format!("[{}]", count), msg.into_text().unwrap());
+            count += 1;
+        }
+        assert_eq!(1, peers.lock().unwrap().peers.len());
+        model_producer.send(String::from("[]")).unwrap();
+        assert_eq!("[]", alive.read_message().unwrap().into_text().unwrap());
+    }
+
+    #[test]
+    fn test_drop_silent_peers() {
+        let peers = Arc::new(Mutex::new(Subscribers::new(Liveness {
+            ping_every: Duration::from_millis(10),
+            max_missed: 2,
+        })));
+        let (_model_producer, model_receiver) = mpsc::channel::<String>();
+        start_dispatcher(peers.clone(), model_receiver, 1);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _silent = accept_model_peer(&listener, &peers);
+        assert_eq!(1, peers.lock().unwrap().peers.len());
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(0, peers.lock().unwrap().peers.len());
+    }
+
+    fn accept_model_peer(
+        listener: &TcpListener,
+        peers: &Peers<String>,
+    ) -> WebSocket<MaybeTlsStream<TcpStream>> {
+        let url = format!("ws://{}/ws/models", listener.local_addr().unwrap());
+        let client = thread::spawn(move || connect(Url::parse(&url).unwrap()).unwrap().0);
+        let stream = listener.accept().map(|(stream, _)| stream);
+        match get_connection(stream, &plain(), None) {
+            Ok(Connection::WebSocket(_, websocket, tcp)) => {
+                handle_model_producer(*websocket, tcp, peers.clone())
+            }
+            _ => panic!("websocket expected"),
+        }
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn test_control_endpoint() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let frozen = Arc::new(AtomicBool::new(false));
+        let (points, write) = ServiceConfig::new()
+            .with_control(frozen.clone())
+            .listen(server)
+            .unwrap();
+        thread::spawn(move || {
+            let algo = Algo::new(space::euclid_dist, space::real_combine).with_freeze_flag(frozen);
+            let mut model = Model::new(space::euclid_dist);
+            let streamer = Streamer::new(points, write);
+            Streamer::run(streamer, algo, &mut model).unwrap();
+        });
+        let connect_to = |path: &str| {
+            let url = format!("ws://127.0.0.1:{}{}", port, path);
+            connect(Url::parse(&url).unwrap()).unwrap().0
+        };
+        let mut models_socket = connect_to("/ws/models");
+        let mut points_socket = connect_to("/ws/points");
+        let mut control_socket = connect_to("/ws/control");
+        let mut send = |point: &str| {
+            points_socket.write_message(Message::from(point)).unwrap();
+            let model = models_socket.read_message().unwrap().into_text().unwrap();
+            let model: Value = serde_json::from_str(&model).unwrap();
+            model.as_array().unwrap().len()
+        };
+        let mut control = |message: &str| {
+            control_socket
+                .write_message(Message::from(message))
+                .unwrap();
+            control_socket.read_message().unwrap().into_text().unwrap()
+        };
+        send("[1.0]");
+        assert_eq!(1, send("[2.0]"));
+        assert_eq!(r#"{"freeze":true}"#, control(r#"{"freeze":true}"#));
+        assert_eq!(1, send("[1000.0]"));
+        assert_eq!(r#"{"freeze":false}"#, control(r#"{"freeze":false}"#));
+        assert_eq!(2, send("[1000000.0]"));
+    }
+
+    #[test]
+    fn test_read_control() {
+        let frozen = AtomicBool::new(false);
+        assert_eq!(
+            r#"{"freeze":true}"#,
+            read_control(r#"{"freeze":true}"#, &frozen)
+        );
+        assert!(frozen.load(Ordering::Relaxed));
+        assert_eq!(
+            r#"{"freeze":true}"#,
+            read_control(r#"{"other":1}"#, &frozen)
+        );
+        assert_eq!(r#"{"freeze":true}"#, read_control("not json", &frozen));
+        assert_eq!(
+            r#"{"freeze":false}"#,
+            read_control(r#"{"freeze":false}"#, &frozen)
+        );
+        assert!(!frozen.load(Ordering::Relaxed));
+    }
 }
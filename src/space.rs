@@ -1,11 +1,35 @@
 //! This module defines the necessary functions to run the algorithm for data points that belong to R^n.
 //!  - the Euclidian distance function
 //!  - the vectorial barycentre function
+//!
+//! The [manhattan_dist] and [manhattan_combine] functions are an alternative for heavy-tailed data,
+//! which is less sensitive to outliers.
+//! The [cosine_dist] and [cosine_combine] functions compare the directions of the points, whatever their norm,
+//! e.g. for text embeddings.
+//! The [haversine_dist] and [geo_combine] functions work with geographic coordinates `[latitude, longitude]` in degrees,
+//! e.g. for GPS fixes.
+//!
+//! The [array_euclid_dist] and [array_combine] functions are the Euclidian distance and the barycentre
+//! for fixed-size points `[f64; N]`, which avoid a heap allocation per point for low-dimensional streams.
+//! With the `ndarray` feature, the [nd_euclid_dist] and [nd_combine] functions are the same for `ndarray::Array1<f64>` points.
+//!
+//! The checked variants [euclid_dist_checked] and [real_combine_checked]
+//! fail with a [DimensionMismatch] error when points do not have the same dimension.
+//! The [check_finite] function rejects points with NaN or infinite coordinates with a [NonFinite] error.
+
+use std::{error::Error, fmt::Display};
+
+#[cfg(feature = "ndarray")]
+use ndarray::{Array1, Zip};
 
 /// A point in R^n.
 pub type RealPoint = Vec<f64>;
 
 /// Conputes the square of the Euclidian distance in R^n.
+///
+/// Points are expected to have the same dimension: otherwise the extra coordinates of the longest point are ignored,
+/// which silently corrupts the model. Use [euclid_dist_checked], or validate the points with [check_dim],
+/// to reject such points.
 pub fn euclid_dist(p1: &RealPoint, p2: &RealPoint) -> f64 {
     p1.iter()
         .zip(p2)
@@ -17,6 +41,7 @@ pub fn euclid_dist(p1: &RealPoint, p2: &RealPoint) -> f64 {
 }
 
 /// Computes weighted center in a R^n vector space.
+/// Like [euclid_dist], the center has the dimension of the shortest point, see [real_combine_checked].
 pub fn real_combine(p1: &RealPoint, w1: f64, p2: &RealPoint, w2: f64) -> RealPoint {
     let w = w1 + w2;
     p1.iter()
@@ -25,6 +50,229 @@ pub fn real_combine(p1: &RealPoint, w1: f64, p2: &RealPoint, w2: f64) -> RealPoi
         .collect()
 }
 
+/// Computes the square of the Euclidian distance in R^N for fixed-size points.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+///
+/// let algo = Algo::new(space::array_euclid_dist, space::array_combine);
+/// let mut model = Model::new(space::array_euclid_dist);
+/// algo.fit(&mut model, [1., 2., 3.]);
+/// assert_eq!(&[1., 2., 3.], model.iter_balls().next().unwrap().center());
+/// ```
+pub fn array_euclid_dist<const N: usize>(p1: &[f64; N], p2: &[f64; N]) -> f64 {
+    p1.iter()
+        .zip(p2)
+        .map(|(x1, x2)| {
+            let d = x1 - x2;
+            d * d
+        })
+        .sum()
+}
+
+/// Computes weighted center in R^N for fixed-size points.
+pub fn array_combine<const N: usize>(p1: &[f64; N], w1: f64, p2: &[f64; N], w2: f64) -> [f64; N] {
+    let w = w1 + w2;
+    let mut center = [0.; N];
+    for ((c, x1), x2) in center.iter_mut().zip(p1).zip(p2) {
+        *c = (x1 * w1 + x2 * w2) / w;
+    }
+    center
+}
+
+/// Computes the square of the Euclidian distance in R^n for `ndarray` points.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+/// use ndarray::array;
+///
+/// let algo = Algo::new(space::nd_euclid_dist, space::nd_combine);
+/// let mut model = Model::new(space::nd_euclid_dist);
+/// algo.fit(&mut model, array![1., 2., 3.]);
+/// assert_eq!(&array![1., 2., 3.], model.iter_balls().next().unwrap().center());
+/// ```
+#[cfg(feature = "ndarray")]
+pub fn nd_euclid_dist(p1: &Array1<f64>, p2: &Array1<f64>) -> f64 {
+    Zip::from(p1).and(p2).fold(0., |acc, x1, x2| {
+        let d = x1 - x2;
+        acc + d * d
+    })
+}
+
+/// Computes weighted center in R^n for `ndarray` points.
+#[cfg(feature = "ndarray")]
+pub fn nd_combine(p1: &Array1<f64>, w1: f64, p2: &Array1<f64>, w2: f64) -> Array1<f64> {
+    let w = w1 + w2;
+    Zip::from(p1)
+        .and(p2)
+        .map_collect(|x1, x2| (x1 * w1 + x2 * w2) / w)
+}
+
+/// Computes the square of the Manhattan (L1) distance in R^n, i.e. the square of the sum of absolute differences.
+/// As the algorithm works with squared distances, the radius of a ball is a Manhattan distance:
+/// balls are diamonds rather than spheres.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+///
+/// let algo = Algo::new(space::manhattan_dist, space::manhattan_combine);
+/// let mut model = Model::new(space::manhattan_dist);
+/// ```
+pub fn manhattan_dist(p1: &RealPoint, p2: &RealPoint) -> f64 {
+    let d: f64 = p1.iter().zip(p2).map(|(x1, x2)| (x1 - x2).abs()).sum();
+    d * d
+}
+
+/// Computes weighted center in R^n for the Manhattan distance.
+/// The center that minimizes the Manhattan distance to a set of points is their median,
+/// which cannot be updated incrementally: the weighted average is an approximation of it.
+pub fn manhattan_combine(p1: &RealPoint, w1: f64, p2: &RealPoint, w2: f64) -> RealPoint {
+    real_combine(p1, w1, p2, w2)
+}
+
+/// Computes the cosine distance in R^n as the square of the Euclidian distance between the normalized points,
+/// i.e. `2 * (1 - cos(p1, p2))`, which ranges from 0 for points in the same direction to 4 for opposite points.
+/// The zero vector is orthogonal to any other point.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+///
+/// assert_eq!(0., space::cosine_dist(&vec![1., 1.], &vec![3., 3.]));
+/// let algo = Algo::new(space::cosine_dist, space::cosine_combine);
+/// let mut model = Model::new(space::cosine_dist);
+/// ```
+pub fn cosine_dist(p1: &RealPoint, p2: &RealPoint) -> f64 {
+    let norm = |p: &RealPoint| p.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let (n1, n2) = (norm(p1), norm(p2));
+    if n1 == 0. || n2 == 0. {
+        return if n1 == n2 { 0. } else { 2. };
+    }
+    let dot: f64 = p1.iter().zip(p2).map(|(x1, x2)| x1 * x2).sum();
+    (2. * (1. - dot / (n1 * n2))).max(0.)
+}
+
+/// Computes weighted center in R^n for the cosine distance.
+/// The direction of the weighted average of two points lies between their directions, closer to the heavier point.
+pub fn cosine_combine(p1: &RealPoint, w1: f64, p2: &RealPoint, w2: f64) -> RealPoint {
+    real_combine(p1, w1, p2, w2)
+}
+
+/// The mean radius of the Earth in kilometers.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Computes the square of the great-circle distance in kilometers between two points `[latitude, longitude]` given in degrees.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+///
+/// let paris = vec![48.8566, 2.3522];
+/// let london = vec![51.5072, -0.1276];
+/// assert!((space::haversine_dist(&paris, &london).sqrt() - 344.).abs() < 1.);
+/// let algo = Algo::new(space::haversine_dist, space::geo_combine);
+/// let mut model = Model::new(space::haversine_dist);
+/// ```
+pub fn haversine_dist(p1: &RealPoint, p2: &RealPoint) -> f64 {
+    let (lat1, lon1) = (p1[0].to_radians(), p1[1].to_radians());
+    let (lat2, lon2) = (p2[0].to_radians(), p2[1].to_radians());
+    let dlat = ((lat2 - lat1) / 2.).sin();
+    let dlon = ((lon2 - lon1) / 2.).sin();
+    let a = dlat * dlat + lat1.cos() * lat2.cos() * dlon * dlon;
+    let d = 2. * EARTH_RADIUS_KM * a.sqrt().min(1.).asin();
+    d * d
+}
+
+/// Computes weighted center on the sphere for points `[latitude, longitude]` given in degrees.
+/// The points are converted to 3-D unit vectors which weighted average is projected back on the sphere,
+/// thus the center is right across the antimeridian and near the poles, unlike the average of the coordinates.
+/// When the points are antipodal with the same weight the center is undefined and the first point is kept.
+pub fn geo_combine(p1: &RealPoint, w1: f64, p2: &RealPoint, w2: f64) -> RealPoint {
+    let to_vector = |p: &RealPoint| {
+        let (lat, lon) = (p[0].to_radians(), p[1].to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    };
+    let (v1, v2) = (to_vector(p1), to_vector(p2));
+    let [x, y, z] = [0, 1, 2].map(|i| v1[i] * w1 + v2[i] * w2);
+    let norm = (x * x + y * y).sqrt();
+    if norm.hypot(z) < 1E-12 * (w1 + w2).abs() {
+        return p1.clone();
+    }
+    vec![z.atan2(norm).to_degrees(), y.atan2(x).to_degrees()]
+}
+
+/// Error raised when two points of R^n do not have the same dimension.
+#[derive(Debug, PartialEq)]
+pub struct DimensionMismatch(pub usize, pub usize);
+
+impl Display for DimensionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dimension mismatch: {} != {}", self.0, self.1)
+    }
+}
+
+impl Error for DimensionMismatch {}
+
+/// Checks that two points of R^n have the same dimension.
+/// ```
+/// use fluent_data::{Algo, space};
+///
+/// let algo = Algo::new(space::euclid_dist, space::real_combine)
+///     .with_validation(space::check_dim);
+/// ```
+pub fn check_dim(p1: &RealPoint, p2: &RealPoint) -> Result<(), DimensionMismatch> {
+    if p1.len() == p2.len() {
+        Ok(())
+    } else {
+        Err(DimensionMismatch(p1.len(), p2.len()))
+    }
+}
+
+/// Error raised when a coordinate of a point is NaN or infinite, with the index of the coordinate.
+#[derive(Debug, PartialEq)]
+pub struct NonFinite(pub usize);
+
+impl Display for NonFinite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "non finite coordinate at index {}", self.0)
+    }
+}
+
+impl Error for NonFinite {}
+
+/// Checks that all the coordinates of a point of R^n are finite.
+/// A NaN coordinate would make all distances NaN and corrupt the model.
+///
+/// The check is not enabled by default: [Algo::new](crate::Algo::new) is generic over the point type
+/// and does not know the coordinates, and the check costs a pass over each point.
+/// Enable it with [Algo::with_point_validation](crate::Algo::with_point_validation) for noisy feeds,
+/// as the `fluent_data` binary does.
+/// ```
+/// use fluent_data::{Algo, Model, space};
+///
+/// let algo = Algo::new(space::euclid_dist, space::real_combine)
+///     .with_point_validation(space::check_finite);
+/// let mut model = Model::new(space::euclid_dist);
+/// assert!(algo.try_fit(&mut model, vec![f64::NAN]).is_err());
+/// assert_eq!(0, model.iter_balls().count());
+/// ```
+pub fn check_finite(p: &RealPoint) -> Result<(), NonFinite> {
+    match p.iter().position(|x| !x.is_finite()) {
+        Some(i) => Err(NonFinite(i)),
+        None => Ok(()),
+    }
+}
+
+/// Conputes the square of the Euclidian distance in R^n, checking dimensions.
+pub fn euclid_dist_checked(p1: &RealPoint, p2: &RealPoint) -> Result<f64, DimensionMismatch> {
+    check_dim(p1, p2)?;
+    Ok(euclid_dist(p1, p2))
+}
+
+/// Computes weighted center in a R^n vector space, checking dimensions.
+pub fn real_combine_checked(
+    p1: &RealPoint,
+    w1: f64,
+    p2: &RealPoint,
+    w2: f64,
+) -> Result<RealPoint, DimensionMismatch> {
+    check_dim(p1, p2)?;
+    Ok(real_combine(p1, w1, p2, w2))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::space::*;
@@ -42,4 +290,112 @@ mod tests {
         let c = real_combine(&vec![1., -1.2], 1., &vec![2.5, -0.9], 2.);
         assert_eq!(vec![2., -1.], c);
     }
+
+    #[test]
+    fn test_manhattan_dist() {
+        let d = manhattan_dist(&vec![1., 1.], &vec![0., 0.]);
+        assert_eq!(4., d);
+        let d = manhattan_dist(&vec![1., 3.], &vec![-1., 4.]);
+        assert_eq!(9., d);
+        assert_eq!(0., manhattan_dist(&vec![1., 3.], &vec![1., 3.]));
+    }
+
+    #[test]
+    fn test_cosine_dist() {
+        assert_eq!(0., cosine_dist(&vec![1., 0.], &vec![2., 0.]));
+        assert_eq!(2., cosine_dist(&vec![1., 0.], &vec![0., 3.]));
+        assert_eq!(4., cosine_dist(&vec![1., 0.], &vec![-1., 0.]));
+        assert_eq!(2., cosine_dist(&vec![1., 0.], &vec![0., 0.]));
+        assert_eq!(0., cosine_dist(&vec![0., 0.], &vec![0., 0.]));
+    }
+
+    #[test]
+    fn test_array() {
+        assert_eq!(5., array_euclid_dist(&[1., 3.], &[-1., 4.]));
+        assert_eq!([1.5, 3.25], array_combine(&[1., 3.], 3., &[3., 4.], 1.));
+        assert_eq!(
+            euclid_dist(&vec![1., 2., 3.], &vec![0., -1., 5.]),
+            array_euclid_dist(&[1., 2., 3.], &[0., -1., 5.])
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_ndarray() {
+        use ndarray::array;
+
+        assert_eq!(5., nd_euclid_dist(&array![1., 3.], &array![-1., 4.]));
+        assert_eq!(
+            array![1.5, 3.25],
+            nd_combine(&array![1., 3.], 3., &array![3., 4.], 1.)
+        );
+        assert_eq!(
+            euclid_dist(&vec![1., 2., 3.], &vec![0., -1., 5.]),
+            nd_euclid_dist(&array![1., 2., 3.], &array![0., -1., 5.])
+        );
+    }
+
+    #[test]
+    fn test_haversine_dist() {
+        let d = haversine_dist(&vec![0., 179.], &vec![0., -179.]).sqrt();
+        assert!((d - 222.39).abs() < 0.01);
+        assert!(haversine_dist(&vec![90., 0.], &vec![90., 180.]) < 1E-12);
+        let d = haversine_dist(&vec![89., 0.], &vec![89., 180.]).sqrt();
+        assert!((d - 222.39).abs() < 0.01);
+        let d = haversine_dist(&vec![90., 0.], &vec![-90., 0.]).sqrt();
+        assert!((d - std::f64::consts::PI * 6371.).abs() < 1E-6);
+    }
+
+    #[test]
+    fn test_haversine_cities() {
+        let cities = [
+            ([40.7128, -74.0060], [34.0522, -118.2437], 3936.),
+            ([48.8566, 2.3522], [51.5072, -0.1276], 344.),
+            ([-33.8688, 151.2093], [35.6762, 139.6503], 7826.),
+            ([21.3069, -157.8583], [-18.1416, 178.4419], 5089.),
+        ];
+        for (p1, p2, km) in cities {
+            let d = haversine_dist(&p1.to_vec(), &p2.to_vec()).sqrt();
+            assert!((d - km).abs() < km * 0.01, "{} != {}", d, km);
+            assert_eq!(d, haversine_dist(&p2.to_vec(), &p1.to_vec()).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_geo_combine() {
+        let c = geo_combine(&vec![10., 179.], 1., &vec![10., -179.], 1.);
+        assert!((c[0] - 10.).abs() < 0.01);
+        assert!((c[1].abs() - 180.).abs() < 1E-9);
+        let c = geo_combine(&vec![0., 170.], 3., &vec![0., -170.], 1.);
+        assert!((c[1] - 175.).abs() < 0.1);
+        let c = geo_combine(&vec![89., 0.], 1., &vec![89., 180.], 1.);
+        assert!((c[0] - 90.).abs() < 1E-9);
+        let c = geo_combine(&vec![0., 0.], 1., &vec![0., 180.], 1.);
+        assert_eq!(vec![0., 0.], c);
+    }
+
+    #[test]
+    fn test_check_finite() {
+        assert_eq!(Ok(()), check_finite(&vec![1., -3.]));
+        assert_eq!(Err(NonFinite(1)), check_finite(&vec![1., f64::NAN]));
+        assert_eq!(Err(NonFinite(0)), check_finite(&vec![f64::INFINITY, 2.]));
+        assert_eq!(
+            "non finite coordinate at index 2",
+            check_finite(&vec![1., 2., f64::NEG_INFINITY])
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_checked() {
+        let d = euclid_dist_checked(&vec![1., 3.], &vec![-1., 4.]);
+        assert_eq!(Ok(5.), d);
+        let d = euclid_dist_checked(&vec![1., 3., 1.], &vec![-1., 4.]);
+        assert_eq!(Err(DimensionMismatch(3, 2)), d);
+        let c = real_combine_checked(&vec![1., -1.2], 1., &vec![2.5, -0.9], 2.);
+        assert_eq!(Ok(vec![2., -1.]), c);
+        let c = real_combine_checked(&vec![1.], 1., &vec![2.5, -0.9], 2.);
+        assert_eq!(Err(DimensionMismatch(1, 2)), c);
+    }
 }
@@ -1,23 +1,220 @@
 //! The [Streamer] continuously consumes data points and produces models.
 //!
+//! Data points are decoded by a [Codec]: json arrays by default (see [JsonCodec])
+//! or comma separated values (see [CsvCodec]).
+//! When the `msgpack` feature is enabled, the `MsgPackCodec` decodes binary points
+//! and the models are written as MessagePack when the streamer frames are bytes (see [Frame]).
+//! Models are written in full after each point by default,
+//! or as changes since the previous write with [Streamer::with_deltas].
+//! The balls can be identified across models with [Streamer::with_ball_ids]
+//! and the neighborhood graph can be written with [Streamer::with_edges].
+//! They can be tagged with a sequence number by [Streamer::with_envelope].
+//!
 //! This module also provides the [stdio] function that builds
 //! a point iterator which reads the standard input and a
 //! write closure that writes to the standard output.
+//! The [csv_reader] function builds a point iterator from csv rows,
+//! the [csv_stdio] function reads and writes csv data on the standard input / output
+//! and the [ndjson_stdio] function writes one json line per ball.
+//! The [file_reader] and [file_writer] functions read points from a file and write models to a file.
+//! The [interruptible] function stops a point iterator on a flag, e.g. set by a signal handler.
+//! The [fanout] function combines several write closures into one.
+//! [Streamer::with_observer] gives access to the model after each fit, without parsing the output.
+//! [Streamer::with_assignment_writer] writes the ball which received each point.
+//! When the `metrics` feature is enabled, `Streamer::with_metrics` reports the received points,
+//! the parse failures, the ball count and the fit latency to the service metrics.
 
 use std::{
+    any::Any,
     error::Error,
-    io,
-    ops::Deref,
-    sync::mpsc::{Receiver, Sender},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Lines, StdinLock, Write},
+    iter,
+    ops::{Deref, Range},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
 use crate::{
-    algorithm::Algo,
-    model::{Ball, Model},
+    algorithm::{Algo, BatchStats, FitResult, Fitted},
+    model::{Ball, BallNode, Changes, Model},
+    space::{self, RealPoint},
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 
+/// Decodes the data points read by the [Streamer].
+/// The input is text by default, binary codecs decode `[u8]` inputs.
+pub trait Codec<Point, Input: ?Sized = str> {
+    /// Decodes a point from the given input.
+    /// Returns `None` if the input does not contain a point, e.g. a csv header line.
+    fn decode(&mut self, input: &Input) -> Result<Option<Point>, Box<dyn Error>>;
+}
+
+impl<Point, Input: ?Sized, C: Codec<Point, Input> + ?Sized> Codec<Point, Input> for Box<C> {
+    fn decode(&mut self, input: &Input) -> Result<Option<Point>, Box<dyn Error>> {
+        (**self).decode(input)
+    }
+}
+
+/// Decodes json points, e.g. `[1.0,2.5]` for a point of R^2.
+#[derive(Default)]
+pub struct JsonCodec;
+
+impl<Point: DeserializeOwned> Codec<Point> for JsonCodec {
+    fn decode(&mut self, input: &str) -> Result<Option<Point>, Box<dyn Error>> {
+        Ok(Some(serde_json::from_str(input)?))
+    }
+}
+
+/// Decodes comma separated values as points of R^n, e.g. `1.0,2.5` for a point of R^2.
+/// Rows are parsed like the [CsvReader] rows: blank lines and trailing delimiters are ignored.
+/// ```
+/// use fluent_data::{streamer::{self, CsvCodec}, Streamer};
+///
+/// let (points, write) = streamer::stdio();
+/// let streamer = Streamer::new_with_codec(points, write, CsvCodec::with_header());
+/// ```
+#[derive(Default)]
+pub struct CsvCodec {
+    skip: bool,
+}
+
+impl CsvCodec {
+    /// Builds a codec for csv data without a header line.
+    pub fn new() -> Self {
+        Self { skip: false }
+    }
+
+    /// Builds a codec for csv data that starts with a header line.
+    pub fn with_header() -> Self {
+        Self { skip: true }
+    }
+}
+
+impl Codec<RealPoint> for CsvCodec {
+    fn decode(&mut self, input: &str) -> Result<Option<RealPoint>, Box<dyn Error>> {
+        if self.skip {
+            self.skip = false;
+            return Ok(None);
+        }
+        parse_csv_row(input, ',', None)
+    }
+}
+
+/// Parses the selected cells of a csv row to a point, or `None` if the row is blank.
+/// Trailing delimiters are ignored.
+fn parse_csv_row(
+    line: &str,
+    delimiter: char,
+    columns: Option<&Range<usize>>,
+) -> Result<Option<RealPoint>, Box<dyn Error>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let line = line.trim_end().trim_end_matches(delimiter);
+    let cells: Vec<&str> = line.split(delimiter).collect();
+    let cells = match columns {
+        Some(columns) => cells.get(columns.clone()).ok_or_else(|| {
+            format!(
+                "expected columns {:?}, found {} columns",
+                columns,
+                cells.len()
+            )
+        })?,
+        None => &cells[..],
+    };
+    let point = cells
+        .iter()
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number: {:?}", v))
+        })
+        .collect::<Result<RealPoint, _>>()?;
+    Ok(Some(point))
+}
+
+/// Decodes MessagePack points, i.e. arrays of floats, read from binary frames.
+/// ```no_run
+/// use fluent_data::{service, streamer::MsgPackCodec, Streamer};
+///
+/// let (points, write) = service::msgpack_backend().unwrap();
+/// let streamer = Streamer::new_with_codec(points, write, MsgPackCodec);
+/// ```
+#[cfg(feature = "msgpack")]
+#[derive(Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl<Point: DeserializeOwned> Codec<Point, [u8]> for MsgPackCodec {
+    fn decode(&mut self, input: &[u8]) -> Result<Option<Point>, Box<dyn Error>> {
+        Ok(Some(rmp_serde::from_slice(input)?))
+    }
+}
+
+/// A frame read or written by the [Streamer]: a text line by default,
+/// or a binary message when the `msgpack` feature is enabled.
+/// Models written in binary frames are MessagePack arrays of maps with center, radius and weight.
+pub trait Frame: Deref + Sized {
+    /// Builds a frame that carries the given text, e.g. a csv model.
+    fn from_text(text: String) -> Result<Self, Box<dyn Error>>;
+
+    /// Builds a frame that carries the given json value, e.g. a json model.
+    fn from_json(value: &Value) -> Result<Self, Box<dyn Error>>;
+}
+
+impl Frame for String {
+    fn from_text(text: String) -> Result<Self, Box<dyn Error>> {
+        Ok(text)
+    }
+
+    fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Frame for Vec<u8> {
+    fn from_text(text: String) -> Result<Self, Box<dyn Error>> {
+        Ok(rmp_serde::to_vec(&text)?)
+    }
+
+    fn from_json(value: &Value) -> Result<Self, Box<dyn Error>> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+}
+
+type ErrorHandler = Box<dyn Fn(Box<dyn Error>)>;
+
+type ProgressHandler = Box<dyn Fn(usize)>;
+
+/// An observer of the fits, which downcasts the model and the ball which received the point
+/// to their actual types, see [Streamer::with_observer].
+type Observer = Box<dyn FnMut(&dyn Any, &dyn Any, BatchStats)>;
+
+/// Keeps a copy of a point before it is fitted, see [Streamer::with_assignment_writer].
+type KeepPoint = Box<dyn Fn(&dyn Any) -> Box<dyn Any>>;
+
+/// Writes the assignment of a fitted point given the model, the copy of the point
+/// and the ball which received the point, see [Streamer::with_assignment_writer].
+type WriteAssignment =
+    Box<dyn FnMut(&dyn Any, &dyn Any, &dyn Any, BatchStats) -> Result<(), Box<dyn Error>>>;
+
+/// The writer of the ball which received each point, see [Streamer::with_assignment_writer].
+struct Assignments {
+    keep: KeepPoint,
+    write: WriteAssignment,
+}
+
 /// Reads data from `In` and writes model to `Out`.
 /// ```
 /// use std::{error::Error, io};
@@ -33,13 +230,78 @@ use serde_json::{json, Map, Value};
 ///     Ok(())
 /// }
 /// ```
-pub struct Streamer<In, Out>
+pub struct Streamer<In, Out, C = JsonCodec, Data = String>
 where
-    In: Iterator<Item = Result<String, Box<dyn Error>>>,
-    Out: FnMut(String) -> Result<(), Box<dyn Error>>,
+    In: Iterator<Item = Result<Data, Box<dyn Error>>>,
+    Out: FnMut(Data) -> Result<(), Box<dyn Error>>,
 {
     points: In,
     write: Out,
+    codec: C,
+    step: usize,
+    min_interval: Option<Duration>,
+    format: Format,
+    envelope: Option<usize>,
+    on_error: Option<ErrorHandler>,
+    on_progress: Option<ProgressHandler>,
+    progress_every: usize,
+    stop_flag: Option<Arc<AtomicBool>>,
+    observer: Option<Observer>,
+    assignments: Option<Assignments>,
+    anomaly: Option<Anomaly>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+/// The format of the models written by the [Streamer].
+enum Format {
+    /// Full json models, see [serialize_model].
+    Json,
+    /// Full json models with the ball ids, see [Streamer::with_ball_ids].
+    JsonWithIds,
+    /// Full json models with the neighborhood graph, see [serialize_model_with_edges].
+    JsonWithEdges,
+    /// Json model changes, see [Streamer::with_deltas].
+    Deltas(Deltas),
+    /// Csv models with the given delimiter, see [serialize_model_csv].
+    Csv(char),
+}
+
+/// A serialized model, see [Streamer::with_envelope].
+enum Output {
+    Json(Value),
+    Text(String),
+}
+
+/// The state of the delta output mode, see [Streamer::with_deltas].
+struct Deltas {
+    snapshot_every: usize,
+    sent: usize,
+}
+
+/// The state of the anomaly detection, see [Streamer::with_anomaly_threshold]:
+/// whether a point fitted since the last write was anomalous and the lowest density of these points,
+/// if any point was fitted.
+struct Anomaly {
+    threshold: f64,
+    flagged: bool,
+    density: Option<f64>,
+}
+
+impl Anomaly {
+    /// Records the density of a point computed before it was fitted.
+    /// The point is anomalous if its density is below the threshold
+    /// and the model has at least one ball with a finite radius.
+    fn record(&mut self, density: f64, scored: bool) {
+        self.flagged |= scored && density < self.threshold;
+        self.density = Some(self.density.map_or(density, |lowest| lowest.min(density)));
+    }
+
+    /// Starts over after a write.
+    fn reset(&mut self) {
+        self.flagged = false;
+        self.density = None;
+    }
 }
 
 impl<In, Out> Streamer<In, Out>
@@ -47,30 +309,707 @@ where
     In: Iterator<Item = Result<String, Box<dyn Error>>>,
     Out: FnMut(String) -> Result<(), Box<dyn Error>>,
 {
-    /// builds a new streamer instance.
+    /// builds a new streamer instance that reads json points.
     pub fn new(points: In, write: Out) -> Self {
-        Self { points, write }
+        Self::new_with_codec(points, write, JsonCodec)
+    }
+}
+
+impl<In, Out, C, Data> Streamer<In, Out, C, Data>
+where
+    In: Iterator<Item = Result<Data, Box<dyn Error>>>,
+    Out: FnMut(Data) -> Result<(), Box<dyn Error>>,
+    Data: Frame,
+{
+    /// builds a new streamer instance that decodes points with the given codec.
+    pub fn new_with_codec(points: In, write: Out, codec: C) -> Self {
+        Self {
+            points,
+            write,
+            codec,
+            step: 1,
+            min_interval: None,
+            format: Format::Json,
+            envelope: None,
+            on_error: None,
+            on_progress: None,
+            progress_every: 1,
+            stop_flag: None,
+            observer: None,
+            assignments: None,
+            anomaly: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Handles points that cannot be decoded or are rejected by [Algo::try_fit] with the given handler
+    /// instead of failing. These points are skipped.
+    /// Errors raised by the `In` source or the `Out` sink still make the streamer fail.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write)
+    ///     .with_error_handler(|err| eprintln!("skipped point: {}", err));
+    /// ```
+    pub fn with_error_handler(mut self, handler: impl Fn(Box<dyn Error>) + 'static) -> Self {
+        self.on_error = Some(Box::new(handler));
+        self
+    }
+
+    /// Calls the given function with the number of points fitted so far
+    /// every [progress_every](Streamer::progress_every) points, e.g. to update a progress bar.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write)
+    ///     .with_progress(|count| eprintln!("{} points", count))
+    ///     .progress_every(10000);
+    /// ```
+    pub fn with_progress(mut self, f: impl Fn(usize) + 'static) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    /// Calls the given function with the model and the [FitResult] after each point is fitted,
+    /// before the model is serialized, e.g. to compute custom metrics or to push the model to a database.
+    /// The observer is called for every fitted point, even when the model is not written
+    /// because of [Streamer::emit_every] or [Streamer::emit_at_most].
+    /// The observer gets a shared reference and cannot change the model.
+    /// Panics when the streamer runs if the observer and the model do not have the same point type.
+    /// ```
+    /// use fluent_data::{algorithm::FitResult, streamer, Model, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_observer(
+    ///     |model: &Model<Vec<f64>>, result: &FitResult<Vec<f64>>| {
+    ///         if result.stats.created > 0 {
+    ///             eprintln!("{} balls", model.iter_balls().count());
+    ///         }
+    ///     },
+    /// );
+    /// ```
+    pub fn with_observer<Point, F>(mut self, mut f: F) -> Self
+    where
+        Point: PartialEq + Clone + 'static,
+        F: FnMut(&Model<Point>, &FitResult<Point>) + 'static,
+    {
+        self.observer = Some(Box::new(move |model, vertex, stats| {
+            let model = model
+                .downcast_ref::<Model<Point>>()
+                .expect("the observer and the model must have the same point type.");
+            let vertex = vertex
+                .downcast_ref::<Option<BallNode<Point>>>()
+                .expect("the observer and the model must have the same point type.");
+            f(model, &FitResult::new(vertex.as_ref(), stats));
+        }));
+        self
+    }
+
+    /// Writes the assignment of each fitted point to the given closure, as a json object
+    /// `{"point":[1.0],"ball_id":4,"dist":0.5,"created":false}`, before the model is possibly written.
+    /// `ball_id` is the id of the ball which received the point, as written by [Streamer::with_ball_ids],
+    /// `dist` is the normalized distance between the point and the center of that ball after the fit,
+    /// i.e. the squared distance divided by the squared radius, and `created` tells whether the fit created a ball.
+    /// `ball_id` and `dist` are null if the model is frozen and empty.
+    /// The closure must fit the point type of the model, otherwise the streamer panics.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write)
+    ///     .with_ball_ids()
+    ///     .with_assignment_writer::<Vec<f64>, _>(|assignment| {
+    ///         eprintln!("{}", assignment);
+    ///         Ok(())
+    ///     });
+    /// ```
+    pub fn with_assignment_writer<Point, F>(mut self, mut f: F) -> Self
+    where
+        Point: PartialEq + Clone + Serialize + 'static,
+        F: FnMut(String) -> Result<(), Box<dyn Error>> + 'static,
+    {
+        let keep: KeepPoint = Box::new(|point| {
+            let point = point
+                .downcast_ref::<Point>()
+                .expect("the assignment writer and the model must have the same point type.");
+            Box::new(point.clone())
+        });
+        let write: WriteAssignment = Box::new(move |model, point, vertex, stats| {
+            let model = model
+                .downcast_ref::<Model<Point>>()
+                .expect("the assignment writer and the model must have the same point type.");
+            let point = point
+                .downcast_ref::<Point>()
+                .expect("the assignment writer and the model must have the same point type.");
+            let vertex = vertex
+                .downcast_ref::<Option<BallNode<Point>>>()
+                .expect("the assignment writer and the model must have the same point type.");
+            let result = FitResult::new(vertex.as_ref(), stats);
+            let assignment = json!({
+                "point": point,
+                "ball_id": result.ball.as_ref().map(|ball| ball.id),
+                "dist": result.ball.as_ref().map(|ball| (model.dist)(point, ball)),
+                "created": stats.created > 0,
+            });
+            f(assignment.to_string())
+        });
+        self.assignments = Some(Assignments { keep, write });
+        self
+    }
+
+    /// Reports the progress every `n` points instead of after each point, see [Streamer::with_progress].
+    pub fn progress_every(mut self, n: usize) -> Self {
+        self.progress_every = n.max(1);
+        self
+    }
+
+    /// Stops reading points as soon as the given flag is set, e.g. by a signal handler.
+    /// The flag is checked between points, then the final model is written as usual.
+    /// Use [interruptible] to also stop while the point iterator waits for the next point.
+    /// ```
+    /// use std::sync::{atomic::AtomicBool, Arc};
+    ///
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let stop = Arc::new(AtomicBool::new(false));
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_stop_flag(stop.clone());
+    /// ```
+    pub fn with_stop_flag(mut self, stop_flag: Arc<AtomicBool>) -> Self {
+        self.stop_flag = Some(stop_flag);
+        self
+    }
+
+    /// Reports the received points, the points that cannot be decoded or are rejected,
+    /// the number of balls and the duration of each fit to the given metrics,
+    /// e.g. exposed by [ServiceConfig::with_metrics](crate::service::ServiceConfig::with_metrics).
+    /// ```no_run
+    /// use fluent_data::{metrics::Metrics, service::ServiceConfig, Streamer};
+    ///
+    /// let metrics = Metrics::new();
+    /// let (points, write) = ServiceConfig::from_env()
+    ///     .with_metrics(metrics.clone())
+    ///     .serve("127.0.0.1:9001")
+    ///     .unwrap();
+    /// let streamer = Streamer::new(points, write).with_metrics(metrics);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Logs points that cannot be decoded or are rejected to the standard error and skips them.
+    pub fn with_log_errors(self) -> Self {
+        self.with_error_handler(|err| eprintln!("{}", err))
+    }
+
+    /// Writes the model every `n` points instead of after each point.
+    /// The final model is always written when `In` source is exhausted.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).emit_every(100);
+    /// ```
+    pub fn emit_every(mut self, n: usize) -> Self {
+        self.step = n.max(1);
+        self
+    }
+
+    /// Writes the model at most once per `min_interval`, whatever the rate of incoming points.
+    /// The final model is always written when `In` source is exhausted.
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).emit_at_most(Duration::from_millis(100));
+    /// ```
+    pub fn emit_at_most(mut self, min_interval: Duration) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+
+    /// Writes only the changes of the model instead of the full model.
+    ///
+    /// The first message and then every `snapshot_every` messages are full snapshots:
+    /// `{"op":"snapshot","balls":[...]}` where each ball has an `id` field.
+    /// The other messages are deltas: `{"op":"delta","decay":0.9025,"balls":[...]}`
+    /// where each ball is either `{"op":"upsert","id":...,"center":...,"radius":...,"weight":...}`
+    /// for a created or updated ball or `{"op":"delete","id":...}` for a removed one.
+    /// The weight of the balls which are not in the delta must be multiplied by `decay`.
+    /// A zero `snapshot_every` only sends the first snapshot.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_deltas(1000);
+    /// ```
+    pub fn with_deltas(mut self, snapshot_every: usize) -> Self {
+        self.format = Format::Deltas(Deltas {
+            snapshot_every,
+            sent: 0,
+        });
+        self
+    }
+
+    /// Writes the full models with an `id` field for each ball, e.g. `{"center":[1.0],"radius":2.0,"weight":3.0,"id":4}`,
+    /// so that clients can track the balls across models.
+    /// A ball keeps its id until it is removed, and the remaining ball of a merge keeps its own id.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_ball_ids();
+    /// ```
+    pub fn with_ball_ids(mut self) -> Self {
+        self.format = Format::JsonWithIds;
+        self
+    }
+
+    /// Writes the full models with the edges of the neighborhood graph, e.g. to draw them,
+    /// see [serialize_model_with_edges].
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_edges();
+    /// ```
+    pub fn with_edges(mut self) -> Self {
+        self.format = Format::JsonWithEdges;
+        self
+    }
+
+    /// Wraps each written model in an envelope `{"model":...,"points":100,"seq":0}`
+    /// where `seq` is the sequence number of the model, starting at zero,
+    /// and `points` the number of points fitted so far, e.g. for replayable audit logs.
+    /// The model is the json value written without envelope, or a string for csv models.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_envelope();
+    /// ```
+    pub fn with_envelope(mut self) -> Self {
+        self.envelope = Some(0);
+        self
+    }
+
+    /// Flags the points that do not fit the current model, i.e. which [density](Model::density)
+    /// computed before they are fitted is below the given threshold.
+    /// Each written model is wrapped in an object `{"anomaly":true,"density":0.02,"model":...}`
+    /// where `anomaly` tells whether a point fitted since the previous write was anomalous
+    /// and `density` is the lowest density of these points.
+    /// `density` is omitted rather than written `null` if no point was fitted since the previous write.
+    /// The points are fitted anyway, so that the model keeps learning.
+    /// Points are not flagged until the model has a ball with a finite radius.
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_anomaly_threshold(0.01);
+    /// ```
+    pub fn with_anomaly_threshold(mut self, threshold: f64) -> Self {
+        self.anomaly = Some(Anomaly {
+            threshold,
+            flagged: false,
+            density: None,
+        });
+        self
+    }
+
+    /// Writes the models as comma separated values instead of json, see [serialize_model_csv].
+    /// ```
+    /// use fluent_data::{streamer, Streamer};
+    ///
+    /// let (points, write) = streamer::stdio();
+    /// let streamer = Streamer::new(points, write).with_csv_output();
+    /// ```
+    pub fn with_csv_output(self) -> Self {
+        self.with_csv_delimiter(',')
+    }
+
+    /// Writes the models as csv with the given delimiter instead of json, see [serialize_model_csv_with].
+    pub fn with_csv_delimiter(mut self, delimiter: char) -> Self {
+        self.format = Format::Csv(delimiter);
+        self
     }
 
     /// Infinitely reads points from `In` source and write model changes to `Out` sink.
-    pub fn run<Point: PartialEq + Serialize + DeserializeOwned + 'static>(
-        mut streamer: Streamer<In, Out>,
+    /// Fails when a point cannot be read or deserialized or is rejected by [Algo::try_fit],
+    /// unless an error handler is set by [Streamer::with_error_handler].
+    pub fn run<Point>(
+        streamer: Streamer<In, Out, C, Data>,
         algo: Algo<Point>,
         model: &mut Model<Point>,
-    ) -> Result<(), Box<dyn Error>> {
-        for input in streamer.points {
-            let point_str = input?;
-            let point: Point = serde_json::from_str(&point_str)?;
-            algo.fit(model, point);
-            let balls = serialize_model(model);
-            let output = serde_json::to_string(&balls)?;
-            (streamer.write)(output)?;
-        }
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+    {
+        streamer.stream(&algo, model, |_, _| false)?;
+        Ok(())
+    }
+
+    /// Infinitely reads points from `In` source and write model changes to `Out` sink every `emit_every` points.
+    /// Models are not serialized when they are not written.
+    /// This is the same as running a streamer built with [Streamer::emit_every].
+    pub fn run_throttled<Point>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        emit_every: usize,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+    {
+        Self::run(streamer.emit_every(emit_every), algo, model)
+    }
+
+    /// Infinitely reads points from `In` source and write model changes to `Out` sink,
+    /// flagging the points which density is below `threshold` as anomalous before they are fitted.
+    /// This is the same as running a streamer built with [Streamer::with_anomaly_threshold].
+    /// ```
+    /// use fluent_data::{Algo, Model, space, Streamer};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let points = ["[0.0]", "[1.0]", "[0.5]", "[100.0]"].map(|p| Ok(String::from(p)));
+    /// let mut flags = vec![];
+    /// let streamer = Streamer::new(points.into_iter(), |output: String| {
+    ///     let output: serde_json::Value = serde_json::from_str(&output)?;
+    ///     flags.push(output["anomaly"].as_bool().unwrap());
+    ///     Ok(())
+    /// });
+    /// Streamer::run_with_anomaly(streamer, algo, &mut model, 0.01).unwrap();
+    /// assert_eq!(vec![false, false, false, true], flags);
+    /// ```
+    pub fn run_with_anomaly<Point>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        threshold: f64,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+    {
+        Self::run(streamer.with_anomaly_threshold(threshold), algo, model)
+    }
+
+    /// Infinitely reads points from `In` source and write model changes to `Out` sink
+    /// at most once per `min_interval`.
+    /// Models are not serialized when they are not written.
+    /// This is the same as running a streamer built with [Streamer::emit_at_most].
+    pub fn run_rate_limited<Point>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        min_interval: Duration,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+    {
+        Self::run(streamer.emit_at_most(min_interval), algo, model)
+    }
+
+    /// Infinitely reads points from `In` source and write model changes to `Out` sink.
+    /// The `checkpoint` closure is called with the model each time `every` points have been fitted,
+    /// e.g. for persisting the model periodically. A zero `every` never calls `checkpoint`.
+    /// ```
+    /// use fluent_data::{Algo, Model, space, Streamer};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let points = (0..10).map(|i| Ok(format!("[{}.0]", i % 3)));
+    /// let streamer = Streamer::new(points, |_| Ok(()));
+    /// let mut weights = vec![];
+    /// let checkpoint = |model: &Model<Vec<f64>>| weights.push(model.total_weight());
+    /// Streamer::run_with_checkpoint(streamer, algo, &mut model, 4, checkpoint).unwrap();
+    /// // the model is checkpointed after the 4th and the 8th points, not at the end of the stream
+    /// let fitted = |n: usize| {
+    ///     let algo = Algo::new(space::euclid_dist, space::real_combine);
+    ///     let mut model = Model::new(space::euclid_dist);
+    ///     for i in 0..n {
+    ///         algo.fit(&mut model, vec![(i % 3) as f64]);
+    ///     }
+    ///     model.total_weight()
+    /// };
+    /// assert_eq!(vec![fitted(4), fitted(8)], weights);
+    /// ```
+    pub fn run_with_checkpoint<Point, Checkpoint>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        every: usize,
+        mut checkpoint: Checkpoint,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+        Checkpoint: FnMut(&Model<Point>),
+    {
+        streamer.stream(&algo, model, |model, count| {
+            if every > 0 && count % every == 0 {
+                checkpoint(model);
+            }
+            false
+        })?;
         Ok(())
     }
+
+    /// Reads at most `n` points from `In` source and write model changes to `Out` sink.
+    /// Returns the number of points actually read, which is less than `n` if `In` source is exhausted.
+    /// ```
+    /// use fluent_data::{Algo, Model, space, Streamer};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let points = vec![Ok("[1.0]".into()), Ok("[2.0]".into()), Ok("[1.5]".into())];
+    /// let streamer = Streamer::new(points.into_iter(), |_| Ok(()));
+    /// let count = Streamer::run_n(streamer, algo, &mut model, 2).unwrap();
+    /// assert_eq!(2, count);
+    /// ```
+    pub fn run_n<Point>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        n: usize,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+    {
+        if n == 0 {
+            return Ok(0);
+        }
+        streamer.stream(&algo, model, |_, count| count >= n)
+    }
+
+    /// Reads points from `In` source and write model changes to `Out` sink
+    /// until `condition` returns true, e.g. when the model has converged.
+    /// `condition` is evaluated after each point is fitted and the model possibly written.
+    /// Returns the number of points read, which is less than expected if `In` source is exhausted first.
+    /// ```
+    /// use fluent_data::{Algo, Model, space, Streamer};
+    ///
+    /// let algo = Algo::new(space::euclid_dist, space::real_combine);
+    /// let mut model = Model::new(space::euclid_dist);
+    /// let points = (0..10).map(|i| Ok(format!("[{}.0]", i % 2)));
+    /// let streamer = Streamer::new(points, |_| Ok(()));
+    /// let count = Streamer::run_until(streamer, algo, &mut model, |m| m.total_weight() > 3.).unwrap();
+    /// assert_eq!(5, count);
+    /// ```
+    pub fn run_until<Point, F>(
+        streamer: Streamer<In, Out, C, Data>,
+        algo: Algo<Point>,
+        model: &mut Model<Point>,
+        condition: F,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+        F: Fn(&Model<Point>) -> bool,
+    {
+        streamer.stream(&algo, model, |model, _| condition(model))
+    }
+
+    /// Reads points from `In` source and write model changes to `Out` sink
+    /// until `In` source is exhausted or `stop` returns true.
+    /// `stop` is called after each point is fitted and the model possibly written,
+    /// with the model and the count of points read so far.
+    /// Returns the number of points read.
+    fn stream<Point, Stop>(
+        mut self,
+        algo: &Algo<Point>,
+        model: &mut Model<Point>,
+        mut stop: Stop,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+        C: Codec<Point, Data::Target>,
+        Stop: FnMut(&Model<Point>, usize) -> bool,
+    {
+        if let Format::Deltas(_) = self.format {
+            model.track_changes();
+        }
+        let mut count = 0;
+        let mut last_emit: Option<Instant> = None;
+        let mut pending = false;
+        while !self.is_stopped() {
+            let point_str = match self.points.next() {
+                Some(input) => input?,
+                None => break,
+            };
+            #[cfg(feature = "metrics")]
+            let start = Instant::now();
+            let anomaly = &mut self.anomaly;
+            let assignments = &self.assignments;
+            let mut kept = None;
+            let fitted = self.codec.decode(&*point_str).and_then(|point| {
+                point
+                    .map(|p| {
+                        kept = assignments.as_ref().map(|a| (a.keep)(&p));
+                        Self::fit(algo, model, p, anomaly.as_mut())
+                    })
+                    .transpose()
+            });
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.metrics {
+                metrics.record_point();
+                match &fitted {
+                    Ok(Some(_)) => metrics.record_fit(start.elapsed(), model.iter_balls().count()),
+                    Ok(None) => {}
+                    Err(_) => metrics.record_parse_failure(),
+                }
+            }
+            match fitted {
+                Ok(Some((vertex, stats))) => {
+                    if let Some(observer) = self.observer.as_mut() {
+                        observer(&*model, &vertex, stats);
+                    }
+                    if let (Some(assignments), Some(point)) = (self.assignments.as_mut(), kept) {
+                        (assignments.write)(&*model, &*point, &vertex, stats)?;
+                    }
+                }
+                Ok(None) => continue,
+                Err(err) => match &self.on_error {
+                    Some(handler) => {
+                        handler(err);
+                        continue;
+                    }
+                    None => return Err(err),
+                },
+            }
+            count += 1;
+            pending = true;
+            let elapsed = match (self.min_interval, last_emit) {
+                (Some(min_interval), Some(last_emit)) => last_emit.elapsed() >= min_interval,
+                _ => true,
+            };
+            if count % self.step == 0 && elapsed {
+                self.emit(model, count)?;
+                last_emit = Some(Instant::now());
+                pending = false;
+            }
+            if let Some(progress) = &self.on_progress {
+                if count % self.progress_every == 0 {
+                    progress(count);
+                }
+            }
+            if stop(model, count) {
+                break;
+            }
+        }
+        if pending {
+            self.emit(model, count)?;
+        }
+        Ok(count)
+    }
+
+    /// Fits the point, after computing its density if anomalies are detected.
+    /// Returns the ball which received the point and the changes made to the model.
+    fn fit<Point>(
+        algo: &Algo<Point>,
+        model: &mut Model<Point>,
+        point: Point,
+        anomaly: Option<&mut Anomaly>,
+    ) -> Result<Fitted<Point>, Box<dyn Error>>
+    where
+        Point: PartialEq + 'static,
+    {
+        let anomaly = anomaly.map(|anomaly| {
+            let scored = model.iter_balls().any(|ball| ball.radius().is_finite());
+            (anomaly, model.density(&point), scored)
+        });
+        let fitted = algo.try_fit_with_stats(model, point)?;
+        if let Some((anomaly, density, scored)) = anomaly {
+            anomaly.record(density, scored);
+        }
+        Ok(fitted)
+    }
+
+    /// Checks if the stop flag is set.
+    fn is_stopped(&self) -> bool {
+        matches!(&self.stop_flag, Some(flag) if flag.load(Ordering::Relaxed))
+    }
+
+    /// Serializes the model in the output format and writes it to `Out` sink.
+    /// The model is wrapped in an envelope with the number of points fitted so far
+    /// and with the anomaly flag if required.
+    fn emit<Point>(&mut self, model: &mut Model<Point>, count: usize) -> Result<(), Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+    {
+        let output = Self::format(&mut self.format, model)?;
+        let frame = if self.envelope.is_some() || self.anomaly.is_some() {
+            let model = match output {
+                Output::Json(value) => value,
+                Output::Text(text) => Value::String(text),
+            };
+            let mut envelope = Map::new();
+            if let Some(seq) = self.envelope.as_mut() {
+                envelope.insert(String::from("seq"), json!(*seq));
+                envelope.insert(String::from("points"), json!(count));
+                *seq += 1;
+            }
+            if let Some(anomaly) = self.anomaly.as_mut() {
+                envelope.insert(String::from("anomaly"), json!(anomaly.flagged));
+                if let Some(density) = anomaly.density {
+                    envelope.insert(String::from("density"), json!(density));
+                }
+                anomaly.reset();
+            }
+            envelope.insert(String::from("model"), model);
+            Data::from_json(&Value::Object(envelope))?
+        } else {
+            match output {
+                Output::Json(value) => Data::from_json(&value)?,
+                Output::Text(text) => Data::from_text(text)?,
+            }
+        };
+        (self.write)(frame)
+    }
+
+    /// Serializes the model in the output format.
+    fn format<Point>(
+        format: &mut Format,
+        model: &mut Model<Point>,
+    ) -> Result<Output, Box<dyn Error>>
+    where
+        Point: PartialEq + Serialize + 'static,
+    {
+        let output = match format {
+            Format::Json => Output::Json(serde_json::to_value(serialize_model(model))?),
+            Format::JsonWithIds => Output::Json(serialize_model_with_ids(model)),
+            Format::JsonWithEdges => Output::Json(serialize_model_with_edges(model)),
+            Format::Csv(delimiter) => Output::Text(serialize_model_csv_with(model, *delimiter)),
+            Format::Deltas(deltas) => {
+                let changes = model.take_changes().unwrap_or_default();
+                let snapshot = deltas.sent == 0
+                    || (deltas.snapshot_every > 0 && deltas.sent % deltas.snapshot_every == 0);
+                deltas.sent += 1;
+                let message = if snapshot {
+                    serialize_snapshot(model)
+                } else {
+                    serialize_delta(model, &changes)
+                };
+                Output::Json(message)
+            }
+        };
+        Ok(output)
+    }
 }
 
-fn serialize_model<Point: PartialEq + Serialize + 'static>(
+/// Serializes the model balls as a vector of json objects.
+pub(crate) fn serialize_model<Point: PartialEq + Serialize + 'static>(
     model: &Model<Point>,
 ) -> Vec<Map<String, Value>> {
     let balls: Vec<_> = model
@@ -80,16 +1019,188 @@ fn serialize_model<Point: PartialEq + Serialize + 'static>(
     balls
 }
 
+/// Serializes the model balls with the edges of the neighborhood graph as
+/// `{"balls":[...],"edges":[[0,1],[1,2]]}`, where the balls are serialized like [serialize_model]
+/// and each edge links the indices of two neighbor balls in the `balls` array.
+/// An edge is written once, lower index first, even if both balls are neighbors of each other.
+/// ```
+/// use fluent_data::{model::Ball, space, streamer, Model};
+///
+/// let model = Model::load(
+///     space::euclid_dist,
+///     vec![Ball::new(vec![0.], 1., 1.), Ball::new(vec![5.], 1., 1.), Ball::new(vec![9.], 1., 1.)],
+/// );
+/// let json = streamer::serialize_model_with_edges(&model);
+/// assert_eq!(r#"[[0,1],[0,2],[1,2]]"#, json["edges"].to_string());
+/// ```
+pub fn serialize_model_with_edges<Point: PartialEq + Serialize + 'static>(
+    model: &Model<Point>,
+) -> Value {
+    let edges: Vec<[usize; 2]> = model.edges().into_iter().map(|(i, j)| [i, j]).collect();
+    json!({ "balls": serialize_model(model), "edges": edges })
+}
+
+/// Serializes the model as comma separated values.
+/// The first row is a header `center_0,center_1,...,radius,weight`,
+/// where the number of center columns is inferred from the first ball,
+/// then each ball is a row. An infinite radius is written `inf`.
+/// ```
+/// use fluent_data::{model::Ball, space, streamer, Model};
+///
+/// let model = Model::load(space::euclid_dist, vec![Ball::new(vec![1., 2.], 4., 1.5)]);
+/// let csv = streamer::serialize_model_csv(&model);
+/// assert_eq!("center_0,center_1,radius,weight\n1.0,2.0,2.0,1.5", csv);
+/// ```
+pub fn serialize_model_csv<Point: PartialEq + Serialize + 'static>(model: &Model<Point>) -> String {
+    serialize_model_csv_with(model, ',')
+}
+
+/// Serializes the model as csv like [serialize_model_csv] with the given delimiter.
+pub fn serialize_model_csv_with<Point: PartialEq + Serialize + 'static>(
+    model: &Model<Point>,
+    delimiter: char,
+) -> String {
+    let rows: Vec<Vec<String>> = model
+        .iter_balls()
+        .map(|data| {
+            let center = match json!(data.center()) {
+                Value::Array(coords) => coords.iter().map(|c| c.to_string()).collect(),
+                center => vec![center.to_string()],
+            };
+            let radius = if data.radius.is_infinite() {
+                String::from("inf")
+            } else {
+                json!(data.radius()).to_string()
+            };
+            let weight = json!(data.weight()).to_string();
+            center.into_iter().chain([radius, weight]).collect()
+        })
+        .collect();
+    let dim = rows.first().map_or(0, |row| row.len() - 2);
+    let header = (0..dim)
+        .map(|i| format!("center_{}", i))
+        .chain([String::from("radius"), String::from("weight")]);
+    let delimiter = delimiter.to_string();
+    let mut lines = vec![header.collect::<Vec<_>>().join(&delimiter)];
+    lines.extend(rows.into_iter().map(|row| row.join(&delimiter)));
+    lines.join("\n")
+}
+
+/// Deserializes balls from a csv model as produced by [serialize_model_csv_with],
+/// e.g. for loading a model saved as csv with [Model::load].
+pub fn deserialize_model_csv(
+    csv: &str,
+    delimiter: char,
+) -> Result<Vec<Ball<RealPoint>>, Box<dyn Error>> {
+    csv.lines()
+        .skip(1)
+        .map(|row| {
+            let mut values = row
+                .split(delimiter)
+                .map(|v| v.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() < 2 {
+                return Err(format!("invalid ball: {}", row).into());
+            }
+            let weight = values.pop().unwrap_or_default();
+            let radius = values.pop().unwrap_or_default();
+            Ok(Ball::new(values, radius * radius, weight))
+        })
+        .collect()
+}
+
+/// Serializes the model balls with their ids as a json array.
+fn serialize_model_with_ids<Point: PartialEq + Serialize + 'static>(model: &Model<Point>) -> Value {
+    let balls: Vec<_> = model
+        .iter_balls()
+        .map(|data| serialize_ball_with_id(data, None))
+        .collect();
+    json!(balls)
+}
+
+/// Serializes the model balls with their ids as a snapshot message for the delta mode.
+fn serialize_snapshot<Point: PartialEq + Serialize + 'static>(model: &Model<Point>) -> Value {
+    let balls: Vec<_> = model
+        .iter_balls()
+        .map(|data| serialize_ball_with_id(data, None))
+        .collect();
+    json!({ "op": "snapshot", "balls": balls })
+}
+
+/// Serializes the given changes of the model as a delta message.
+fn serialize_delta<Point: PartialEq + Serialize + 'static>(
+    model: &Model<Point>,
+    changes: &Changes,
+) -> Value {
+    let upserted = model
+        .iter_balls()
+        .filter(|data| changes.upserted.contains(&data.id))
+        .map(|data| serialize_ball_with_id(data, Some("upsert")));
+    let deleted = changes.deleted.iter().map(|id| {
+        let mut map = Map::new();
+        map.insert("id".into(), json!(id));
+        map.insert("op".into(), json!("delete"));
+        map
+    });
+    let balls: Vec<_> = upserted.chain(deleted).collect();
+    json!({ "op": "delta", "decay": changes.decay, "balls": balls })
+}
+
+/// Serializes a ball as a json object with its id and an optional delta operation.
+fn serialize_ball_with_id<Point: PartialEq + Serialize>(
+    data: impl Deref<Target = Ball<Point>>,
+    op: Option<&str>,
+) -> Map<String, Value> {
+    let id = data.id;
+    let mut map = serialize_ball(data);
+    map.insert("id".into(), json!(id));
+    if let Some(op) = op {
+        map.insert("op".into(), json!(op));
+    }
+    map
+}
+
+/// Serializes a ball as a json object.
+/// The radius is omitted while it is infinite, i.e. for the initial ball of a model that has seen a single point,
+/// since json has no representation of infinity.
 fn serialize_ball<Point: PartialEq + Serialize>(
     data: impl Deref<Target = Ball<Point>>,
 ) -> Map<String, Value> {
     let mut map = Map::new();
     map.insert("center".into(), json!(data.center()));
-    map.insert("radius".into(), json!(data.radius()));
+    if data.radius.is_finite() {
+        map.insert("radius".into(), json!(data.radius()));
+    }
     map.insert("weight".into(), json!(data.weight()));
     map
 }
 
+/// A ball as produced by [serialize_ball].
+#[derive(Deserialize)]
+struct SerializedBall<Point> {
+    center: Point,
+    #[serde(default)]
+    radius: Option<f64>,
+    weight: f64,
+}
+
+/// Deserializes balls from a json value as produced by [serialize_model].
+/// A missing radius stands for the infinite radius of the initial ball, as does a `null` radius
+/// which former versions wrote.
+pub(crate) fn deserialize_balls<Point: PartialEq + DeserializeOwned>(
+    value: Value,
+) -> Result<Vec<Ball<Point>>, Box<dyn Error>> {
+    let balls: Vec<SerializedBall<Point>> = serde_json::from_value(value)?;
+    let balls = balls
+        .into_iter()
+        .map(|b| {
+            let radius = b.radius.map_or(f64::INFINITY, |r| r * r);
+            Ball::new(b.center, radius, b.weight)
+        })
+        .collect();
+    Ok(balls)
+}
+
 /// Returns point iterator / model writer that use standard in out.
 pub fn stdio() -> (
     impl Iterator<Item = Result<String, Box<dyn Error>>>,
@@ -105,13 +1216,143 @@ pub fn stdio() -> (
     (points, write)
 }
 
+/// Returns a point iterator that reads the lines of the given file,
+/// or of the standard input if the path is `-`.
+/// Fails if the file cannot be opened.
+/// ```no_run
+/// use fluent_data::{streamer, Streamer};
+///
+/// let points = streamer::file_reader("points.json").unwrap();
+/// let write = streamer::file_writer("models.json").unwrap();
+/// let streamer = Streamer::new(points, write);
+/// ```
+pub fn file_reader<P: AsRef<Path>>(
+    path: P,
+) -> Result<impl Iterator<Item = Result<String, Box<dyn Error>>>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let input: Box<dyn BufRead + Send> = if path == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let file = File::open(path)
+            .map_err(|reason| format!("cannot open {}: {}", path.display(), reason))?;
+        Box::new(BufReader::new(file))
+    };
+    let points = input
+        .lines()
+        .map(|f| -> Result<String, Box<dyn Error>> { Ok(f?) });
+    Ok(points)
+}
+
+/// Returns a model writer that writes one model per line to the given file, which is created or truncated.
+/// Writes are buffered and flushed when the writer is dropped.
+/// Fails if the file cannot be created.
+pub fn file_writer<P: AsRef<Path>>(path: P) -> Result<Sink<'static>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let file = File::create(path)
+        .map_err(|reason| format!("cannot create {}: {}", path.display(), reason))?;
+    let mut output = BufWriter::new(file);
+    let write = move |model: String| {
+        writeln!(output, "{}", model)?;
+        Ok(())
+    };
+    Ok(Box::new(write))
+}
+
+/// Returns point iterator / model writer that use the standard input / output
+/// and write each ball of the model as a json line, see [ndjson_writer].
+pub fn ndjson_stdio() -> (
+    impl Iterator<Item = Result<String, Box<dyn Error>>>,
+    Sink<'static>,
+) {
+    let (points, _) = stdio();
+    (points, Box::new(ndjson_writer(io::stdout())))
+}
+
+/// Returns a model writer that writes each ball of a model as a json object on its own line,
+/// tagged with the sequence number of the model and the index of the ball,
+/// e.g. `{"ball":0,"center":[1.0,1.0],"seq":0,"weight":0.0}`.
+/// ```
+/// use fluent_data::streamer;
+///
+/// let mut output = vec![];
+/// let mut write = streamer::ndjson_writer(&mut output);
+/// write(r#"[{"center":[1.0],"weight":0.0}]"#.into()).unwrap();
+/// drop(write);
+/// assert_eq!(
+///     "{\"ball\":0,\"center\":[1.0],\"seq\":0,\"weight\":0.0}\n",
+///     String::from_utf8(output).unwrap()
+/// );
+/// ```
+pub fn ndjson_writer<W: Write>(mut output: W) -> impl FnMut(String) -> Result<(), Box<dyn Error>> {
+    let mut seq = 0;
+    move |model: String| {
+        let balls: Vec<Map<String, Value>> = serde_json::from_str(&model)?;
+        for (i, mut ball) in balls.into_iter().enumerate() {
+            ball.insert("seq".into(), json!(seq));
+            ball.insert("ball".into(), json!(i));
+            writeln!(output, "{}", Value::Object(ball))?;
+        }
+        output.flush()?;
+        seq += 1;
+        Ok(())
+    }
+}
+
+/// Returns point iterator / model writer that use the standard input / output with csv data.
+/// Each row of the standard input is a point: all columns are its coordinates.
+/// Blank lines and trailing delimiters are ignored, the delimiter can be set by [CsvReader::with_delimiter].
+/// Models are written as described by [csv_writer].
+/// ```
+/// use fluent_data::{streamer, Streamer};
+///
+/// let (points, write) = streamer::csv_stdio();
+/// let streamer = Streamer::new(points.with_delimiter(';'), write);
+/// ```
+pub fn csv_stdio() -> (CsvReader<StdinLock<'static>>, Sink<'static>) {
+    let points = CsvReader {
+        lines: io::stdin().lock().lines(),
+        columns: None,
+        delimiter: ',',
+        skip: false,
+    };
+    (points, Box::new(csv_writer(io::stdout())))
+}
+
+/// Returns a model writer that writes the json models of the [Streamer] as csv to the given output,
+/// in the format of [serialize_model_csv], so that they can be loaded by [deserialize_model_csv].
+/// Models are separated by a blank line.
+/// ```
+/// use fluent_data::streamer;
+///
+/// let mut output = vec![];
+/// let mut write = streamer::csv_writer(&mut output);
+/// write(r#"[{"center":[1.0,2.0],"weight":0.0}]"#.into()).unwrap();
+/// drop(write);
+/// assert_eq!(
+///     "center_0,center_1,radius,weight\n1.0,2.0,inf,0.0\n\n",
+///     String::from_utf8(output).unwrap()
+/// );
+/// ```
+pub fn csv_writer<W: Write>(mut output: W) -> impl FnMut(String) -> Result<(), Box<dyn Error>> {
+    move |model: String| {
+        let balls = deserialize_balls::<RealPoint>(serde_json::from_str(&model)?)?;
+        let mut model = Model::new(space::euclid_dist);
+        for ball in balls {
+            model.add_ball(ball, vec![]);
+        }
+        writeln!(output, "{}\n", serialize_model_csv(&model))?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
 /// Returns point iterator / model writer that use mpsc channels.
-pub fn channels(
-    point_receiver: Receiver<String>,
-    model_producer: Sender<String>,
+pub fn channels<Data: 'static>(
+    point_receiver: Receiver<Data>,
+    model_producer: Sender<Data>,
 ) -> (
-    impl Iterator<Item = Result<String, Box<dyn Error>>>,
-    impl FnMut(String) -> Result<(), Box<dyn Error>>,
+    impl Iterator<Item = Result<Data, Box<dyn Error>>>,
+    impl FnMut(Data) -> Result<(), Box<dyn Error>>,
 ) {
     let points = point_receiver.into_iter().map(|f| Ok(f));
     let write = move |model| {
@@ -121,21 +1362,190 @@ pub fn channels(
     (points, write)
 }
 
-#[cfg(test)]
-mod tests {
+/// Reads the points of the given iterator in a separate thread and ends as soon as the given flag is set,
+/// even while the iterator waits for the next point, e.g. from a websocket client or the standard input.
+/// The thread that reads the points is left waiting for the next point.
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicBool, Ordering},
+///     Arc,
+/// };
+///
+/// use fluent_data::streamer;
+///
+/// let stop = Arc::new(AtomicBool::new(false));
+/// let source = std::iter::repeat_with(|| Ok(String::from("[1.0]")));
+/// let mut points = streamer::interruptible(source, stop.clone());
+/// assert_eq!("[1.0]", points.next().unwrap().unwrap());
+/// stop.store(true, Ordering::Relaxed);
+/// assert!(points.next().is_none());
+/// ```
+pub fn interruptible<Data: Send + 'static>(
+    points: impl Iterator<Item = Result<Data, Box<dyn Error>>> + Send + 'static,
+    stop: Arc<AtomicBool>,
+) -> impl Iterator<Item = Result<Data, Box<dyn Error>>> {
+    let (point_producer, point_receiver) = mpsc::sync_channel(1);
+    thread::spawn(move || {
+        for point in points {
+            // the errors are not sendable, their messages are
+            if point_producer
+                .send(point.map_err(|err| err.to_string()))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    iter::from_fn(move || loop {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        match point_receiver.recv_timeout(STOP_POLL) {
+            Ok(point) => return Some(point.map_err(Into::into)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    })
+}
 
-    use std::sync::mpsc;
+/// The interval at which an [interruptible] point iterator checks its stop flag.
+const STOP_POLL: Duration = Duration::from_millis(100);
 
-    use crate::{space, streamer::*};
+/// Reads csv rows and produces json points that the [Streamer] reads by default.
+/// Built by the [csv_reader] function.
+pub struct CsvReader<R: BufRead> {
+    lines: Lines<R>,
+    columns: Option<Range<usize>>,
+    delimiter: char,
+    skip: bool,
+}
 
-    #[test]
-    fn test_serialize_ball() {
-        let obj = serialize_ball(&Ball::new(vec![3., 5.1], 4.7, 0.999));
+/// Returns a point iterator that reads csv rows from the given reader
+/// and selects the given range of columns as the point coordinates.
+/// Rows are comma separated unless another delimiter is set by [CsvReader::with_delimiter].
+/// A cell that is not a number produces an error item.
+/// ```
+/// use std::io::Cursor;
+///
+/// use fluent_data::streamer;
+///
+/// let data = Cursor::new("id;x;y\n1;2.5;3\n");
+/// let mut points = streamer::csv_reader(data, 1..3)
+///     .with_delimiter(';')
+///     .with_header();
+/// assert_eq!("[2.5,3.0]", points.next().unwrap().unwrap());
+/// ```
+pub fn csv_reader<R: BufRead>(reader: R, columns: Range<usize>) -> CsvReader<R> {
+    CsvReader {
+        lines: reader.lines(),
+        columns: Some(columns),
+        delimiter: ',',
+        skip: false,
+    }
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// Skips the first row which is a header.
+    pub fn with_header(mut self) -> Self {
+        self.skip = true;
+        self
+    }
+
+    /// Sets the character which separates the cells of a row, e.g. `';'`.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Parses the selected cells of a row to a json point, or `None` if the row is blank.
+    fn parse(&self, line: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match parse_csv_row(line, self.delimiter, self.columns.as_ref())? {
+            Some(point) => Ok(Some(serde_json::to_string(&point)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<String, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if self.skip {
+                self.skip = false;
+                continue;
+            }
+            if let Some(point) = self.parse(&line).transpose() {
+                return Some(point);
+            }
+        }
+    }
+}
+
+/// A boxed model writer.
+pub type Sink<'a> = Box<dyn FnMut(String) -> Result<(), Box<dyn Error>> + 'a>;
+
+/// Returns a model writer that forwards models to all the given writers.
+/// Stops at the first writer that fails and returns its error.
+/// ```
+/// use fluent_data::streamer::{self, Sink};
+///
+/// let (_points, stdout) = streamer::stdio();
+/// let mut count = 0;
+/// let counter = |_model| {
+///     count += 1;
+///     Ok(())
+/// };
+/// let sinks: Vec<Sink> = vec![Box::new(stdout), Box::new(counter)];
+/// let write = streamer::fanout(sinks);
+/// ```
+pub fn fanout<'a>(
+    mut sinks: Vec<Sink<'a>>,
+) -> impl FnMut(String) -> Result<(), Box<dyn Error>> + 'a {
+    move |model: String| {
+        for sink in sinks.iter_mut() {
+            sink(model.clone())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::mpsc};
+
+    use approx_eq::assert_approx_eq;
+
+    use crate::{space, streamer::*};
+
+    #[test]
+    fn test_serialize_ball() {
+        let obj = serialize_ball(&Ball::new(vec![3., 5.1], 4.7, 0.999));
         let json = serde_json::to_string(&obj).unwrap();
         assert_eq!(
             r#"{"center":[3.0,5.1],"radius":2.16794833886788,"weight":0.999}"#,
             json
         );
+        let obj = serialize_ball(&Ball::new(vec![3., 5.1], f64::INFINITY, 0.));
+        let json = serde_json::to_string(&obj).unwrap();
+        assert_eq!(r#"{"center":[3.0,5.1],"weight":0.0}"#, json);
+    }
+
+    #[test]
+    fn test_serialize_single_point_model() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        algo.fit(&mut model, vec![1., 1.]);
+        let json = serde_json::to_string(&serialize_model(&model)).unwrap();
+        assert_eq!(r#"[{"center":[1.0,1.0],"weight":0.0}]"#, json);
+        let json = serialize_snapshot(&model).to_string();
+        assert!(!json.contains("null"));
+        let balls: Vec<Ball<Vec<f64>>> = crate::model::parse_snapshot(&json).unwrap();
+        assert!(balls[0].radius().is_infinite());
     }
 
     #[test]
@@ -151,6 +1561,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deserialize_model() {
+        let json = r#"[{"center":[3.0,5.1],"radius":2.0,"weight":0.999},{"center":[1.2,6.0],"weight":0.0}]"#;
+        let balls: Vec<Ball<Vec<f64>>> = crate::model::parse_snapshot(json).unwrap();
+        assert_eq!(Ball::new(vec![3., 5.1], 4., 0.999), balls[0]);
+        assert_eq!(Ball::new(vec![1.2, 6.], f64::INFINITY, 0.), balls[1]);
+        let legacy = r#"[{"center":[1.2,6.0],"radius":null,"weight":0.0}]"#;
+        let balls: Vec<Ball<Vec<f64>>> = crate::model::parse_snapshot(legacy).unwrap();
+        assert_eq!(Ball::new(vec![1.2, 6.], f64::INFINITY, 0.), balls[0]);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_codec() {
+        let input = rmp_serde::to_vec(&vec![1.5, -2.]).unwrap();
+        let point: Option<Vec<f64>> = MsgPackCodec.decode(&input[..]).unwrap();
+        assert_eq!(Some(vec![1.5, -2.]), point);
+        let model = Model::load(
+            space::euclid_dist,
+            vec![Ball::new(vec![3., 5.1], 4., 0.999)],
+        );
+        let frame = Vec::<u8>::from_json(&json!(serialize_model(&model))).unwrap();
+        let value: Value = rmp_serde::from_slice(&frame).unwrap();
+        let balls: Vec<Ball<Vec<f64>>> = deserialize_balls(value).unwrap();
+        assert_eq!(vec![Ball::new(vec![3., 5.1], 4., 0.999)], balls);
+    }
+
     #[test]
     fn test_streamer() {
         let algo = Algo::new(space::euclid_dist, space::real_combine);
@@ -163,14 +1600,459 @@ mod tests {
         };
         let streamer = Streamer::new(points, write);
         match Streamer::run(streamer, algo, &mut model) {
-            Ok(()) => assert_eq!(
-                r#"[{"center":[1.0,1.0],"radius":null,"weight":0.0}]"#,
-                result
-            ),
+            Ok(()) => assert_eq!(r#"[{"center":[1.0,1.0],"weight":0.0}]"#, result),
             Err(_) => panic!(),
         };
     }
 
+    #[test]
+    fn test_run_n() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut points = (0..10).map(|i| Ok(format!("[{}.0]", i)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points.by_ref(), write);
+        let count = Streamer::run_n(streamer, algo, &mut model, 4).unwrap();
+        assert_eq!(4, count);
+        assert_eq!(4, result.len());
+        assert_eq!("[4.0]", points.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_run_n_exhausted() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..3).map(|i| Ok(format!("[{}.0]", i)));
+        let streamer = Streamer::new(points, |_| Ok(()));
+        let count = Streamer::run_n(streamer, algo, &mut model, 4).unwrap();
+        assert_eq!(3, count);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..10).map(|i| Ok(format!("[{}.0]", i * i)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write).emit_every(3).with_envelope();
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(4, result.len());
+        for (i, output) in result.iter().enumerate() {
+            let envelope: Value = serde_json::from_str(output).unwrap();
+            assert_eq!(json!(i), envelope["seq"]);
+            assert_eq!(json!(usize::min(3 * (i + 1), 10)), envelope["points"]);
+            assert!(envelope["model"].is_array());
+        }
+        let last: Value = serde_json::from_str(&result[3]).unwrap();
+        assert_eq!(json!(serialize_model(&model)), last["model"]);
+    }
+
+    #[test]
+    fn test_anomaly_reset() {
+        let mut anomaly = Anomaly {
+            threshold: 0.01,
+            flagged: false,
+            density: None,
+        };
+        anomaly.record(0.5, true);
+        anomaly.record(0.001, true);
+        assert!(anomaly.flagged);
+        assert_eq!(Some(0.001), anomaly.density);
+        anomaly.reset();
+        assert!(!anomaly.flagged);
+        assert_eq!(None, anomaly.density);
+    }
+
+    #[test]
+    fn test_anomaly() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut points: Vec<String> = (0..100).map(|i| format!("[{}.0]", i % 5)).collect();
+        points.push(String::from("[1000.0]"));
+        points.push(String::from("[2.0]"));
+        let points = points.into_iter().map(Ok);
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write).with_envelope();
+        Streamer::run_with_anomaly(streamer, algo, &mut model, 0.01).unwrap();
+        assert_eq!(102, result.len());
+        let outputs: Vec<Value> = result
+            .iter()
+            .map(|output| serde_json::from_str(output).unwrap())
+            .collect();
+        let flagged: Vec<usize> = outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output["anomaly"] == json!(true))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(vec![100], flagged);
+        assert!(outputs[100]["density"].as_f64().unwrap() < 0.01);
+        assert!(outputs[101]["density"].as_f64().unwrap() > 0.01);
+        assert_eq!(json!(100), outputs[100]["seq"]);
+        assert!(outputs[100]["model"].is_array());
+        // the anomalous point is fitted anyway
+        let balls = outputs[100]["model"].as_array().unwrap();
+        assert!(balls
+            .iter()
+            .any(|ball| ball["center"][0].as_f64().unwrap() > 100.));
+    }
+
+    #[test]
+    fn test_observer() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use crate::algorithm::FitResult;
+
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..100).map(|i| Ok(format!("[{}.0]", (i * 37) % 50)));
+        let observed = Rc::new(RefCell::new(vec![]));
+        let observed_clone = observed.clone();
+        let mut written = vec![];
+        let write = |s: String| {
+            let model: Vec<Value> = serde_json::from_str(&s)?;
+            written.push(model.len());
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write).with_observer(
+            move |model: &Model<Vec<f64>>, result: &FitResult<Vec<f64>>| {
+                let received = result.ball.as_ref().unwrap();
+                assert!(model.iter_balls().any(|ball| *ball == *received));
+                assert_eq!(1, result.stats.points);
+                observed_clone.borrow_mut().push(model.iter_balls().count());
+            },
+        );
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(100, written.len());
+        assert_eq!(*observed.borrow(), written);
+    }
+
+    #[test]
+    fn test_assignment_writer() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..200).map(|i| Ok(format!("[{}.0]", (i / 50) * 1000 + (i * 7) % 5)));
+        let lines = Rc::new(RefCell::new(vec![]));
+        let models = lines.clone();
+        let write = move |s: String| {
+            models.borrow_mut().push(s);
+            Ok(())
+        };
+        let assignments = lines.clone();
+        let streamer = Streamer::new(points, write)
+            .with_ball_ids()
+            .with_assignment_writer::<Vec<f64>, _>(move |s| {
+                assignments.borrow_mut().push(s);
+                Ok(())
+            });
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        let lines: Vec<Value> = lines
+            .borrow()
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect();
+        assert_eq!(400, lines.len());
+        let mut created = 0;
+        for pair in lines.chunks(2) {
+            let (assignment, model) = (&pair[0], &pair[1]);
+            assert!(assignment["point"].is_array());
+            if assignment["created"].as_bool().unwrap() {
+                created += 1;
+            }
+            let id = match assignment["ball_id"].as_u64() {
+                Some(id) => id,
+                None => continue,
+            };
+            assert!(assignment["dist"].is_f64());
+            let balls = model.as_array().unwrap();
+            assert!(balls.iter().any(|ball| ball["id"] == json!(id)));
+        }
+        assert!(created > 1);
+    }
+
+    #[test]
+    fn test_progress() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..100).map(|i| Ok(format!("[{}.0]", i % 7)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let counts = Rc::new(RefCell::new(vec![]));
+        let progress = counts.clone();
+        let streamer = Streamer::new(points, write)
+            .with_progress(move |count| progress.borrow_mut().push(count))
+            .progress_every(30);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(vec![30, 60, 90], *counts.borrow());
+        assert_eq!(100, result.len());
+    }
+
+    #[test]
+    fn test_run_until() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let mut points = (0..100).map(|i| Ok(format!("[{}.0]", i % 3)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points.by_ref(), write).emit_every(4);
+        let count =
+            Streamer::run_until(streamer, algo, &mut model, |m| m.total_weight() > 8.).unwrap();
+        assert_eq!(10, count);
+        assert_eq!(3, result.len());
+        assert!(model.total_weight() > 8.);
+        assert_eq!("[1.0]", points.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_run_with_checkpoint() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..10).map(|i| Ok(format!("[{}.0]", i * i)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let mut checkpoints = vec![];
+        let checkpoint = |model: &Model<Vec<f64>>| {
+            let balls = serialize_model(model);
+            checkpoints.push(serde_json::to_string(&balls).unwrap());
+        };
+        let streamer = Streamer::new(points, write);
+        Streamer::run_with_checkpoint(streamer, algo, &mut model, 3, checkpoint).unwrap();
+        assert_eq!(10, result.len());
+        assert_eq!(
+            vec![&result[2], &result[5], &result[8]],
+            checkpoints.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_emit_every() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..999).map(|i| Ok(format!("[{}.0]", i % 7)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write).emit_every(100);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(10, result.len());
+        let last = serde_json::to_string(&serialize_model(&model)).unwrap();
+        assert_eq!(&last, result.last().unwrap());
+    }
+
+    #[test]
+    fn test_emit_every_no_final_duplicate() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..200).map(|i| Ok(format!("[{}.0]", i % 7)));
+        let mut count = 0;
+        let write = |_| {
+            count += 1;
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write).emit_every(100);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_run_throttled() {
+        // (points, emit_every, ceil(points / emit_every))
+        for (total, emit_every, expected) in [(25, 10, 3), (30, 10, 3), (3, 1, 3), (1, 5, 1)] {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let mut model = Model::new(space::euclid_dist);
+            let points = (0..total).map(|i| Ok(format!("[{}.0]", i % 7)));
+            let mut count = 0;
+            let write = |_| {
+                count += 1;
+                Ok(())
+            };
+            let streamer = Streamer::new(points, write);
+            Streamer::run_throttled(streamer, algo, &mut model, emit_every).unwrap();
+            assert_eq!(expected, count);
+        }
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut read = 0;
+        let points = (0..).map(|i| {
+            read += 1;
+            if read == 50 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            Ok(format!("[{}.0]", i % 7))
+        });
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write)
+            .emit_every(20)
+            .with_stop_flag(stop.clone());
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(50, read);
+        assert_eq!(3, result.len());
+        let last = serde_json::to_string(&serialize_model(&model)).unwrap();
+        assert_eq!(&last, result.last().unwrap());
+    }
+
+    #[test]
+    fn test_run_rate_limited() {
+        // (min_interval, expected writes for 100 points read at once)
+        for (min_interval, expected) in [(Duration::from_secs(3600), 2), (Duration::ZERO, 100)] {
+            let algo = Algo::new(space::euclid_dist, space::real_combine);
+            let mut model = Model::new(space::euclid_dist);
+            let points = (0..100).map(|i| Ok(format!("[{}.0]", i % 7)));
+            let mut result = vec![];
+            let write = |s| {
+                result.push(s);
+                Ok(())
+            };
+            let streamer = Streamer::new(points, write);
+            Streamer::run_rate_limited(streamer, algo, &mut model, min_interval).unwrap();
+            assert_eq!(expected, result.len());
+            let last = serde_json::to_string(&serialize_model(&model)).unwrap();
+            assert_eq!(&last, result.last().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_run_rate_limited_slow_input() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..3).map(|i| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(format!("[{}.0]", i))
+        });
+        let mut count = 0;
+        let write = |_| {
+            count += 1;
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write);
+        Streamer::run_rate_limited(streamer, algo, &mut model, Duration::from_millis(10)).unwrap();
+        assert_eq!(3, count);
+    }
+
+    #[test]
+    fn test_streamer_dimension_mismatch() {
+        let algo =
+            Algo::new(space::euclid_dist, space::real_combine).with_validation(space::check_dim);
+        let mut model = Model::new(space::euclid_dist);
+        let points = vec![
+            Ok(String::from("[1.0,1.0]")),
+            Ok(String::from("[1.0,1.0,1.0]")),
+        ]
+        .into_iter();
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write);
+        let err = Streamer::run(streamer, algo, &mut model).unwrap_err();
+        assert_eq!("dimension mismatch: 3 != 2", err.to_string());
+        assert_eq!(1, result.len());
+        assert_eq!(&vec![1., 1.], model.iter_balls().next().unwrap().center());
+    }
+
+    #[test]
+    fn test_csv_codec() {
+        let mut codec = CsvCodec::with_header();
+        assert_eq!(None, codec.decode("x,y").unwrap());
+        assert_eq!(Some(vec![1., -2.5]), codec.decode("1, -2.5").unwrap());
+        assert!(codec.decode("1,a").is_err());
+        assert_eq!(None, codec.decode(" ").unwrap());
+        assert_eq!(Some(vec![1., 2.]), codec.decode("1,2,").unwrap());
+        let mut codec = CsvCodec::new();
+        assert_eq!(Some(vec![3.]), codec.decode("3").unwrap());
+    }
+
+    #[test]
+    fn test_csv_streamer() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = vec![Ok(String::from("x,y")), Ok(String::from("1.0,1.0"))].into_iter();
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new_with_codec(points, write, CsvCodec::with_header());
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(vec![r#"[{"center":[1.0,1.0],"weight":0.0}]"#], result);
+    }
+
+    #[test]
+    fn test_error_handler() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = vec!["[1.0,1.0]", "[2.0,", "[3.0,1.0]"]
+            .into_iter()
+            .map(|p| Ok(String::from(p)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let errors = Rc::new(RefCell::new(vec![]));
+        let handler_errors = errors.clone();
+        let streamer = Streamer::new(points, write)
+            .with_error_handler(move |err| handler_errors.borrow_mut().push(err.to_string()));
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(1, errors.borrow().len());
+        assert_eq!(
+            vec![
+                r#"[{"center":[1.0,1.0],"weight":0.0}]"#,
+                r#"[{"center":[3.0,1.0],"radius":2.0,"weight":1.0}]"#
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn test_error_handler_input_error() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = vec![Ok(String::from("[1.0]")), Err("io error".into())].into_iter();
+        let streamer = Streamer::new(points, |_| Ok(())).with_error_handler(|_| panic!());
+        let err = Streamer::run(streamer, algo, &mut model).unwrap_err();
+        assert_eq!("io error", err.to_string());
+    }
+
     #[test]
     fn test_channels() {
         let (point_producer, point_receiver) = mpsc::channel();
@@ -183,4 +2065,270 @@ mod tests {
         let m = model_receiver.recv().unwrap();
         assert_eq!("model", m);
     }
+
+    #[test]
+    fn test_csv_reader() {
+        let data = Cursor::new("a,b,c\n1,2,3\n\n4, 5.5,6\n");
+        let points: Vec<_> = csv_reader(data, 1..3)
+            .with_header()
+            .map(|p| p.unwrap())
+            .collect();
+        assert_eq!(vec!["[2.0,3.0]", "[5.5,6.0]"], points);
+
+        let data = Cursor::new("1;2\n3;x\n4\n");
+        let mut points = csv_reader(data, 0..2).with_delimiter(';');
+        assert_eq!("[1.0,2.0]", points.next().unwrap().unwrap());
+        let err = points.next().unwrap().unwrap_err();
+        assert_eq!(r#"invalid number: "x""#, err.to_string());
+        assert!(points.next().unwrap().is_err());
+        assert!(points.next().is_none());
+    }
+
+    #[test]
+    fn test_csv_reader_all_columns() {
+        let lines = Cursor::new("1,2,\n\n3,4\n").lines();
+        let points: Vec<_> = CsvReader {
+            lines,
+            columns: None,
+            delimiter: ',',
+            skip: false,
+        }
+        .map(|p| p.unwrap())
+        .collect();
+        assert_eq!(vec!["[1.0,2.0]", "[3.0,4.0]"], points);
+    }
+
+    #[test]
+    fn test_serialize_model_csv() {
+        let data = vec![
+            Ball::new(vec![0.5, -1.], f64::INFINITY, 0.),
+            Ball::new(vec![1. / 3., 2.], 0.7, 1.9),
+            Ball::new(vec![1E10, -3E-5], 1E-8, 0.25),
+        ];
+        let model = Model::load(space::euclid_dist, data.clone());
+        let csv = serialize_model_csv(&model);
+        let mut lines = csv.lines();
+        assert_eq!(Some("center_0,center_1,radius,weight"), lines.next());
+        assert_eq!(Some("0.5,-1.0,inf,0.0"), lines.next());
+        for delimiter in [',', ';'] {
+            let csv = serialize_model_csv_with(&model, delimiter);
+            let balls = deserialize_model_csv(&csv, delimiter).unwrap();
+            assert_eq!(data.len(), balls.len());
+            for (expected, actual) in data.iter().zip(balls.iter()) {
+                assert_eq!(expected.center, actual.center);
+                assert_eq!(expected.weight, actual.weight);
+                assert_approx_eq!(expected.radius, actual.radius, 1E-12);
+            }
+        }
+        let empty = Model::<Vec<f64>>::new(space::euclid_dist);
+        assert_eq!("radius,weight", serialize_model_csv(&empty));
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = vec![Ok(String::from("[1.0,1.0]")), Ok(String::from("[3.0,1.0]"))];
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points.into_iter(), write).with_csv_delimiter(';');
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(
+            vec![
+                "center_0;center_1;radius;weight\n1.0;1.0;inf;0.0",
+                "center_0;center_1;radius;weight\n3.0;1.0;2.0;1.0"
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn test_ball_ids() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = ["[1.0]", "[2.0]", "[1000.0]", "[1.1]"].map(|p| Ok(String::from(p)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points.into_iter(), write).with_ball_ids();
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        let ids: Vec<Vec<u64>> = result
+            .iter()
+            .map(|m| {
+                let balls: Vec<Value> = serde_json::from_str(m).unwrap();
+                balls.iter().map(|b| b["id"].as_u64().unwrap()).collect()
+            })
+            .collect();
+        assert_eq!(vec![vec![0], vec![0], vec![0, 1], vec![0, 1]], ids);
+        let ids: Vec<u64> = model.iter_balls().map(|b| b.id()).collect();
+        assert_eq!(vec![0, 1], ids);
+    }
+
+    #[test]
+    fn test_edges() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = ["[1.0]", "[2.0]", "[1000.0]", "[-1000.0]"].map(|p| Ok(String::from(p)));
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points.into_iter(), write).with_edges();
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        let last: Value = serde_json::from_str(result.last().unwrap()).unwrap();
+        assert_eq!(3, last["balls"].as_array().unwrap().len());
+        assert_eq!(json!([[0, 1], [0, 2], [1, 2]]), last["edges"]);
+    }
+
+    #[test]
+    fn test_ndjson_writer() {
+        let mut output = vec![];
+        let mut write = ndjson_writer(&mut output);
+        write(r#"[{"center":[1.0,1.0],"weight":0.0}]"#.into()).unwrap();
+        write(
+            r#"[{"center":[1.0,1.5],"radius":2.0,"weight":0.95},{"center":[3.0,-1.0],"radius":0.5,"weight":1.0}]"#
+                .into(),
+        )
+        .unwrap();
+        assert!(write("{}".into()).is_err());
+        drop(write);
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            json!({"seq":0,"ball":0,"center":[1.0,1.0],"weight":0.0}),
+            lines[0]
+        );
+        assert_eq!(
+            json!({"seq":1,"ball":1,"center":[3.0,-1.0],"radius":0.5,"weight":1.0}),
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let mut output = vec![];
+        let mut write = csv_writer(&mut output);
+        write(r#"[{"center":[1.0,1.0],"weight":0.0}]"#.into()).unwrap();
+        write(
+            r#"[{"center":[1.0,1.5],"radius":2.0,"weight":0.95},{"center":[3.0,-1.0],"radius":0.5,"weight":1.0}]"#
+                .into(),
+        )
+        .unwrap();
+        assert!(write("{}".into()).is_err());
+        drop(write);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            "center_0,center_1,radius,weight\n1.0,1.0,inf,0.0\n\n\
+            center_0,center_1,radius,weight\n1.0,1.5,2.0,0.95\n3.0,-1.0,0.5,1.0\n\n",
+            output
+        );
+        let last = output.trim_end().split("\n\n").last().unwrap();
+        let balls = deserialize_model_csv(last, ',').unwrap();
+        assert_eq!(
+            vec![
+                Ball::new(vec![1., 1.5], 4., 0.95),
+                Ball::new(vec![3., -1.], 0.25, 1.)
+            ],
+            balls
+        );
+    }
+
+    #[test]
+    fn test_csv_reader_streamer() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = csv_reader(Cursor::new("x,y\n1,1\n3,1\n"), 0..2).with_header();
+        let mut result = vec![];
+        let write = |s| {
+            result.push(s);
+            Ok(())
+        };
+        let streamer = Streamer::new(points, write);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(
+            r#"[{"center":[3.0,1.0],"radius":2.0,"weight":1.0}]"#,
+            result[1]
+        );
+    }
+
+    #[test]
+    fn test_file_reader_writer() {
+        let dir = std::env::temp_dir();
+        let input = dir.join("fluent_data_test_file_reader.json");
+        let output = dir.join("fluent_data_test_file_writer.json");
+        std::fs::write(&input, "[1.0,1.0]\n[3.0,1.0]\n").unwrap();
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = file_reader(&input).unwrap();
+        let write = file_writer(&output).unwrap();
+        let streamer = Streamer::new(points, write);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        let models = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(
+            concat!(
+                r#"[{"center":[1.0,1.0],"weight":0.0}]"#,
+                "\n",
+                r#"[{"center":[3.0,1.0],"radius":2.0,"weight":1.0}]"#,
+                "\n"
+            ),
+            models
+        );
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_file_reader_missing() {
+        let path = std::env::temp_dir().join("fluent_data_test_missing.json");
+        let err = file_reader(&path).err().unwrap();
+        assert!(err.to_string().starts_with("cannot open"));
+    }
+
+    #[test]
+    fn test_fanout() {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let points = (0..5).map(|i| Ok(format!("[{}.0]", i)));
+        let mut result1 = vec![];
+        let mut result2 = vec![];
+        let write = fanout(vec![
+            Box::new(|s| {
+                result1.push(s);
+                Ok(())
+            }),
+            Box::new(|s| {
+                result2.push(s);
+                Ok(())
+            }),
+        ]);
+        let streamer = Streamer::new(points, write);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+        assert_eq!(5, result1.len());
+        assert_eq!(result1, result2);
+    }
+
+    #[test]
+    fn test_fanout_error() {
+        let mut result = vec![];
+        let mut write = fanout(vec![
+            Box::new(|_| Err("failed".into())),
+            Box::new(|s| {
+                result.push(s);
+                Ok(())
+            }),
+        ]);
+        assert!(write(String::from("model")).is_err());
+        drop(write);
+        assert!(result.is_empty());
+    }
 }
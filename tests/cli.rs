@@ -0,0 +1,229 @@
+use fluent_data::{space, Model};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+#[path = "./utilities.rs"]
+mod utilities;
+use utilities::{assert_final_result, assert_results, get_point_iter};
+
+#[test]
+fn test_save() {
+    let path = env::temp_dir().join("fluent_data_test_cli_save.json");
+    let _ = fs::remove_file(&path);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .arg("--save")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    thread::spawn(move || {
+        for p in get_point_iter(10000) {
+            writeln!(stdin, "{}", p.unwrap()).unwrap();
+        }
+        // closing the input ends the stream
+    });
+    let models = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(10000, models.len());
+    let last: Vec<serde_json::Value> = serde_json::from_str(models.last().unwrap()).unwrap();
+    assert_results(models);
+    let saved = fs::read_to_string(&path).unwrap();
+    let model = Model::load_from_file(space::euclid_dist, &path).unwrap();
+    assert!(!last.is_empty());
+    assert_eq!(last.len(), model.iter_balls().count());
+    for (ball, expected) in model.iter_balls().zip(last.iter()) {
+        // the initial ball, which radius is infinite, has no radius
+        let radius = expected["radius"].as_f64().unwrap_or(f64::INFINITY);
+        assert!(ball.radius() == radius || (ball.radius() - radius).abs() < 1E-9);
+        assert_eq!(expected["weight"].as_f64().unwrap(), ball.weight());
+    }
+    assert_final_result(&saved);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_max_balls() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(["--max-balls", "3"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    thread::spawn(move || {
+        for i in 0..2000 {
+            let x = (i % 7) as f64 * 100. + (i % 3) as f64;
+            writeln!(stdin, "[{}]", x).unwrap();
+        }
+    });
+    let models = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(2000, models.len());
+    for model in models {
+        let balls: Vec<serde_json::Value> = serde_json::from_str(&model).unwrap();
+        assert!(balls.len() <= 3);
+    }
+}
+
+#[test]
+fn test_distance() {
+    for distance in ["euclid", "cosine", "manhattan"] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+            .args(["--distance", distance])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        thread::spawn(move || {
+            for p in get_point_iter(500) {
+                writeln!(stdin, "{}", p.unwrap()).unwrap();
+            }
+        });
+        let models = BufReader::new(child.stdout.take().unwrap())
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(500, models.len());
+        let balls: Vec<serde_json::Value> = serde_json::from_str(models.last().unwrap()).unwrap();
+        assert!(!balls.is_empty());
+    }
+    let status = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(["--distance", "chebyshev"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn test_emit_every() {
+    let points: Vec<String> = get_point_iter(1050).map(|p| p.unwrap()).collect();
+    let output = run_with_output(&["--emit-every", "100"], points.clone());
+    assert_eq!(11, output.matches('\n').count());
+    let output = run_with_output(&[], points);
+    assert_eq!(1050, output.matches('\n').count());
+    let status = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(["--emit-every", "0"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn test_emit_assignments() {
+    let points: Vec<String> = get_point_iter(500).map(|p| p.unwrap()).collect();
+    let output = run_with_output(&["--emit", "assignments"], points.clone());
+    let lines: Vec<serde_json::Value> = output
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(500, lines.len());
+    assert!(lines.iter().all(|l| l["point"].is_array()));
+    let output = run_with_output(&["--emit", "both"], points);
+    let lines: Vec<serde_json::Value> = output
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(1000, lines.len());
+    for pair in lines.chunks(2) {
+        let (assignment, model) = (&pair[0], &pair[1]);
+        if let Some(id) = assignment["ball_id"].as_u64() {
+            let balls = model.as_array().unwrap();
+            assert!(balls.iter().any(|ball| ball["id"].as_u64() == Some(id)));
+        }
+    }
+}
+
+#[test]
+fn test_load_save_round_trip() {
+    let dir = env::temp_dir();
+    let saved = dir.join("fluent_data_test_cli_round_trip.json");
+    let resaved = dir.join("fluent_data_test_cli_round_trip_again.json");
+    let points: Vec<String> = get_point_iter(1000).map(|p| p.unwrap()).collect();
+    run(&["--save", saved.to_str().unwrap()], points);
+    let args = [
+        "--load",
+        saved.to_str().unwrap(),
+        "--save",
+        resaved.to_str().unwrap(),
+    ];
+    run(&args, vec![]);
+    assert_eq!(
+        fs::read_to_string(&saved).unwrap(),
+        fs::read_to_string(&resaved).unwrap()
+    );
+    fs::remove_file(&saved).unwrap();
+    fs::remove_file(&resaved).unwrap();
+}
+
+#[test]
+fn test_predict() {
+    let path = env::temp_dir().join("fluent_data_test_cli_predict.json");
+    let points: Vec<String> = get_point_iter(1000).map(|p| p.unwrap()).collect();
+    run(&["run", "--save", path.to_str().unwrap()], points);
+    let output = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(["predict", "--load", path.to_str().unwrap(), "[2.0]"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let prediction: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(prediction["index"].is_u64());
+    assert!(prediction["distance"].is_f64());
+    assert_eq!(1, prediction["center"].as_array().unwrap().len());
+    let output = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(["predict", "--load", path.to_str().unwrap(), "oops"])
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    fs::remove_file(&path).unwrap();
+}
+
+fn run(args: &[&str], points: Vec<String>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for p in points {
+        writeln!(stdin, "{}", p).unwrap();
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+fn run_with_output(args: &[&str], points: Vec<String>) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fluent_data"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    thread::spawn(move || {
+        for p in points {
+            writeln!(stdin, "{}", p).unwrap();
+        }
+    });
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
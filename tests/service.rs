@@ -1,6 +1,9 @@
-use fluent_data::{service, space, Algo, Model, Streamer};
-use std::thread;
-use tungstenite::{connect, Message};
+use fluent_data::{
+    service::{self, ServiceHandle},
+    space, Algo, Model, Streamer,
+};
+use std::{net::TcpStream, thread};
+use tungstenite::{connect, stream::MaybeTlsStream, Message, WebSocket};
 use url::Url;
 
 #[path = "./utilities.rs"]
@@ -9,17 +12,41 @@ use utilities::{assert_results, get_point_iter};
 
 #[test]
 fn test_streamer() {
-    thread::spawn(|| start());
+    let handle = start();
+    // subscribe before feeding, otherwise the first models may be missed
+    let models_socket = subscribe();
     thread::spawn(|| feed());
-    assert_results(collect());
+    assert_results(collect(models_socket));
+    handle.shutdown();
+    // the port is released, thus a fresh service can be started
+    let handle = start();
+    let mut models_socket = subscribe();
+    let (mut points_socket, _resp) =
+        connect(Url::parse("ws://localhost:9001/ws/points").unwrap()).expect("Can't connect");
+    points_socket
+        .write_message(Message::Text("[1.0,1.0]".into()))
+        .unwrap();
+    assert_eq!(
+        r#"[{"center":[1.0,1.0],"weight":0.0}]"#,
+        models_socket.read_message().unwrap().into_text().unwrap()
+    );
+    handle.shutdown();
+    assert!(matches!(
+        models_socket.read_message(),
+        Ok(Message::Close(_))
+    ));
+    assert!(TcpStream::connect("127.0.0.1:9001").is_err());
 }
 
-fn start() {
-    let algo = Algo::new(space::euclid_dist, space::real_combine);
-    let mut model = Model::new(space::euclid_dist);
-    let (points, write) = service::backend();
-    let streamer = Streamer::new(points, write);
-    Streamer::run(streamer, algo, &mut model).unwrap();
+fn start() -> ServiceHandle {
+    let (points, write, handle) = service::backend_with_handle().unwrap();
+    thread::spawn(move || {
+        let algo = Algo::new(space::euclid_dist, space::real_combine);
+        let mut model = Model::new(space::euclid_dist);
+        let streamer = Streamer::new(points, write);
+        Streamer::run(streamer, algo, &mut model).unwrap();
+    });
+    handle
 }
 
 fn feed() {
@@ -35,10 +62,13 @@ fn feed() {
     points_socket.close(None).unwrap();
 }
 
-fn collect() -> Vec<String> {
+fn subscribe() -> WebSocket<MaybeTlsStream<TcpStream>> {
     let models_url = "ws://localhost:9001/ws/models";
-    let (mut models_socket, _resp) =
-        connect(Url::parse(models_url).unwrap()).expect("Can't connect");
+    let (models_socket, _resp) = connect(Url::parse(models_url).unwrap()).expect("Can't connect");
+    models_socket
+}
+
+fn collect(mut models_socket: WebSocket<MaybeTlsStream<TcpStream>>) -> Vec<String> {
     let mut results: Vec<String> = vec![];
     for _i in 0..10000 {
         let m = models_socket.read_message().unwrap();
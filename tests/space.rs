@@ -0,0 +1,72 @@
+#![cfg(test)]
+use fluent_data::{space, Algo, Model};
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+
+#[test]
+fn test_manhattan_blobs() {
+    let algo = Algo::new(space::manhattan_dist, space::manhattan_combine);
+    let mut model = Model::new(space::manhattan_dist);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(9787043385113690);
+    let blobs = [[0., 0.], [50., 50.]];
+    for i in 0..2000 {
+        let [x, y] = blobs[i / 2 % 2];
+        let dx: f64 = rng.sample(StandardNormal);
+        let dy: f64 = rng.sample(StandardNormal);
+        algo.fit(&mut model, vec![x + dx, y + dy]);
+    }
+    let mut balls: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+    balls.sort_by(|b1, b2| b2.weight().total_cmp(&b1.weight()));
+    for [x, y] in blobs {
+        let found = balls[..2]
+            .iter()
+            .any(|b| space::manhattan_dist(b.center(), &vec![x, y]) < 1.);
+        assert!(found);
+    }
+}
+
+#[test]
+fn test_geo_cities() {
+    let algo = Algo::new(space::haversine_dist, space::geo_combine);
+    let mut model = Model::new(space::haversine_dist);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(5093817206334561);
+    // the second city lies on the antimeridian
+    let cities = [[48.8566, 2.3522], [-16.8, 180.]];
+    for i in 0..2000 {
+        let [lat, lon] = cities[i / 2 % 2];
+        let dlat: f64 = rng.sample(StandardNormal);
+        let dlon: f64 = rng.sample(StandardNormal);
+        let lon = lon + 0.1 * dlon;
+        let lon = if lon > 180. { lon - 360. } else { lon };
+        algo.fit(&mut model, vec![lat + 0.1 * dlat, lon]);
+    }
+    let mut balls: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+    balls.sort_by(|b1, b2| b2.weight().total_cmp(&b1.weight()));
+    for [lat, lon] in cities {
+        let found = balls[..2]
+            .iter()
+            .any(|b| space::haversine_dist(b.center(), &vec![lat, lon]) < 25.);
+        assert!(found);
+    }
+}
+
+#[test]
+fn test_array_blobs() {
+    let algo = Algo::new(space::array_euclid_dist, space::array_combine);
+    let mut model = Model::new(space::array_euclid_dist);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2740563149187723);
+    let blobs = [[0., 0., 0.], [30., 30., 30.]];
+    for i in 0..2000 {
+        let blob = blobs[i / 2 % 2];
+        let point = blob.map(|x| x + rng.sample::<f64, _>(StandardNormal));
+        algo.fit(&mut model, point);
+    }
+    let mut balls: Vec<_> = model.iter_balls().map(|b| *b).collect();
+    balls.sort_by(|b1, b2| b2.weight().total_cmp(&b1.weight()));
+    for blob in blobs {
+        let found = balls[..2]
+            .iter()
+            .any(|b| space::array_euclid_dist(b.center(), &blob) < 1.);
+        assert!(found);
+    }
+}
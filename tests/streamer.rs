@@ -1,8 +1,18 @@
 #![cfg(test)]
-use fluent_data::{algorithm::Algo, model::Model, space, streamer::*};
+use std::{collections::BTreeMap, error::Error};
+
+use approx_eq::assert_approx_eq;
+use fluent_data::{
+    algorithm::Algo,
+    model::{Ball, Model},
+    neighborhood::Neighborhood,
+    space,
+    streamer::*,
+};
 
 #[path = "./utilities.rs"]
 mod utilities;
+use serde_json::Value;
 use utilities::{assert_results, get_point_iter};
 
 #[test]
@@ -18,3 +28,155 @@ fn test_streamer() {
         Err(_) => panic!(),
     };
 }
+
+#[test]
+fn test_csv_streamer() {
+    let (json_model, json_results) = run(Streamer::new(get_point_iter(1000), |_| Ok(())));
+    let csv_points = get_point_iter(1000).map(|p| Ok(p?.replace(['[', ']'], "")));
+    let streamer = Streamer::new_with_codec(csv_points, |_| Ok(()), CsvCodec::new());
+    let (csv_model, csv_results) = run(streamer);
+    assert_eq!(1000, json_results);
+    assert_eq!(json_results, csv_results);
+    assert_eq!(json_model, csv_model);
+}
+
+#[test]
+fn test_delta_streamer() {
+    let mut full: Vec<String> = vec![];
+    let write = |model: String| {
+        full.push(model);
+        Ok(())
+    };
+    run(Streamer::new(get_point_iter(3000), write));
+    let mut deltas: Vec<String> = vec![];
+    let write = |model: String| {
+        deltas.push(model);
+        Ok(())
+    };
+    run(Streamer::new(get_point_iter(3000), write).with_deltas(500));
+    assert_eq!(full.len(), deltas.len());
+
+    let mut balls: BTreeMap<u64, Value> = BTreeMap::new();
+    for (i, (full, delta)) in full.iter().zip(deltas.iter()).enumerate() {
+        let delta: Value = serde_json::from_str(delta).unwrap();
+        let op = delta["op"].as_str().unwrap();
+        assert_eq!(i % 500 == 0, op == "snapshot");
+        if op == "snapshot" {
+            balls.clear();
+        } else {
+            let decay = delta["decay"].as_f64().unwrap();
+            for ball in balls.values_mut() {
+                ball["weight"] = (ball["weight"].as_f64().unwrap() * decay).into();
+            }
+        }
+        for ball in delta["balls"].as_array().unwrap() {
+            let mut ball = ball.clone();
+            let id = ball["id"].as_u64().unwrap();
+            match ball["op"].as_str() {
+                Some("delete") => {
+                    balls.remove(&id);
+                }
+                _ => {
+                    let ball = ball.as_object_mut().unwrap();
+                    ball.remove("id");
+                    ball.remove("op");
+                    balls.insert(id, Value::Object(ball.clone()));
+                }
+            }
+        }
+        let full: Vec<Value> = serde_json::from_str(full).unwrap();
+        assert_eq!(full.len(), balls.len());
+        for (expected, actual) in full.iter().zip(balls.values()) {
+            assert_eq!(expected["center"], actual["center"]);
+            assert_eq!(expected["radius"], actual["radius"]);
+            let (w1, w2) = (expected["weight"].as_f64(), actual["weight"].as_f64());
+            assert!((w1.unwrap() - w2.unwrap()).abs() < 1E-9);
+        }
+    }
+}
+
+#[test]
+fn test_fit_batch() {
+    let points: Vec<Vec<f64>> = get_point_iter(10000)
+        .map(|p| serde_json::from_str(&p.unwrap()).unwrap())
+        .collect();
+    let algo = Algo::new(space::euclid_dist, space::real_combine);
+    let mut model = Model::new(space::euclid_dist);
+    for point in points.iter() {
+        algo.fit(&mut model, point.clone());
+    }
+    let mut batch_model = Model::new(space::euclid_dist);
+    let mut stats = Default::default();
+    for batch in points.chunks(1000) {
+        stats = algo.fit_batch(&mut batch_model, batch.to_vec());
+        assert_eq!(1000, stats.points);
+    }
+    assert!(stats.created >= stats.removed);
+    let balls: Vec<_> = model.iter_balls().map(|b| b.clone()).collect();
+    let batch_balls: Vec<_> = batch_model.iter_balls().map(|b| b.clone()).collect();
+    assert_eq!(balls.len(), batch_balls.len());
+    for (ball, batch_ball) in balls.iter().zip(batch_balls.iter()) {
+        assert_approx_eq!(ball.center()[0], batch_ball.center()[0], 1E-6);
+        assert_approx_eq!(ball.radius(), batch_ball.radius(), 1E-6);
+        assert_approx_eq!(ball.weight(), batch_ball.weight(), 1E-6);
+    }
+    let predictions = batch_model.predict_batch(&points[..10]);
+    assert_eq!(10, predictions.len());
+    for (point, prediction) in points.iter().zip(predictions) {
+        match (model.predict(point), prediction) {
+            (Neighborhood::One(n), Neighborhood::One(m)) => {
+                assert_approx_eq!(n.dist(), m.dist(), 1E-6)
+            }
+            (Neighborhood::Two(n, _), Neighborhood::Two(m, _)) => {
+                assert_approx_eq!(n.dist(), m.dist(), 1E-6)
+            }
+            _ => panic!(),
+        }
+    }
+}
+
+#[test]
+fn test_merge_shards() {
+    let points: Vec<Vec<f64>> = get_point_iter(10000)
+        .map(|p| serde_json::from_str(&p.unwrap()).unwrap())
+        .collect();
+    let algo = Algo::new(space::euclid_dist, space::real_combine);
+    let fit = |points: &[Vec<f64>]| {
+        let mut model = Model::new(space::euclid_dist);
+        for point in points {
+            algo.fit(&mut model, point.clone());
+        }
+        model
+    };
+    let single = fit(&points);
+    let (first, second) = points.split_at(5000);
+    let mut merged = fit(first);
+    merged.merge_from(fit(second), &space::real_combine, 1.);
+    let heaviest = |model: &Model<Vec<f64>>| {
+        let ball = model
+            .iter_balls()
+            .max_by(|b1, b2| b1.weight().total_cmp(&b2.weight()))
+            .unwrap();
+        (ball.center()[0], ball.radius(), ball.weight())
+    };
+    let (center, radius, weight) = heaviest(&single);
+    let (merged_center, merged_radius, merged_weight) = heaviest(&merged);
+    assert_approx_eq!(center, merged_center, 5E-2);
+    assert_approx_eq!(radius, merged_radius, 5E-2);
+    assert_approx_eq!(weight, merged_weight, 5E-2);
+    assert_approx_eq!(single.total_weight(), merged.total_weight(), 5E-2);
+}
+
+fn run<C: Codec<Vec<f64>>>(
+    streamer: Streamer<
+        impl Iterator<Item = Result<String, Box<dyn Error>>>,
+        impl FnMut(String) -> Result<(), Box<dyn Error>>,
+        C,
+    >,
+) -> (Vec<Ball<Vec<f64>>>, usize) {
+    let algo = Algo::new(space::euclid_dist, space::real_combine);
+    let mut model = Model::new(space::euclid_dist);
+    let count = Streamer::run_n(streamer, algo, &mut model, usize::MAX).unwrap();
+    let balls = model.iter_balls().map(|b| b.clone()).collect();
+    (balls, count)
+}
@@ -7,7 +7,7 @@ use regex::Regex;
 use serde_json::{json, Value};
 
 const OUT_PATTERN: &str =
-    r#"^\[(\{"center":\[[-0-9.]*\],"radius":(null|[0-9.]*),"weight":[0-9.]*\},?)*\]$"#;
+    r#"^\[(\{"center":\[[-0-9.]*\],("radius":[0-9.]*,)?"weight":[0-9.]*\},?)*\]$"#;
 
 pub fn assert_results(result: Vec<String>) {
     let re = Regex::new(OUT_PATTERN).unwrap();